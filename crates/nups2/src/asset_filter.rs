@@ -0,0 +1,229 @@
+use crate::pack2::Asset;
+use crate::Nups2Error;
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// pack2 names are case-insensitive (they are hashed in uppercase)
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// Selects assets by name, size, compression, etc.
+///
+/// Each kind of condition only applies once something was added to it.
+/// Multiple globs/regexes/extensions are or-ed, the different kinds are and-ed.
+/// Unnamed assets never match name based conditions (globs, regexes, extensions).
+#[derive(Debug, Default)]
+pub struct AssetFilter {
+    globs: Vec<Pattern>,
+    regexes: Vec<Regex>,
+    /// lowercase, without leading `.`
+    extensions: Vec<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    min_compressed_size: Option<u64>,
+    max_compressed_size: Option<u64>,
+    compressed_only: bool,
+    name_hashes: Option<HashSet<u64>>,
+}
+
+impl AssetFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// true if no conditions were added (everything matches)
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+            && self.regexes.is_empty()
+            && self.extensions.is_empty()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.min_compressed_size.is_none()
+            && self.max_compressed_size.is_none()
+            && !self.compressed_only
+            && self.name_hashes.is_none()
+    }
+
+    /// for example `*_Lod0.dme` (case-insensitive)
+    pub fn add_glob(&mut self, glob: &str) -> Result<&mut Self, Nups2Error> {
+        self.globs
+            .push(Pattern::new(glob).map_err(|e| format!("Invalid glob {glob:?}: {e}"))?);
+        Ok(self)
+    }
+
+    /// the regex is searched for in the name (use `^...$` to match the whole name)
+    pub fn add_regex(&mut self, regex: &str) -> Result<&mut Self, Nups2Error> {
+        self.regexes
+            .push(Regex::new(regex).map_err(|e| format!("Invalid regex {regex:?}: {e}"))?);
+        Ok(self)
+    }
+
+    /// `dme`, `.dme` and `DME` are all the same
+    pub fn add_extension(&mut self, extension: &str) -> &mut Self {
+        self.extensions
+            .push(extension.trim_start_matches('.').to_lowercase());
+        self
+    }
+
+    /// limits for the uncompressed size
+    pub fn set_size_range(&mut self, min_size: Option<u64>, max_size: Option<u64>) -> &mut Self {
+        self.min_size = min_size;
+        self.max_size = max_size;
+        self
+    }
+
+    /// limits for the size within the pack2 file (same as the uncompressed size for uncompressed assets)
+    pub fn set_compressed_size_range(
+        &mut self,
+        min_compressed_size: Option<u64>,
+        max_compressed_size: Option<u64>,
+    ) -> &mut Self {
+        self.min_compressed_size = min_compressed_size;
+        self.max_compressed_size = max_compressed_size;
+        self
+    }
+
+    pub fn set_compressed_only(&mut self, compressed_only: bool) -> &mut Self {
+        self.compressed_only = compressed_only;
+        self
+    }
+
+    pub fn add_name_hashes(&mut self, name_hashes: impl IntoIterator<Item = u64>) -> &mut Self {
+        self.name_hashes
+            .get_or_insert_with(HashSet::new)
+            .extend(name_hashes);
+        self
+    }
+
+    /// newline-seperated list of name hashes (`0x` prefixed hex or decimal).
+    /// empty lines and lines starting with `#` are ignored.
+    pub fn add_name_hash_list_file(&mut self, path: &Path) -> Result<&mut Self, Nups2Error> {
+        let content: String = std::fs::read_to_string(path)?;
+        let mut name_hashes: Vec<u64> = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            name_hashes.push(
+                parse_name_hash(line)
+                    .ok_or_else(|| format!("Invalid name hash {line:?} in {path:?}"))?,
+            );
+        }
+        Ok(self.add_name_hashes(name_hashes))
+    }
+
+    pub fn matches(&self, asset: &Asset) -> bool {
+        if self.compressed_only && !asset.is_zipped {
+            return false;
+        }
+        if let Some(ref name_hashes) = self.name_hashes {
+            if !name_hashes.contains(&asset.name_hash) {
+                return false;
+            }
+        }
+
+        if !in_range(asset.uncompressed_length(), self.min_size, self.max_size)
+            || !in_range(
+                asset.data_length,
+                self.min_compressed_size,
+                self.max_compressed_size,
+            )
+        {
+            return false;
+        }
+
+        if self.globs.is_empty() && self.regexes.is_empty() && self.extensions.is_empty() {
+            return true;
+        }
+        let name: &str = match asset.name {
+            Some(ref name) => name,
+            None => return false,
+        };
+        if !self.extensions.is_empty() {
+            let extension: Option<String> = Path::new(name)
+                .extension()
+                .map(|i| i.to_string_lossy().to_lowercase());
+            if !extension.is_some_and(|e| self.extensions.contains(&e)) {
+                return false;
+            }
+        }
+        if self.globs.is_empty() && self.regexes.is_empty() {
+            return true;
+        }
+        self.globs
+            .iter()
+            .any(|glob| glob.matches_with(name, GLOB_OPTIONS))
+            || self.regexes.iter().any(|regex| regex.is_match(name))
+    }
+}
+
+/// `0x` prefixed hex or decimal
+pub fn parse_name_hash(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse::<u64>().ok(),
+    }
+}
+
+fn in_range(value: u64, min: Option<u64>, max: Option<u64>) -> bool {
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AssetFilter;
+    use crate::pack2::Pack2;
+    use crate::pack2_writer::Pack2Writer;
+    use std::io::Cursor;
+
+    #[test]
+    fn filter_assets() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("Tree_Lod0.dme", vec![0; 1000])
+            .add_bytes("Tree_Lod1.dme", vec![0; 10])
+            .add_bytes("Tree.dma", vec![0; 10])
+            .add_raw(1234, vec![0; 10]);
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+        pack2.apply_filename_list(&[
+            "Tree_Lod0.dme".into(),
+            "Tree_Lod1.dme".into(),
+            "Tree.dma".into(),
+        ]);
+        let matching = |filter: &AssetFilter| -> Vec<u64> {
+            pack2
+                .assets
+                .iter()
+                .filter(|asset| filter.matches(asset))
+                .map(|asset| asset.name_hash)
+                .collect()
+        };
+        let hash = |name: &str| -> u64 { crate::crc64::convert_filename(name) };
+
+        assert_eq!(matching(&AssetFilter::new()).len(), 4);
+        assert_eq!(
+            matching(AssetFilter::new().add_glob("*_lod0.DME").unwrap()),
+            vec![hash("Tree_Lod0.dme")]
+        );
+        let mut filter = AssetFilter::new();
+        filter.add_extension(".dme").set_size_range(None, Some(100));
+        assert_eq!(matching(&filter), vec![hash("Tree_Lod1.dme")]);
+        let mut filter = AssetFilter::new();
+        filter
+            .add_regex("^Tree\\.")
+            .unwrap()
+            .add_name_hashes([1234]);
+        assert_eq!(matching(&filter), Vec::<u64>::new());
+        assert_eq!(
+            matching(AssetFilter::new().add_name_hashes([1234])),
+            vec![1234]
+        );
+    }
+}
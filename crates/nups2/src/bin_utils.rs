@@ -1,7 +1,36 @@
 use std::io::Read;
 use std::io::Result;
+use std::io::Seek;
 use std::io::Write;
 
+/// for when the concrete reader is only known at runtime (`Box<dyn ReadSeek>`)
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// keeps track of how many bytes have been read (for error messages in formats read without `Seek`)
+pub struct CountingReader<'a> {
+    inner: &'a mut dyn Read,
+    position: u64,
+}
+
+impl<'a> CountingReader<'a> {
+    pub fn new(inner: &'a mut dyn Read) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read: usize = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
 pub fn read_u32_le(br: &mut dyn Read) -> Result<u32> {
     let mut buf: [u8; 4] = [0; 4];
     br.read_exact(&mut buf)?;
@@ -115,6 +144,10 @@ pub fn write_u16_le(num: u16, bw: &mut dyn Write) -> Result<()> {
     bw.write_all(&num.to_le_bytes())?;
     Ok(())
 }
+pub fn write_u64_le(num: u64, bw: &mut dyn Write) -> Result<()> {
+    bw.write_all(&num.to_le_bytes())?;
+    Ok(())
+}
 pub fn write_u32_be(num: u32, bw: &mut dyn Write) -> Result<()> {
     bw.write_all(&num.to_be_bytes())?;
     Ok(())
@@ -10,10 +10,13 @@ use std::io::Read;
 #[allow(unused_imports)]
 // not used with "--no-defualt-features", but id rather always have it in compile scope to avoid forgetting to add a faeture flaag thing
 use std::io::Write;
-use std::path::PathBuf;
+#[allow(unused_imports)]
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use crate::pack2::Pack2;
+use crate::extract_utils::{ExtractReport, OnConflict};
+use crate::pack2::{AssetSource, ExtractLayout, Pack2};
+use crate::pack_set::{AssetLocation, PackSet};
 use crate::Nups2Error;
 
 pub fn cli() -> Result<(), Nups2Error> {
@@ -30,12 +33,15 @@ pub fn cli() -> Result<(), Nups2Error> {
         Commands::Pack2Ls {
             pack2_file,
             filename_list_file,
+            detect_types,
+            #[cfg(feature = "asset_filter")]
+            filter,
             #[cfg(feature = "json")]
             json,
             #[cfg(feature = "rainbow_table")]
             rainbow_table_file,
         } => {
-            let mut br: File = File::open(pack2_file)?;
+            let mut br: BufReader<File> = BufReader::new(File::open(pack2_file)?);
             #[allow(unused_mut, unused_variables)]
             let mut pack2: Pack2 = Pack2::load_from_file(&mut br)?;
             #[cfg(feature = "rainbow_table")]
@@ -45,6 +51,11 @@ pub fn cli() -> Result<(), Nups2Error> {
             if let Some(tmp) = filename_list_file {
                 pack2.apply_filename_list(&read_file_lines(&tmp)?);
             }
+            #[cfg(feature = "asset_filter")]
+            pack2.retain_assets(&filter.to_asset_filter()?);
+            if detect_types {
+                pack2.detect_types(&mut br);
+            }
             #[cfg(feature = "json")]
             if json {
                 println!("{}", pack2.ls_assets_as_json());
@@ -57,6 +68,9 @@ pub fn cli() -> Result<(), Nups2Error> {
             files_to_extract,
             output_dir,
             filename_list_file,
+            on_conflict,
+            #[cfg(feature = "asset_filter")]
+            filter,
             #[cfg(feature = "rainbow_table")]
             rainbow_table_file,
         } => {
@@ -70,9 +84,75 @@ pub fn cli() -> Result<(), Nups2Error> {
             if let Some(tmp) = filename_list_file {
                 pack2.apply_filename_list(&read_file_lines(&tmp)?);
             }
+            let on_conflict: OnConflict = on_conflict.into();
             for file in files_to_extract {
-                pack2.extract_file(&mut br, file, output_dir.as_path())?;
+                pack2.extract_file(&mut br, file, output_dir.as_path(), on_conflict)?;
+            }
+            #[cfg(feature = "asset_filter")]
+            {
+                let filter: crate::asset_filter::AssetFilter = filter.to_asset_filter()?;
+                if !filter.is_empty() {
+                    pack2.retain_assets(&filter);
+                    let report: ExtractReport = pack2
+                        .extract_all_named(&mut br, &output_dir, &ExtractLayout::Flat, on_conflict)?
+                        .merge(pack2.extract_all_unnamed(
+                            &mut br,
+                            &output_dir,
+                            &ExtractLayout::Flat,
+                            on_conflict,
+                        )?);
+                    print_extract_report(&report);
+                }
+            }
+        }
+
+        #[cfg(feature = "export")]
+        Commands::Pack2Export {
+            pack2_file,
+            output_file,
+            format,
+            filename_list_file,
+            layout,
+            #[cfg(feature = "json")]
+            index,
+            #[cfg(feature = "asset_filter")]
+            filter,
+        } => {
+            use crate::pack2_export::Pack2Exporter;
+
+            let mut br: BufReader<File> = BufReader::new(File::open(&pack2_file)?);
+            let mut pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            if let Some(tmp) = filename_list_file {
+                pack2.apply_filename_list(&read_file_lines(&tmp)?);
+            }
+            #[cfg(feature = "asset_filter")]
+            pack2.retain_assets(&filter.to_asset_filter()?);
+            let mut exporter: Pack2Exporter = Pack2Exporter::new(format.into());
+            exporter.set_layout(layout.to_extract_layout(&pack2_file));
+            #[cfg(feature = "json")]
+            exporter.set_include_index(index);
+            let target: std::io::BufWriter<File> =
+                std::io::BufWriter::new(File::create_new(&output_file)?);
+            let report: ExtractReport = exporter.export(&pack2, &mut br, target)?;
+            print_extract_report(&report);
+        }
+
+        Commands::Pack2Cat {
+            pack2_file,
+            asset,
+            filename_list_file,
+        } => {
+            let mut br: File = File::open(pack2_file)?;
+            let mut pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            if let Some(tmp) = filename_list_file {
+                pack2.apply_filename_list(&read_file_lines(&tmp)?);
             }
+            let asset: &crate::pack2::Asset = pack2.find_asset(&asset).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "Asset not found in pack2")
+            })?;
+            let mut stdout = std::io::stdout().lock();
+            std::io::copy(&mut asset.open(&mut br)?, &mut stdout)?;
+            stdout.flush()?;
         }
 
         Commands::Pack2ExtractAll {
@@ -85,12 +165,24 @@ pub fn cli() -> Result<(), Nups2Error> {
             rainbow_table_file,
             #[cfg(feature = "manifests")]
             last_extract_manifest,
+            #[cfg(feature = "mmap")]
+            mmap,
+            #[cfg(feature = "fast")]
+            jobs,
+            #[cfg(feature = "asset_filter")]
+            filter,
+            layout,
+            on_conflict,
         } => {
+            let layout: ExtractLayout = layout.to_extract_layout(&pack2_file);
+            let on_conflict: OnConflict = on_conflict.into();
+            let mut report: ExtractReport = ExtractReport::default();
             let mut name_lookup_table: HashMap<u64, String> = HashMap::new();
             log::trace!("open pack2 file {pack2_file:?}");
-            let mut br: File = File::open(pack2_file)?;
-            #[allow(unused_mut)]
-            let mut pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            #[cfg(not(feature = "mmap"))]
+            let mmap: bool = false;
+            let mut opened: OpenedPack2 = OpenedPack2::open(&pack2_file, mmap)?;
+            let pack2: &mut Pack2 = opened.pack2_mut();
             #[cfg(feature = "rainbow_table")]
             if let Some(rtf) = rainbow_table_file {
                 pack2.crack_names_with_rainbow_table(rtf.as_path())?;
@@ -106,26 +198,525 @@ pub fn cli() -> Result<(), Nups2Error> {
             {
                 crate::pack2_manifest::read_manifest_file(&lem)?
             } else {
-                crate::pack2_manifest::Manifest::new()
+                crate::pack2_manifest::Manifest::default()
             };
-            if !exclude_named {
-                #[cfg(not(feature = "manifests"))]
-                pack2.extract_all_named(&mut br, &output_dir)?;
-                #[cfg(feature = "manifests")]
-                pack2.incremental_extract_all_named(&mut br, &output_dir, &manifest)?;
-            }
-            if !exclude_unnamed {
-                #[cfg(not(feature = "manifests"))]
-                pack2.extract_all_unnamed(&mut br, &output_dir)?;
-                #[cfg(feature = "manifests")]
-                pack2.incremental_extract_all_unnamed(&mut br, &output_dir, &manifest)?;
-            }
+            // before filtering, since filtered out assets are not deleted
             #[cfg(feature = "manifests")]
             pack2.incremental_extract_delete_old_files(
                 &output_dir,
+                &layout,
                 &manifest,
                 &name_lookup_table,
             )?;
+            #[cfg(feature = "asset_filter")]
+            pack2.retain_assets(&filter.to_asset_filter()?);
+            #[cfg(not(feature = "fast"))]
+            let jobs: Option<usize> = None;
+            match jobs {
+                #[cfg(feature = "fast")]
+                Some(jobs) => {
+                    let pack2: &Pack2 = opened.pack2();
+                    // every thread gets its own reader
+                    let open_reader = || opened.open_source(&pack2_file);
+                    if !exclude_named {
+                        #[cfg(not(feature = "manifests"))]
+                        let part: ExtractReport = pack2.par_extract_all_named(
+                            open_reader,
+                            &output_dir,
+                            &layout,
+                            on_conflict,
+                            jobs,
+                        )?;
+                        #[cfg(feature = "manifests")]
+                        let part: ExtractReport = pack2.par_incremental_extract_all_named(
+                            open_reader,
+                            &output_dir,
+                            &layout,
+                            &manifest,
+                            on_conflict,
+                            jobs,
+                        )?;
+                        report = report.merge(part);
+                    }
+                    if !exclude_unnamed {
+                        #[cfg(not(feature = "manifests"))]
+                        let part: ExtractReport = pack2.par_extract_all_unnamed(
+                            open_reader,
+                            &output_dir,
+                            &layout,
+                            on_conflict,
+                            jobs,
+                        )?;
+                        #[cfg(feature = "manifests")]
+                        let part: ExtractReport = pack2.par_incremental_extract_all_unnamed(
+                            open_reader,
+                            &output_dir,
+                            &layout,
+                            &manifest,
+                            on_conflict,
+                            jobs,
+                        )?;
+                        report = report.merge(part);
+                    }
+                }
+                _ => {
+                    let (pack2, mut br): (&Pack2, Box<dyn AssetSource + '_>) = opened.split();
+                    if !exclude_named {
+                        #[cfg(not(feature = "manifests"))]
+                        let part: ExtractReport =
+                            pack2.extract_all_named(&mut br, &output_dir, &layout, on_conflict)?;
+                        #[cfg(feature = "manifests")]
+                        let part: ExtractReport = pack2.incremental_extract_all_named(
+                            &mut br,
+                            &output_dir,
+                            &layout,
+                            &manifest,
+                            on_conflict,
+                        )?;
+                        report = report.merge(part);
+                    }
+                    if !exclude_unnamed {
+                        #[cfg(not(feature = "manifests"))]
+                        let part: ExtractReport = pack2.extract_all_unnamed(
+                            &mut br,
+                            &output_dir,
+                            &layout,
+                            on_conflict,
+                        )?;
+                        #[cfg(feature = "manifests")]
+                        let part: ExtractReport = pack2.incremental_extract_all_unnamed(
+                            &mut br,
+                            &output_dir,
+                            &layout,
+                            &manifest,
+                            on_conflict,
+                        )?;
+                        report = report.merge(part);
+                    }
+                }
+            }
+            print_extract_report(&report);
+        }
+
+        Commands::Pack2Header {
+            pack2_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let mut br: File = File::open(pack2_file)?;
+            let pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            let flag_counts: Vec<(u32, usize)> = pack2.flag_counts();
+            #[cfg(feature = "json")]
+            if json {
+                println!(
+                    r#"{{"header":{},"flags":[{}]}}"#,
+                    pack2.header().to_json(),
+                    flag_counts
+                        .iter()
+                        .map(|(flags, count)| format!(r#"{{"flags":{flags},"count":{count}}}"#))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                );
+                return Ok(());
+            }
+            println!("{}", pack2.header());
+            println!("Asset flags:");
+            for (flags, count) in flag_counts {
+                println!("  0x{flags:02X}: {count} assets");
+            }
+        }
+
+        Commands::InstallLs {
+            install_dir,
+            filename_list_file,
+            #[cfg(feature = "asset_filter")]
+            filter,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let pack_set: PackSet = open_pack_set(&install_dir, filename_list_file)?;
+            #[cfg(feature = "asset_filter")]
+            let filter: crate::asset_filter::AssetFilter = filter.to_asset_filter()?;
+            let locations: Vec<AssetLocation> = pack_set
+                .list()
+                .filter(|(_, _, _asset)| {
+                    #[cfg(feature = "asset_filter")]
+                    return filter.matches(_asset);
+                    #[cfg(not(feature = "asset_filter"))]
+                    return true;
+                })
+                .map(|(location, _, _)| location)
+                .collect();
+            #[cfg(feature = "json")]
+            if json {
+                println!("{}", pack_set.ls_as_json(&locations));
+                return Ok(());
+            }
+            println!("{}", pack_set.ls_for_humans(&locations));
+        }
+
+        Commands::InstallExtract {
+            install_dir,
+            files_to_extract,
+            output_dir,
+            filename_list_file,
+            layout,
+            on_conflict,
+            #[cfg(feature = "asset_filter")]
+            filter,
+        } => {
+            let pack_set: PackSet = open_pack_set(&install_dir, filename_list_file)?;
+            let mut locations: Vec<AssetLocation> = Vec::with_capacity(files_to_extract.len());
+            for file in files_to_extract.iter() {
+                locations.push(pack_set.find_asset(file).ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("{file:?} not found in any pack2 file"),
+                    )
+                })?);
+            }
+            #[cfg(feature = "asset_filter")]
+            {
+                let filter: crate::asset_filter::AssetFilter = filter.to_asset_filter()?;
+                if !filter.is_empty() {
+                    locations.extend(
+                        pack_set
+                            .list()
+                            .filter(|(_, _, asset)| filter.matches(asset))
+                            .map(|(location, _, _)| location),
+                    );
+                }
+            }
+            let report: ExtractReport = pack_set.extract(
+                &locations,
+                &output_dir,
+                &layout.to_extract_layout(&install_dir),
+                on_conflict.into(),
+            )?;
+            print_extract_report(&report);
+        }
+
+        Commands::InstallFind {
+            install_dir,
+            assets,
+            filename_list_file,
+        } => {
+            let pack_set: PackSet = open_pack_set(&install_dir, filename_list_file)?;
+            let mut missing: bool = false;
+            for asset in assets {
+                let copies: Vec<AssetLocation> = crate::pack2::candidate_name_hashes(&asset)
+                    .into_iter()
+                    .map(|hash| pack_set.find_all(hash))
+                    .find(|copies| !copies.is_empty())
+                    .unwrap_or_default();
+                if copies.is_empty() {
+                    println!("{asset}: not found");
+                    missing = true;
+                    continue;
+                }
+                for (i, location) in copies.into_iter().enumerate() {
+                    let (entry, found) = pack_set.get(location);
+                    println!(
+                        "{asset}: {pack} (0x{hash:X}){shadowed}",
+                        pack = entry.name,
+                        hash = found.name_hash,
+                        shadowed = if i == 0 { "" } else { " [shadowed]" },
+                    );
+                }
+            }
+            if missing {
+                exit(1);
+            }
+        }
+
+        #[cfg(feature = "manifests")]
+        Commands::InstallSnapshot {
+            install_dir,
+            output_file,
+            game_build,
+        } => {
+            use crate::install_snapshot::InstallSnapshot;
+            let pack_set: PackSet = open_pack_set(&install_dir, None)?;
+            InstallSnapshot::from_pack_set(&pack_set, game_build).write_to_file(&output_file)?;
+        }
+
+        #[cfg(feature = "manifests")]
+        Commands::InstallDiff {
+            new_snapshot_file,
+            old_snapshot_file,
+            filename_list_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            use crate::install_snapshot::{InstallDiff, InstallSnapshot};
+            let filename_list: Vec<String> = if let Some(fnlf) = filename_list_file {
+                read_file_lines(&fnlf)?
+            } else {
+                Vec::new()
+            };
+            let name_lookup_table: HashMap<u64, String> =
+                crate::crc64::filename_list_to_lookup_table(&filename_list);
+            let old_snapshot: InstallSnapshot = InstallSnapshot::read_file(&old_snapshot_file)?;
+            let new_snapshot: InstallSnapshot = InstallSnapshot::read_file(&new_snapshot_file)?;
+            let diff: InstallDiff = new_snapshot.diff(&old_snapshot);
+            #[cfg(feature = "json")]
+            if json {
+                println!("{}", diff.to_json(&name_lookup_table));
+                return Ok(());
+            }
+            println!("{}", diff.render_for_humans(&name_lookup_table));
+        }
+
+        Commands::Pack2Stats {
+            pack2_files,
+            filename_list_file,
+            detect_types,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let named_paths: Vec<(PathBuf, String)> = collect_pack2_files(pack2_files)?;
+            let name_lookup_table: HashMap<u64, String> = match filename_list_file {
+                Some(tmp) => crate::crc64::filename_list_to_lookup_table(&read_file_lines(&tmp)?),
+                None => HashMap::new(),
+            };
+
+            let mut per_pack: Vec<(String, crate::pack2_stats::Pack2Stats)> = Vec::new();
+            let mut total: crate::pack2_stats::Pack2Stats =
+                crate::pack2_stats::Pack2Stats::default();
+            for (path, name) in named_paths {
+                log::info!("reading {path:?}");
+                let mut br: BufReader<File> = BufReader::new(File::open(&path)?);
+                let mut pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+                pack2.apply_filename_lookup_table(&name_lookup_table);
+                if detect_types {
+                    pack2.detect_types(&mut br);
+                }
+                let stats: crate::pack2_stats::Pack2Stats = pack2.stats().with_pack_name(&name);
+                total.merge(stats.clone());
+                per_pack.push((name, stats));
+            }
+
+            #[cfg(feature = "json")]
+            if json {
+                use crate::json_utils::escape_string;
+                println!(
+                    r#"{{"packs":[{}],"total":{}}}"#,
+                    per_pack
+                        .iter()
+                        .map(|(name, stats)| format!(
+                            r#"{{"pack":{},"stats":{}}}"#,
+                            escape_string(name),
+                            stats.to_json()
+                        ))
+                        .collect::<Vec<String>>()
+                        .join(","),
+                    total.to_json()
+                );
+                return Ok(());
+            }
+            let pack_count: usize = per_pack.len();
+            for (name, stats) in per_pack {
+                println!("== {name} ==\n{}\n", stats.render_for_humans());
+            }
+            if pack_count > 1 {
+                println!("== total ==\n{}", total.render_for_humans());
+            }
+        }
+
+        Commands::Duplicates {
+            pack2_files,
+            filename_list_file,
+            verify,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            use crate::duplicates::DuplicateGroup;
+
+            let mut pack_set: PackSet = PackSet::open_files(collect_pack2_files(pack2_files)?)?;
+            if let Some(tmp) = filename_list_file {
+                pack_set.apply_filename_list(&read_file_lines(&tmp)?);
+            }
+            let mut groups: Vec<DuplicateGroup> = crate::duplicates::find_duplicates(&pack_set);
+            if verify {
+                groups = crate::duplicates::verify_duplicates(&pack_set, groups)?;
+            }
+            #[cfg(feature = "json")]
+            if json {
+                println!(
+                    "[{}]",
+                    groups
+                        .iter()
+                        .map(|i| i.to_json(&pack_set))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                );
+                return Ok(());
+            }
+            for group in groups.iter() {
+                println!("{}", group.render_for_humans(&pack_set));
+            }
+            println!(
+                "{} groups, {} redundant copies ({})",
+                groups.len(),
+                groups.iter().map(|i| i.locations.len() - 1).sum::<usize>(),
+                crate::cli_utils::humanise_bytes(
+                    groups.iter().map(|i| i.redundant_bytes()).sum::<u64>() as f64
+                )
+            );
+        }
+
+        Commands::Pack2Check {
+            pack2_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let mut br: File = File::open(pack2_file)?;
+            let pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            let issues: Vec<crate::pack2::Pack2Issue> = pack2.validate();
+            #[cfg(feature = "json")]
+            if json {
+                println!(
+                    "[{}]",
+                    issues
+                        .iter()
+                        .map(|i| i.to_json())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                );
+            }
+            #[cfg(feature = "json")]
+            let print_for_humans: bool = !json;
+            #[cfg(not(feature = "json"))]
+            let print_for_humans: bool = true;
+            if print_for_humans {
+                for issue in issues.iter() {
+                    println!("{issue}");
+                }
+                if issues.is_empty() {
+                    println!("No problems found");
+                }
+            }
+            if !issues.is_empty() {
+                exit(1);
+            }
+        }
+
+        Commands::Pack2Verify {
+            pack2_file,
+            filename_list_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let mut br: File = File::open(&pack2_file)?;
+            let mut pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            if let Some(tmp) = filename_list_file {
+                pack2.apply_filename_list(&read_file_lines(&tmp)?);
+            }
+            let corrupted: Vec<crate::pack2::CorruptedAsset> =
+                pack2.find_corrupted_assets(&pack2_file)?;
+            #[cfg(feature = "json")]
+            if json {
+                println!(
+                    "[{}]",
+                    corrupted
+                        .iter()
+                        .map(|i| format!(
+                            r#"{{"asset":{asset},"found_data_hash":{found}}}"#,
+                            asset = pack2.assets[i.index].to_json(),
+                            found = i
+                                .found_data_hash
+                                .map(|h| h.to_string())
+                                .unwrap_or(String::from("null")),
+                        ))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                );
+            }
+            #[cfg(feature = "json")]
+            let print_for_humans: bool = !json;
+            #[cfg(not(feature = "json"))]
+            let print_for_humans: bool = true;
+            if print_for_humans {
+                for i in corrupted.iter() {
+                    let asset: &crate::pack2::Asset = &pack2.assets[i.index];
+                    match i.found_data_hash {
+                        Some(found) => println!(
+                            "{asset}: expected data_hash 0x{:X}, found 0x{found:X}",
+                            asset.data_hash
+                        ),
+                        None => println!("{asset}: unable to read data"),
+                    }
+                }
+                println!(
+                    "{} of {} assets are corrupted",
+                    corrupted.len(),
+                    pack2.assets.len()
+                );
+            }
+            if !corrupted.is_empty() {
+                exit(1);
+            }
+        }
+
+        Commands::Pack2Create {
+            pack2_file,
+            inputs,
+            uncompressed,
+            embed_filename_list,
+        } => {
+            use crate::pack2_writer::Pack2Writer;
+            let mut writer: Pack2Writer = Pack2Writer::new();
+            writer
+                .set_compression(!uncompressed)
+                .set_embed_filename_list(embed_filename_list);
+            for input in inputs {
+                if input.is_dir() {
+                    writer.add_directory(&input)?;
+                } else {
+                    writer.add_file_by_filename(&input)?;
+                }
+            }
+            log::info!("Writing pack2 file");
+            writer.write_to_file(&pack2_file)?;
+        }
+
+        Commands::Pack2Patch {
+            pack2_file,
+            replacements,
+            compress,
+            uncompressed,
+            dry_run,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            use crate::pack2_patch::{Pack2Patch, Pack2Patcher};
+            let mut patcher: Pack2Patcher = Pack2Patcher::new();
+            patcher.set_compression(match (compress, uncompressed) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            });
+            for replacement in replacements {
+                match replacement.split_once('=') {
+                    Some((name_or_hash, path)) => {
+                        patcher.add_file(name_or_hash, &PathBuf::from(path));
+                    }
+                    None => {
+                        patcher.add_file_by_filename(&PathBuf::from(replacement))?;
+                    }
+                }
+            }
+            let mut file: File = File::options()
+                .read(true)
+                .write(!dry_run)
+                .open(pack2_file)?;
+            let patch: Pack2Patch = patcher.patch(&mut file, dry_run)?;
+            #[cfg(feature = "json")]
+            if json {
+                println!("{}", patch.to_json());
+                return Ok(());
+            }
+            println!("{}", patch.render_for_humans());
         }
 
         #[cfg(feature = "filename_scraper")]
@@ -136,14 +727,23 @@ pub fn cli() -> Result<(), Nups2Error> {
             scrape_mode,
             #[cfg(feature = "manifests")]
             manifest_from_last_scrape,
+            #[cfg(feature = "mmap")]
+            mmap,
+            #[cfg(feature = "asset_filter")]
+            filter,
         } => {
             if scrape_mode > 4 {
                 eprintln!("scrape_mode has to be between 0, 1, 2, 3, or 4");
                 exit(1);
             }
             let mut output_file = File::create_new(output_file)?;
-            let mut br: File = File::open(pack2_file)?;
-            let pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+            #[cfg(not(feature = "mmap"))]
+            let mmap: bool = false;
+            let mut opened: OpenedPack2 = OpenedPack2::open(&pack2_file, mmap)?;
+            #[allow(unused_variables)]
+            let pack2: &mut Pack2 = opened.pack2_mut();
+            #[cfg(feature = "asset_filter")]
+            pack2.retain_assets(&filter.to_asset_filter()?);
             #[allow(unused_mut)]
             let mut limit_to_files: Option<Vec<u64>> = None;
 
@@ -155,13 +755,23 @@ pub fn cli() -> Result<(), Nups2Error> {
                 limit_to_files = Some(diff.iter().map(|i| i.name_hash).collect());
             }
 
-            let filenames: Vec<String> = crate::filename_extractor::extract_names(
-                &pack2,
-                &mut br,
-                filesize_limit,
-                scrape_mode,
-                limit_to_files,
-            )?;
+            let filenames: Vec<String> = match opened {
+                OpenedPack2::File(mut br, pack2) => crate::filename_extractor::extract_names(
+                    &pack2,
+                    &mut br,
+                    filesize_limit,
+                    scrape_mode,
+                    limit_to_files,
+                )?,
+                #[cfg(feature = "mmap")]
+                OpenedPack2::Mapped(mapped) => crate::filename_extractor::extract_names_from_slice(
+                    &mapped.pack2,
+                    mapped.data(),
+                    filesize_limit,
+                    scrape_mode,
+                    limit_to_files,
+                )?,
+            };
             output_file.write_all(filenames.join("\n").as_bytes())?;
         }
 
@@ -169,14 +779,44 @@ pub fn cli() -> Result<(), Nups2Error> {
         Commands::Pack2GenerateManifest {
             pack2_file,
             output_file,
+            pack_name,
+            game_build,
+            v1,
         } => {
+            use crate::pack2_manifest::Manifest;
             if output_file.exists() {
                 eprintln!("ERROR: Output file already exists");
                 exit(1);
             }
-            let mut br: File = File::open(pack2_file)?;
+            let mut br: File = File::open(&pack2_file)?;
             let pack2: Pack2 = Pack2::load_from_file(&mut br)?;
-            pack2.write_manifest_file(output_file.as_path())?;
+            let pack_name: String = pack_name.unwrap_or_else(|| {
+                pack2_file
+                    .file_name()
+                    .map(|i| i.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let mut manifest: Manifest = Manifest::from_pack2(&pack2, &pack_name, game_build);
+            if v1 {
+                manifest.header = None;
+            }
+            manifest.write_to_file(output_file.as_path())?;
+        }
+
+        #[cfg(feature = "manifests")]
+        Commands::ManifestInfo {
+            manifest_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            use crate::pack2_manifest::{read_manifest_file, Manifest};
+            let manifest: Manifest = read_manifest_file(manifest_file.as_path())?;
+            #[cfg(feature = "json")]
+            if json {
+                println!("{}", manifest.header_to_json());
+                return Ok(());
+            }
+            println!("{}", manifest.describe_header());
         }
 
         #[cfg(feature = "manifests")]
@@ -184,6 +824,7 @@ pub fn cli() -> Result<(), Nups2Error> {
             pack2_file,
             filename_list_file,
             manifest_file,
+            output_format,
         } => {
             use crate::pack2_manifest::*;
             let filename_list: Vec<String> = if let Some(fnlf) = filename_list_file {
@@ -197,9 +838,10 @@ pub fn cli() -> Result<(), Nups2Error> {
             let diff: ManifestDiff = pack2.diff_with_manifest(&manifest);
             println!(
                 "{}",
-                render_for_humans(
+                render(
                     &diff,
                     &crate::crc64::filename_list_to_lookup_table(&filename_list),
+                    output_format.into(),
                 )
             );
         }
@@ -209,6 +851,7 @@ pub fn cli() -> Result<(), Nups2Error> {
             new_manifest_file,
             old_manifest_file,
             filename_list_file,
+            output_format,
         } => {
             use crate::pack2_manifest::*;
             let filename_list: Vec<String> = if let Some(fnlf) = filename_list_file {
@@ -221,11 +864,64 @@ pub fn cli() -> Result<(), Nups2Error> {
             let diff: ManifestDiff = diff_two_manifests(&old_manifest, &new_manifest);
             println!(
                 "{}",
-                render_for_humans(
+                render(
                     &diff,
                     &crate::crc64::filename_list_to_lookup_table(&filename_list),
+                    output_format.into(),
                 )
-            )
+            );
+        }
+
+        #[cfg(feature = "manifests")]
+        Commands::ManifestHistory { cmd } => manifest_history(cmd)?,
+
+        #[cfg(all(feature = "manifests", feature = "filename_scraper"))]
+        Commands::PatchNotes {
+            new,
+            old,
+            filename_list_file,
+            title,
+            html,
+        } => {
+            use crate::manifest_history::{read_manifest_or_snapshot, ManifestOrSnapshot};
+            use crate::patch_notes::{PatchNotes, ReportFormat};
+            let name_lookup_table: HashMap<u64, String> = match filename_list_file {
+                Some(fnlf) => crate::crc64::filename_list_to_lookup_table(&read_file_lines(&fnlf)?),
+                None => HashMap::new(),
+            };
+            let notes: PatchNotes = match (
+                read_manifest_or_snapshot(&new)?,
+                read_manifest_or_snapshot(&old)?,
+            ) {
+                (
+                    ManifestOrSnapshot::Manifest(pack_name, new_manifest),
+                    ManifestOrSnapshot::Manifest(_, old_manifest),
+                ) => PatchNotes::from_manifest_diffs(
+                    &title,
+                    &[(
+                        pack_name,
+                        crate::pack2_manifest::diff_two_manifests(&old_manifest, &new_manifest),
+                    )],
+                    &name_lookup_table,
+                ),
+                (
+                    ManifestOrSnapshot::Snapshot(new_snapshot),
+                    ManifestOrSnapshot::Snapshot(old_snapshot),
+                ) => PatchNotes::from_install_diff(
+                    &title,
+                    &new_snapshot.diff(&old_snapshot),
+                    &name_lookup_table,
+                ),
+                _ => return Err("a single manifest can't be compared with a whole install".into()),
+            };
+            println!(
+                "{}",
+                notes.render(if html {
+                    ReportFormat::Html
+                } else {
+                    ReportFormat::Markdown
+                })
+            );
         }
 
         #[cfg(feature = "pack1")]
@@ -250,11 +946,14 @@ pub fn cli() -> Result<(), Nups2Error> {
             pack1_file,
             output_dir,
             chunk_sub_dirs,
+            on_conflict,
         } => {
             use crate::pack1::Pack1;
             let mut br: File = File::open(pack1_file)?;
             let pack1: Pack1 = Pack1::load_from_file(&mut br)?;
-            pack1.extract_all(&mut br, &output_dir, chunk_sub_dirs)?;
+            let report: ExtractReport =
+                pack1.extract_all(&mut br, &output_dir, chunk_sub_dirs, on_conflict.into())?;
+            print_extract_report(&report);
         }
 
         #[cfg(feature = "pack1")]
@@ -292,6 +991,34 @@ pub fn cli() -> Result<(), Nups2Error> {
             pack1.write(&mut br, &mut pack1_br)?;
         }
 
+        #[cfg(feature = "pack1")]
+        Commands::ConvertPack1ToPack2 {
+            pack1_file,
+            pack2_file,
+            uncompressed,
+            embed_filename_list,
+        } => {
+            use crate::pack1::Pack1;
+
+            log::info!("Loading pack1 file");
+            let mut br: BufReader<File> = BufReader::new(File::open(pack1_file)?);
+            let pack1: Pack1 = Pack1::load_from_file(&mut br)?;
+            log::info!("Writing pack2 file");
+            let mut pack2_br: File = File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(pack2_file)?;
+            let pack2: Pack2 = Pack2::from_pack1(
+                &pack1,
+                &mut br,
+                &mut pack2_br,
+                !uncompressed,
+                embed_filename_list,
+            )?;
+            log::info!("Converted {} assets", pack2.assets.len());
+        }
+
         #[cfg(feature = "rainbow_table")]
         Commands::RainbowtableBuild {
             wordlist_file,
@@ -324,6 +1051,118 @@ pub fn cli() -> Result<(), Nups2Error> {
     Ok(())
 }
 
+/// a pack2 file, read through `File` or memory-mapped (`--mmap`)
+enum OpenedPack2 {
+    File(File, Pack2),
+    #[cfg(feature = "mmap")]
+    Mapped(crate::pack2_mmap::MappedPack2),
+}
+
+impl OpenedPack2 {
+    #[allow(unused_variables)]
+    fn open(pack2_file: &Path, mmap: bool) -> Result<Self, Nups2Error> {
+        #[cfg(feature = "mmap")]
+        if mmap {
+            log::debug!("memory-mapping {pack2_file:?}");
+            // SAFETY: not guaranteed, the user opted in with --mmap and was told not to
+            // let anything modify the file while we run (see the --mmap help)
+            return Ok(Self::Mapped(unsafe {
+                crate::pack2_mmap::MappedPack2::open(pack2_file)?
+            }));
+        }
+        let mut br: File = File::open(pack2_file)?;
+        let pack2: Pack2 = Pack2::load_from_file(&mut br)?;
+        Ok(Self::File(br, pack2))
+    }
+
+    #[allow(dead_code)]
+    fn pack2(&self) -> &Pack2 {
+        match self {
+            Self::File(_, pack2) => pack2,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(mapped) => &mapped.pack2,
+        }
+    }
+
+    fn pack2_mut(&mut self) -> &mut Pack2 {
+        match self {
+            Self::File(_, pack2) => pack2,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(mapped) => &mut mapped.pack2,
+        }
+    }
+
+    /// the pack2 and where to read its assets from
+    fn split(&mut self) -> (&Pack2, Box<dyn AssetSource + '_>) {
+        match self {
+            Self::File(br, pack2) => (pack2, Box::new(br)),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(mapped) => (&mapped.pack2, Box::new(&*mapped)),
+        }
+    }
+
+    /// a new source, for threads which can't share one
+    #[allow(dead_code)]
+    fn open_source(&self, pack2_file: &Path) -> std::io::Result<Box<dyn AssetSource + '_>> {
+        match self {
+            Self::File(..) => Ok(Box::new(File::open(pack2_file)?)),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(mapped) => Ok(Box::new(mapped)),
+        }
+    }
+}
+
+/// pack2 files and directories (searched recursively) -> (path, name for display)
+fn collect_pack2_files(paths: Vec<PathBuf>) -> std::io::Result<Vec<(PathBuf, String)>> {
+    let mut result: Vec<(PathBuf, String)> = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut found: Vec<PathBuf> = Vec::new();
+            crate::pack_set::find_pack2_files(&path, &mut found)?;
+            found.sort();
+            for i in found {
+                let name: String = i
+                    .strip_prefix(&path)
+                    .unwrap_or(&i)
+                    .to_string_lossy()
+                    .into_owned();
+                result.push((i, name));
+            }
+        } else {
+            let name: String = path
+                .file_name()
+                .map(|i| i.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            result.push((path, name));
+        }
+    }
+    Ok(result)
+}
+
+fn open_pack_set(
+    install_dir: &Path,
+    filename_list_file: Option<PathBuf>,
+) -> Result<PackSet, Nups2Error> {
+    log::info!("loading pack2 files from {install_dir:?}");
+    let mut pack_set: PackSet = PackSet::open_directory(install_dir)?;
+    if let Some(tmp) = filename_list_file {
+        pack_set.apply_filename_list(&read_file_lines(&tmp)?);
+    }
+    Ok(pack_set)
+}
+
+fn print_extract_report(report: &ExtractReport) {
+    for name in &report.rejected_names {
+        log::warn!("unsafe name {name:?}, extracted under its hash instead");
+    }
+    eprintln!(
+        "extracted {} files, skipped {} existing files, rejected {} names",
+        report.extracted,
+        report.skipped,
+        report.rejected_names.len()
+    );
+}
+
 fn read_file(path: &PathBuf) -> std::io::Result<String> {
     let f: File = File::open(path)?;
     let mut b: BufReader<File> = BufReader::new(f);
@@ -362,16 +1201,25 @@ enum Commands {
         #[clap(long)]
         filename_list_file: Option<PathBuf>,
 
+        /// also show the detected type of each asset (reads the start of every asset)
+        #[clap(long, action)]
+        detect_types: bool,
+
         /// Rainbow-table file to use for decrypting names
         /// You can generate one via rainbowtable-build
         #[cfg(feature = "rainbow_table")]
         #[clap(long)]
         rainbow_table_file: Option<PathBuf>,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
     },
     /// Extract specific files from a pack2 file
     Pack2Extract {
         pack2_file: PathBuf,
 
+        /// names, 0x prefixed hashes or decimal hashes (all assets matching the filter are extracted as well)
         files_to_extract: Vec<String>,
 
         /// Into which dircetory should the extracted files be put?
@@ -382,11 +1230,58 @@ enum Commands {
         #[clap(long)]
         filename_list_file: Option<PathBuf>,
 
+        /// what to do if a file already exists in the output directory
+        #[clap(long, value_enum, default_value_t = OnConflictArg::Error)]
+        on_conflict: OnConflictArg,
+
         /// Rainbow-table file to use for decrypting names
         /// You can generate one via rainbowtable-build
         #[cfg(feature = "rainbow_table")]
         #[clap(long)]
         rainbow_table_file: Option<PathBuf>,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
+    },
+    /// Write the (uncompressed) assets of a pack2 file into a tar or zip archive
+    #[cfg(feature = "export")]
+    Pack2Export {
+        pack2_file: PathBuf,
+
+        /// the archive to create
+        output_file: PathBuf,
+
+        #[clap(long, value_enum)]
+        format: ExportFormatArg,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// how to sort the files into directories inside of the archive
+        #[clap(long, value_enum, default_value_t = LayoutArg::Flat)]
+        layout: LayoutArg,
+
+        /// also add a json index of all assets (nups2_index.json)
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        index: bool,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
+    },
+    /// Write the (uncompressed) content of a single asset to stdout
+    Pack2Cat {
+        pack2_file: PathBuf,
+
+        /// name, 0x prefixed hash or decimal hash of the asset
+        asset: String,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
     },
     /// Extract all files from a pack2 file
     Pack2ExtractAll {
@@ -412,11 +1307,251 @@ enum Commands {
         #[clap(long)]
         last_extract_manifest: Option<PathBuf>,
 
+        /// memory-map the pack2 file instead of reading it
+        /// (the file must not be modified, e.g. by the game launcher, while this runs)
+        #[cfg(feature = "mmap")]
+        #[clap(long, action)]
+        mmap: bool,
+
+        /// how to sort the extracted files into subdirectories
+        #[clap(long, value_enum, default_value_t = LayoutArg::Flat)]
+        layout: LayoutArg,
+
+        /// what to do if a file already exists in the output directory
+        /// (files from the last extraction, see --last-extract-manifest, are always updated)
+        #[clap(long, value_enum, default_value_t = OnConflictArg::Error)]
+        on_conflict: OnConflictArg,
+
+        /// extract with this many threads (0 = one per cpu core)
+        #[cfg(feature = "fast")]
+        #[clap(long)]
+        jobs: Option<usize>,
+
         /// Rainbow-table file to use for decrypting names
         /// You can generate one via rainbowtable-build
         #[cfg(feature = "rainbow_table")]
         #[clap(long)]
         rainbow_table_file: Option<PathBuf>,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
+    },
+
+    /// List the assets of all pack2 files in a directory (for example the game install)
+    /// (if a asset exists in multiple pack2 files only the one from the first file is listed)
+    InstallLs {
+        /// directory, which is searched for pack2 files (recursive)
+        install_dir: PathBuf,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Extract assets from whichever pack2 file in a directory contains them
+    InstallExtract {
+        /// directory, which is searched for pack2 files (recursive)
+        install_dir: PathBuf,
+
+        /// names, 0x prefixed hashes or decimal hashes (all assets matching the filter are extracted as well)
+        files_to_extract: Vec<String>,
+
+        /// Into which dircetory should the extracted files be put?
+        #[clap(long, default_value = ".")]
+        output_dir: PathBuf,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// how to sort the extracted files into subdirectories
+        #[clap(long, value_enum, default_value_t = LayoutArg::Flat)]
+        layout: LayoutArg,
+
+        /// what to do if a file already exists in the output directory
+        #[clap(long, value_enum, default_value_t = OnConflictArg::Error)]
+        on_conflict: OnConflictArg,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
+    },
+
+    /// Show which pack2 files in a directory contain the given assets
+    /// exits with 1 if one was not found
+    InstallFind {
+        /// directory, which is searched for pack2 files (recursive)
+        install_dir: PathBuf,
+
+        /// names, 0x prefixed hashes or decimal hashes
+        #[clap(required = true)]
+        assets: Vec<String>,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+    },
+
+    /// Record the manifests of all pack2 files of a install in one file (see install-diff)
+    #[cfg(feature = "manifests")]
+    InstallSnapshot {
+        /// directory, which is searched for pack2 files (recursive)
+        install_dir: PathBuf,
+
+        output_file: PathBuf,
+
+        /// game build/version stored in the snapshot (if known)
+        #[clap(long)]
+        game_build: Option<String>,
+    },
+
+    /// Compare two install snapshots (including added/removed packs and files moved between packs)
+    #[cfg(feature = "manifests")]
+    InstallDiff {
+        new_snapshot_file: PathBuf,
+        old_snapshot_file: PathBuf,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Show the header of a pack2 file (including the unknown fields) and which asset flags are used
+    Pack2Header {
+        pack2_file: PathBuf,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Statistics about pack2 files (sizes, how many names are known, file types, etc)
+    Pack2Stats {
+        /// pack2 files or directories (searched recursively for pack2 files)
+        #[clap(required = true)]
+        pack2_files: Vec<PathBuf>,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// also count the detected type of each asset (reads the start of every asset)
+        #[clap(long, action)]
+        detect_types: bool,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Find assets with the same content (data_hash and length) in and across pack2 files
+    Duplicates {
+        /// pack2 files or directories (searched recursively for pack2 files)
+        #[clap(required = true)]
+        pack2_files: Vec<PathBuf>,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// read all copies and compare a hash of their actual content (slow)
+        #[clap(long, action)]
+        verify: bool,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Check a pack2 file for corruption (assets outside of the file, overlapping data, etc)
+    /// exits with 1 if problems were found
+    Pack2Check {
+        pack2_file: PathBuf,
+
+        /// Output the problems as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Check the data of all assets in a pack2 file against their data_hash,
+    /// assuming it is a crc32 of the uncompressed data (not verified against files from the game)
+    /// exits with 1 if corrupted assets were found
+    Pack2Verify {
+        pack2_file: PathBuf,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// Output the corrupted assets as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Create a pack2 file from files and directories
+    /// files named crc_64_<hash> (unnamed assets from pack2-extract-all) keep their hash
+    Pack2Create {
+        /// The pack2 file to create
+        pack2_file: PathBuf,
+
+        /// Files or directories (not recursive) to include
+        #[clap(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// do not zlib compress the assets
+        #[clap(long, action)]
+        uncompressed: bool,
+
+        /// include a list of all asset names ({NAMELIST}) so names are known when reading it again
+        #[clap(long, action)]
+        embed_filename_list: bool,
+    },
+
+    /// Replace assets of a pack2 file in place
+    /// (the new data is appended, untouched assets stay where they are)
+    Pack2Patch {
+        pack2_file: PathBuf,
+
+        /// `NAME_OR_HASH=PATH` or just `PATH` to use the filename as name (`crc_64_<hash>.<ext>` as hash)
+        #[clap(required = true)]
+        replacements: Vec<String>,
+
+        /// zlib compress all replacements (by default they are stored like the assets they replace)
+        #[clap(long, action, conflicts_with = "uncompressed")]
+        compress: bool,
+
+        /// store all replacements uncompressed
+        #[clap(long, action)]
+        uncompressed: bool,
+
+        /// only print the changes to the map without modifying the file
+        #[clap(long, action)]
+        dry_run: bool,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
     },
 
     #[cfg(feature = "filename_scraper")]
@@ -437,12 +1572,45 @@ enum Commands {
         #[cfg(feature = "manifests")]
         #[clap(long)]
         manifest_from_last_scrape: Option<PathBuf>,
+
+        /// memory-map the pack2 file instead of reading it
+        /// (the file must not be modified, e.g. by the game launcher, while this runs)
+        #[cfg(feature = "mmap")]
+        #[clap(long, action)]
+        mmap: bool,
+
+        #[cfg(feature = "asset_filter")]
+        #[clap(flatten)]
+        filter: AssetFilterArgs,
     },
 
     #[cfg(feature = "manifests")]
     Pack2GenerateManifest {
         pack2_file: PathBuf,
         output_file: PathBuf,
+
+        /// name of the pack stored in the manifest (default: the filename of the pack2 file)
+        #[clap(long)]
+        pack_name: Option<String>,
+
+        /// game build/version stored in the manifest (if known)
+        #[clap(long)]
+        game_build: Option<String>,
+
+        /// write the old format (only name and data hashes, no header)
+        #[clap(long, action)]
+        v1: bool,
+    },
+
+    /// Show the header of a manifest (pack, creation time, game build, record count)
+    #[cfg(feature = "manifests")]
+    ManifestInfo {
+        manifest_file: PathBuf,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
     },
 
     #[cfg(feature = "manifests")]
@@ -453,6 +1621,9 @@ enum Commands {
 
         #[clap(long)]
         filename_list_file: Option<PathBuf>,
+
+        #[clap(long, value_enum, default_value_t = DiffFormatArg::Human)]
+        output_format: DiffFormatArg,
     },
 
     #[cfg(feature = "manifests")]
@@ -462,6 +1633,35 @@ enum Commands {
 
         #[clap(long)]
         filename_list_file: Option<PathBuf>,
+
+        #[clap(long, value_enum, default_value_t = DiffFormatArg::Human)]
+        output_format: DiffFormatArg,
+    },
+
+    /// Categorised patch notes (markdown or html) for the changes between two manifests,
+    /// two manifest directories (like generate_manifests.nu creates them) or two install snapshots
+    #[cfg(all(feature = "manifests", feature = "filename_scraper"))]
+    PatchNotes {
+        new: PathBuf,
+        old: PathBuf,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        #[clap(long, default_value = "Patch notes")]
+        title: String,
+
+        /// Output a html page instead of markdown
+        #[clap(long, action)]
+        html: bool,
+    },
+
+    /// Collect many manifests / install snapshots over time and query when assets changed
+    #[cfg(feature = "manifests")]
+    ManifestHistory {
+        #[command(subcommand)]
+        cmd: ManifestHistoryCommands,
     },
 
     #[cfg(feature = "pack1")]
@@ -486,6 +1686,10 @@ enum Commands {
         /// put each chunk into its own subdirectory
         #[clap(long, action)]
         chunk_sub_dirs: bool,
+
+        /// what to do if a file already exists in the output directory
+        #[clap(long, value_enum, default_value_t = OnConflictArg::Error)]
+        on_conflict: OnConflictArg,
     },
 
     /// this only works if all filenames are known
@@ -508,6 +1712,21 @@ enum Commands {
         rainbow_table_file: Option<PathBuf>,
     },
 
+    /// names are hashed the same way the game does, so they are lost unless --embed-filename-list is used
+    #[cfg(feature = "pack1")]
+    ConvertPack1ToPack2 {
+        pack1_file: PathBuf,
+        pack2_file: PathBuf,
+
+        /// do not zlib compress the assets
+        #[clap(long, action)]
+        uncompressed: bool,
+
+        /// include a list of all asset names ({NAMELIST}) so names are known when reading it again
+        #[clap(long, action)]
+        embed_filename_list: bool,
+    },
+
     #[cfg(feature = "rainbow_table")]
     RainbowtableBuild {
         /// Path to file containing a list of words (1 word per line)
@@ -555,3 +1774,396 @@ impl clap::ValueEnum for crate::pack1::UnknownNameHandling {
         }
     }
 }
+
+/// shared by all commands, which work on a selection of assets
+#[cfg(feature = "asset_filter")]
+#[derive(clap::Args, Debug)]
+struct AssetFilterArgs {
+    /// only include assets with a name matching this glob (case-insensitive, for example "*_Lod0.dme")
+    #[clap(long = "glob")]
+    globs: Vec<String>,
+
+    /// only include assets with a name containing this regex
+    #[clap(long = "regex")]
+    regexes: Vec<String>,
+
+    /// only include assets with one of these extensions (comma-seperated, for example "dme,dma")
+    #[clap(long = "ext", value_delimiter = ',')]
+    extensions: Vec<String>,
+
+    /// minimum uncompressed size in bytes
+    #[clap(long)]
+    min_size: Option<u64>,
+
+    /// maximum uncompressed size in bytes
+    #[clap(long)]
+    max_size: Option<u64>,
+
+    /// minimum size within the pack2 file in bytes
+    #[clap(long)]
+    min_compressed_size: Option<u64>,
+
+    /// maximum size within the pack2 file in bytes
+    #[clap(long)]
+    max_compressed_size: Option<u64>,
+
+    /// only include compressed assets
+    #[clap(long, action)]
+    compressed_only: bool,
+
+    /// only include assets listed in this file (newline-seperated name hashes, 0x prefixed hex or decimal)
+    #[clap(long)]
+    hash_list_file: Option<PathBuf>,
+}
+
+#[cfg(feature = "asset_filter")]
+impl AssetFilterArgs {
+    fn to_asset_filter(&self) -> Result<crate::asset_filter::AssetFilter, Nups2Error> {
+        let mut filter = crate::asset_filter::AssetFilter::new();
+        for glob in self.globs.iter() {
+            filter.add_glob(glob)?;
+        }
+        for regex in self.regexes.iter() {
+            filter.add_regex(regex)?;
+        }
+        for extension in self.extensions.iter() {
+            filter.add_extension(extension);
+        }
+        filter
+            .set_size_range(self.min_size, self.max_size)
+            .set_compressed_size_range(self.min_compressed_size, self.max_compressed_size)
+            .set_compressed_only(self.compressed_only);
+        if let Some(ref hash_list_file) = self.hash_list_file {
+            filter.add_name_hash_list_file(hash_list_file)?;
+        }
+        Ok(filter)
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum LayoutArg {
+    /// everything directly in the output directory
+    Flat,
+    /// one directory per file extension
+    ByExtension,
+    /// one directory per detected file type
+    ByType,
+    /// one directory per first name token (`Weapon`, `Amerish`, ...)
+    ByNamePrefix,
+    /// one directory per pack2 file (named after it)
+    ByPack,
+}
+
+impl LayoutArg {
+    fn to_extract_layout(&self, pack2_file: &Path) -> ExtractLayout {
+        match self {
+            LayoutArg::Flat => ExtractLayout::Flat,
+            LayoutArg::ByExtension => ExtractLayout::ByExtension,
+            LayoutArg::ByType => ExtractLayout::ByType,
+            LayoutArg::ByNamePrefix => ExtractLayout::ByNamePrefix,
+            LayoutArg::ByPack => ExtractLayout::ByPack(
+                pack2_file
+                    .file_stem()
+                    .map(|i| i.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| String::from("pack")),
+            ),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum OnConflictArg {
+    /// abort the extraction
+    Error,
+    /// keep the existing file
+    Skip,
+    /// replace the existing file
+    Overwrite,
+    /// keep the existing file if its size and crc32 match, replace it otherwise
+    SkipIfIdentical,
+}
+
+impl From<OnConflictArg> for OnConflict {
+    fn from(value: OnConflictArg) -> Self {
+        match value {
+            OnConflictArg::Error => OnConflict::Error,
+            OnConflictArg::Skip => OnConflict::Skip,
+            OnConflictArg::Overwrite => OnConflict::Overwrite,
+            OnConflictArg::SkipIfIdentical => OnConflict::SkipIfIdentical,
+        }
+    }
+}
+
+#[cfg(feature = "manifests")]
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum DiffFormatArg {
+    /// "Created file: ..." lines
+    Human,
+    /// one array
+    #[cfg(feature = "json")]
+    Json,
+    /// one json object per line
+    #[cfg(feature = "json")]
+    Ndjson,
+    Csv,
+}
+
+#[cfg(feature = "manifests")]
+impl From<DiffFormatArg> for crate::pack2_manifest::DiffFormat {
+    fn from(value: DiffFormatArg) -> Self {
+        use crate::pack2_manifest::DiffFormat;
+        match value {
+            DiffFormatArg::Human => DiffFormat::Human,
+            #[cfg(feature = "json")]
+            DiffFormatArg::Json => DiffFormat::Json,
+            #[cfg(feature = "json")]
+            DiffFormatArg::Ndjson => DiffFormat::Ndjson,
+            DiffFormatArg::Csv => DiffFormat::Csv,
+        }
+    }
+}
+
+#[cfg(feature = "manifests")]
+#[derive(Subcommand, Debug)]
+enum ManifestHistoryCommands {
+    /// Add manifests and install snapshots to a history file (created if it does not exist).
+    /// They are added oldest first, manifests with the same date become one revision.
+    Add {
+        history_file: PathBuf,
+
+        /// manifests (v1 or v2), install snapshots and directories with manifests (one revision each)
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+
+        /// date of all files (unix timestamp or YYYY-MM-DD[_HH-MM]).
+        /// default: the creation time stored in the file,
+        /// for v1 manifests and manifest directories the name of their directory (like generate_manifests.nu creates them)
+        #[clap(long)]
+        date: Option<String>,
+
+        /// game build/version of all files (default: the one stored in the file, if any)
+        #[clap(long)]
+        game_build: Option<String>,
+    },
+
+    /// Show the packs and revisions in a history file
+    Info {
+        history_file: PathBuf,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Every version of the given assets (with data_hash and date)
+    Asset {
+        history_file: PathBuf,
+
+        /// names, 0x prefixed hashes or decimal hashes
+        #[clap(required = true)]
+        assets: Vec<String>,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Everything, which changed after FROM up to (including) TO
+    Changes {
+        history_file: PathBuf,
+
+        /// unix timestamp or YYYY-MM-DD[ HH:MM[:SS]] (UTC)
+        from: String,
+
+        /// same format as FROM (default: now)
+        to: Option<String>,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Assets, which still exist and have not changed after DATE
+    UnchangedSince {
+        history_file: PathBuf,
+
+        /// unix timestamp or YYYY-MM-DD[ HH:MM[:SS]] (UTC)
+        date: String,
+
+        /// Path to a file containing a newline-seperated list of filenames (for example from pack2-scrape-filenames)
+        #[clap(long)]
+        filename_list_file: Option<PathBuf>,
+
+        /// Output as json for further use by other programs
+        #[cfg(feature = "json")]
+        #[clap(long, action)]
+        json: bool,
+    },
+}
+
+#[cfg(feature = "manifests")]
+fn manifest_history(cmd: ManifestHistoryCommands) -> Result<(), Nups2Error> {
+    use crate::manifest_history::{render_entries_for_humans, HistoryEntry, ManifestHistory};
+
+    let parse_date = |date: &str| -> Result<u64, Nups2Error> {
+        crate::cli_utils::parse_timestamp(date)
+            .ok_or_else(|| Nups2Error::OtherDyn(format!("{date:?} is not a valid date")))
+    };
+    let lookup_table =
+        |filename_list_file: Option<PathBuf>| -> Result<HashMap<u64, String>, Nups2Error> {
+            Ok(match filename_list_file {
+                Some(fnlf) => crate::crc64::filename_list_to_lookup_table(&read_file_lines(&fnlf)?),
+                None => HashMap::new(),
+            })
+        };
+    #[allow(unused_variables)]
+    let print_entries =
+        |entries: Vec<HistoryEntry>, name_lookup_table: HashMap<u64, String>, json: bool| {
+            #[cfg(feature = "json")]
+            if json {
+                println!(
+                    "{}",
+                    crate::manifest_history::entries_to_json(&entries, &name_lookup_table)
+                );
+                return;
+            }
+            println!(
+                "{}",
+                render_entries_for_humans(&entries, &name_lookup_table)
+            );
+        };
+    #[cfg(not(feature = "json"))]
+    let json: bool = false;
+
+    match cmd {
+        ManifestHistoryCommands::Add {
+            history_file,
+            files,
+            date,
+            game_build,
+        } => {
+            let mut history: ManifestHistory = if history_file.exists() {
+                ManifestHistory::read_file(&history_file)?
+            } else {
+                ManifestHistory::new()
+            };
+            let date: Option<u64> = date.as_deref().map(parse_date).transpose()?;
+            let revision_count: usize = history.revisions.len();
+            let new_versions: usize = history.add_files(&files, date, game_build)?;
+            history.write_to_file(&history_file)?;
+            println!(
+                "Added {} revisions with {new_versions} new versions ({} assets, {} versions in total)",
+                history.revisions.len() - revision_count,
+                history.asset_count(),
+                history.version_count()
+            );
+        }
+
+        ManifestHistoryCommands::Info {
+            history_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let history: ManifestHistory = ManifestHistory::read_file(&history_file)?;
+            #[cfg(feature = "json")]
+            if json {
+                println!("{}", history.to_json());
+                return Ok(());
+            }
+            println!("{}", history.describe());
+        }
+
+        ManifestHistoryCommands::Asset {
+            history_file,
+            assets,
+            filename_list_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let history: ManifestHistory = ManifestHistory::read_file(&history_file)?;
+            let mut name_lookup_table: HashMap<u64, String> = lookup_table(filename_list_file)?;
+            let mut entries: Vec<HistoryEntry> = Vec::new();
+            for asset in assets.iter() {
+                let found: Vec<HistoryEntry> = crate::pack2::candidate_name_hashes(asset)
+                    .into_iter()
+                    .map(|name_hash| history.versions(name_hash))
+                    .find(|i| !i.is_empty())
+                    .unwrap_or_default();
+                if found.is_empty() {
+                    log::warn!("{asset:?} not found in the history");
+                    continue;
+                }
+                let name_hash: u64 = found[0].name_hash;
+                if name_hash == crate::crc64::convert_filename(asset) {
+                    name_lookup_table.insert(name_hash, asset.clone());
+                }
+                entries.extend(found);
+            }
+            print_entries(entries, name_lookup_table, json);
+        }
+
+        ManifestHistoryCommands::Changes {
+            history_file,
+            from,
+            to,
+            filename_list_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let history: ManifestHistory = ManifestHistory::read_file(&history_file)?;
+            let to: u64 = match to {
+                Some(to) => parse_date(&to)?,
+                None => u64::MAX,
+            };
+            print_entries(
+                history.changes_between(parse_date(&from)?, to),
+                lookup_table(filename_list_file)?,
+                json,
+            );
+        }
+
+        ManifestHistoryCommands::UnchangedSince {
+            history_file,
+            date,
+            filename_list_file,
+            #[cfg(feature = "json")]
+            json,
+        } => {
+            let history: ManifestHistory = ManifestHistory::read_file(&history_file)?;
+            print_entries(
+                history.unchanged_since(parse_date(&date)?),
+                lookup_table(filename_list_file)?,
+                json,
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "export")]
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum ExportFormatArg {
+    Tar,
+    Zip,
+}
+
+#[cfg(feature = "export")]
+impl From<ExportFormatArg> for crate::pack2_export::ExportFormat {
+    fn from(value: ExportFormatArg) -> Self {
+        match value {
+            ExportFormatArg::Tar => crate::pack2_export::ExportFormat::Tar,
+            ExportFormatArg::Zip => crate::pack2_export::ExportFormat::Zip,
+        }
+    }
+}
@@ -10,3 +10,108 @@ pub fn humanise_bytes(bytes: f64) -> String {
     }
     format!("{:.0}Bytes", bytes)
 }
+
+/// `YYYY-MM-DD HH:MM:SS UTC`
+pub fn format_unix_timestamp(timestamp: u64) -> String {
+    let days: i64 = (timestamp / 86400) as i64;
+    let seconds: u64 = timestamp % 86400;
+    // civil_from_days (https://howardhinnant.github.io/date_algorithms.html)
+    let z: i64 = days + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let doe: i64 = z.rem_euclid(146097);
+    let yoe: i64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: i64 = doy - (153 * mp + 2) / 5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// a unix timestamp or `YYYY-MM-DD` with an optional time (`HH:MM[:SS]` or `HH-MM[-SS]`),
+/// separated by a space, `T` or `_` (like the directory names of generate_manifests.nu).
+/// always UTC, a trailing ` UTC` or `Z` is ignored.
+pub fn parse_timestamp(text: &str) -> Option<u64> {
+    let text: &str = text.trim();
+    let text: &str = text
+        .strip_suffix(" UTC")
+        .or_else(|| text.strip_suffix('Z'))
+        .unwrap_or(text);
+    if text.bytes().all(|i| i.is_ascii_digit()) {
+        return text.parse::<u64>().ok();
+    }
+    let (date, time): (&str, Option<&str>) = match text.split_once([' ', 'T', '_']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|i| i.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let (year, month, day): (i64, i64, i64) = match date[..] {
+        [year, month, day] if (1..=12).contains(&month) && day >= 1 => (year, month, day),
+        _ => return None,
+    };
+    let is_leap_year: bool = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month: i64 = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if day > days_in_month {
+        return None;
+    }
+    let seconds: i64 = match time {
+        Some(time) => {
+            let time: Vec<i64> = time
+                .split([':', '-'])
+                .map(|i| i.parse::<i64>().ok())
+                .collect::<Option<Vec<i64>>>()?;
+            let (hours, minutes, seconds): (i64, i64, i64) = match time[..] {
+                [hours, minutes] => (hours, minutes, 0),
+                [hours, minutes, seconds] => (hours, minutes, seconds),
+                _ => return None,
+            };
+            if hours > 23 || minutes > 59 || seconds > 59 {
+                return None;
+            }
+            hours * 3600 + minutes * 60 + seconds
+        }
+        None => 0,
+    };
+    // days_from_civil (see format_unix_timestamp)
+    let y: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = y.div_euclid(400);
+    let yoe: i64 = y - era * 400;
+    let doy: i64 = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe: i64 = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    // years far in the future would overflow
+    let days: i64 = era.checked_mul(146097)?.checked_add(doe - 719468)?;
+    u64::try_from(days.checked_mul(86400)?.checked_add(seconds)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_timestamp;
+
+    #[test]
+    fn parse_timestamps() {
+        let day: u64 = 86400;
+        assert_eq!(parse_timestamp("86400"), Some(day));
+        assert_eq!(parse_timestamp("1970-01-02"), Some(day));
+        assert_eq!(parse_timestamp("2024-02-29_13-37"), Some(1709213820));
+        assert_eq!(parse_timestamp("2024-02-29 13:37:00 UTC"), Some(1709213820));
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+        assert_eq!(parse_timestamp("2024-02-31"), None);
+        assert_eq!(parse_timestamp("2023-02-29"), None);
+        assert_eq!(parse_timestamp("2024-04-31"), None);
+        assert_eq!(parse_timestamp("9223372036854775807-01-01"), None);
+        assert_eq!(parse_timestamp("292277026596-12-31_23:59:59"), None);
+    }
+}
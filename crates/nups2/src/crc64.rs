@@ -4,12 +4,38 @@
 use std::collections::HashMap;
 
 pub fn hash(bytes: &[u8]) -> u64 {
-    let mut crc: u64 = u64::MAX;
-    for c in bytes {
-        let tab_index = ((crc & 0xff) ^ (*c as u64)) & 0xff;
-        crc = CRC_TABLE[tab_index as usize] ^ (crc >> 8);
+    let mut hasher: Hasher = Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// same as `hash`, but for data, which is not in memory at once
+#[derive(Debug, Clone)]
+pub struct Hasher {
+    crc: u64,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self { crc: u64::MAX }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for c in bytes {
+            let tab_index = ((self.crc & 0xff) ^ (*c as u64)) & 0xff;
+            self.crc = CRC_TABLE[tab_index as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    pub fn finalize(&self) -> u64 {
+        self.crc ^ u64::MAX
     }
-    crc ^ u64::MAX
 }
 
 /// additionally applies .to_uppercase
@@ -1,4 +1,5 @@
 use crate::bin_utils::*;
+use crate::Nups2Error;
 use std::io::Read;
 
 #[derive(Debug)]
@@ -23,10 +24,25 @@ pub struct DmaParameter {
 }
 
 impl Dma {
-    pub fn read(br: &mut dyn Read) -> std::io::Result<Self> {
+    pub fn read(br: &mut dyn Read) -> Result<Self, Nups2Error> {
+        Self::read_counted(&mut CountingReader::new(br))
+    }
+
+    /// for formats embedding a dma (`Dme`), so offsets in errors are relative to their start
+    pub(crate) fn read_counted(br: &mut CountingReader) -> Result<Self, Nups2Error> {
+        Self::internal_read(br).map_err(|e| e.truncated_at(br.position()))
+    }
+
+    fn internal_read(br: &mut CountingReader) -> Result<Self, Nups2Error> {
         log::trace!("started reading dma");
         let magic_value: u32 = read_u32_be(br)?;
-        assert_eq!(magic_value, 0x444d4154u32, "Magick value of DMA is wrong");
+        if magic_value != 0x444d4154u32 {
+            return Err(Nups2Error::BadMagic {
+                format: "dma",
+                expected: 0x444d4154u32,
+                found: magic_value,
+            });
+        }
         let version: u32 = read_u32_le(br)?;
 
         let var_a_length: u32 = read_u32_le(br)?;
@@ -65,7 +81,7 @@ impl Dma {
 }
 
 impl DmaMaterial {
-    pub fn read(br: &mut dyn Read) -> std::io::Result<Self> {
+    pub fn read(br: &mut dyn Read) -> Result<Self, Nups2Error> {
         let name_hash: u32 = read_u32_le(br)?;
         let _data_length: u32 = read_u32_le(br)?;
         let material_definition_hash: u32 = read_u32_le(br)?;
@@ -97,21 +113,19 @@ impl DmaMaterial {
 }
 
 impl DmaParameter {
-    pub fn read(br: &mut dyn Read) -> std::io::Result<Self> {
+    pub fn read(br: &mut dyn Read) -> Result<Self, Nups2Error> {
         let name_hash: u32 = read_u32_le(br)?;
-        let d3dx_parameter_class: D3dxparameterClass =
-            D3dxparameterClass::try_from(read_u32_le(br)?).map_err(|_| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "Invalid D3dxparameterClass in dma",
-                )
+        let class_value: u32 = read_u32_le(br)?;
+        let d3dx_parameter_class: D3dxparameterClass = D3dxparameterClass::try_from(class_value)
+            .map_err(|_| Nups2Error::InvalidEnum {
+                name: "D3dxparameterClass",
+                value: class_value,
             })?;
-        let d3dx_parameter_type: D3dxparameterType = D3dxparameterType::try_from(read_u32_le(br)?)
-            .map_err(|_| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    "Invalid D3dxparameterType in dma",
-                )
+        let type_value: u32 = read_u32_le(br)?;
+        let d3dx_parameter_type: D3dxparameterType = D3dxparameterType::try_from(type_value)
+            .map_err(|_| Nups2Error::InvalidEnum {
+                name: "D3dxparameterType",
+                value: type_value,
             })?;
 
         let data_length: u32 = read_u32_le(br)?;
@@ -20,25 +20,34 @@ pub struct Dme {
 impl Dme {
     pub fn read(br: &mut dyn Read) -> Result<Self, Nups2Error> {
         log::trace!("[Dme::read] start");
+        let mut br: CountingReader = CountingReader::new(br);
+        Self::internal_read(&mut br).map_err(|e| e.truncated_at(br.position()))
+    }
+
+    fn internal_read(br: &mut CountingReader) -> Result<Self, Nups2Error> {
         let magic: u32 = read_u32_be(br)?;
         if magic != 0x444d4f44 {
-            return Err(Nups2Error::Other("DME: file missing magic value"));
+            return Err(Nups2Error::BadMagic {
+                format: "dme",
+                expected: 0x444d4f44,
+                found: magic,
+            });
         }
         let version: u32 = read_u32_le(br)?;
         match version {
-            4 => Ok(Self::internal_read_v4(br)?),
-            _ => Err(Nups2Error::OtherDyn(format!(
-                "Unsupported DME version ({})",
-                version
-            ))),
+            4 => Self::internal_read_v4(br),
+            _ => Err(Nups2Error::UnsupportedVersion {
+                format: "dme",
+                version,
+            }),
         }
     }
 
     /// DO NOT CALL FROM OUTSIDE
     /// called by read if it determines its v4 after changing some things
-    fn internal_read_v4(br: &mut dyn Read) -> std::io::Result<Self> {
+    fn internal_read_v4(br: &mut CountingReader) -> Result<Self, Nups2Error> {
         let _dma_length: u32 = read_u32_le(br)?;
-        let dma: Dma = Dma::read(br)?;
+        let dma: Dma = Dma::read_counted(br)?;
         let bounding_box_min: Vector3 = read_vector3(br)?;
         let bounding_box_max: Vector3 = read_vector3(br)?;
 
@@ -0,0 +1,232 @@
+use crate::pack2::Asset;
+use crate::pack_set::{AssetLocation, PackSet};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Result};
+
+/// Assets with the same content (in one or more pack2 files)
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    /// crc32 from the map
+    pub data_hash: u32,
+    /// uncompressed
+    pub length: u64,
+    /// crc64 of the actual content (only set by `verify_duplicates`)
+    pub content_hash: Option<u64>,
+    /// every copy, including shadowed ones (see `PackSet`)
+    pub locations: Vec<AssetLocation>,
+}
+
+impl DuplicateGroup {
+    /// how much space hardlinking / deduplicating the copies would save
+    pub fn redundant_bytes(&self) -> u64 {
+        self.length * (self.locations.len() as u64 - 1)
+    }
+
+    /// the known names of the copies (can be applied to the unnamed ones)
+    pub fn names<'a>(&self, pack_set: &'a PackSet) -> Vec<&'a str> {
+        let mut names: Vec<&str> = self
+            .locations
+            .iter()
+            .filter_map(|location| pack_set.get(*location).1.name.as_deref())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, pack_set: &PackSet) -> String {
+        use crate::json_utils::escape_string;
+
+        format!(
+            r#"{{"data_hash":{data_hash},"length":{length},"content_hash":{content_hash},"locations":[{locations}]}}"#,
+            data_hash = self.data_hash,
+            length = self.length,
+            content_hash = self
+                .content_hash
+                .map(|i| i.to_string())
+                .unwrap_or(String::from("null")),
+            locations = self
+                .locations
+                .iter()
+                .map(|location| {
+                    let (entry, asset) = pack_set.get(*location);
+                    format!(
+                        r#"{{"pack":{},"name_hash":{},"name":{}}}"#,
+                        escape_string(&entry.name),
+                        asset.name_hash,
+                        asset
+                            .name
+                            .as_deref()
+                            .map(escape_string)
+                            .unwrap_or(String::from("null"))
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+    }
+
+    pub fn render_for_humans(&self, pack_set: &PackSet) -> String {
+        let mut lines: Vec<String> = vec![format!(
+            "{} copies of {} bytes (data_hash 0x{:08X}{})",
+            self.locations.len(),
+            self.length,
+            self.data_hash,
+            if self.content_hash.is_some() {
+                ", verified"
+            } else {
+                ""
+            }
+        )];
+        for location in self.locations.iter() {
+            let (entry, asset) = pack_set.get(*location);
+            lines.push(format!(
+                "  {}: {}",
+                entry.name,
+                asset
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("crc64({})", asset.name_hash))
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// group all (non-empty) assets by `data_hash` and uncompressed length.
+/// groups are sorted by `redundant_bytes`, biggest first.
+/// the map could lie (or crc32 collide), use `verify_duplicates` to be sure.
+pub fn find_duplicates(pack_set: &PackSet) -> Vec<DuplicateGroup> {
+    let mut groups: HashMap<(u32, u64), Vec<AssetLocation>> = HashMap::new();
+    for (pack_id, entry) in pack_set.packs.iter().enumerate() {
+        for (asset_id, asset) in entry.pack2.assets.iter().enumerate() {
+            let length: u64 = asset.uncompressed_length();
+            if length == 0 {
+                continue;
+            }
+            groups
+                .entry((asset.data_hash, length))
+                .or_default()
+                .push(AssetLocation {
+                    pack: pack_id,
+                    asset: asset_id,
+                });
+        }
+    }
+    let mut result: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|(_, locations)| locations.len() > 1)
+        .map(|((data_hash, length), locations)| DuplicateGroup {
+            data_hash,
+            length,
+            content_hash: None,
+            locations,
+        })
+        .collect();
+    sort_groups(&mut result);
+    result
+}
+
+/// read every copy and split the groups by the hash of their actual content.
+/// copies, which turn out to be different from all others, are dropped.
+pub fn verify_duplicates(
+    pack_set: &PackSet,
+    groups: Vec<DuplicateGroup>,
+) -> Result<Vec<DuplicateGroup>> {
+    let mut readers: HashMap<usize, BufReader<File>> = HashMap::new();
+    let mut result: Vec<DuplicateGroup> = Vec::with_capacity(groups.len());
+    for group in groups {
+        let mut by_content: HashMap<u64, Vec<AssetLocation>> = HashMap::new();
+        for location in group.locations.iter() {
+            let br: &mut BufReader<File> = match readers.entry(location.pack) {
+                std::collections::hash_map::Entry::Occupied(i) => i.into_mut(),
+                std::collections::hash_map::Entry::Vacant(i) => i.insert(BufReader::new(
+                    File::open(&pack_set.packs[location.pack].path)?,
+                )),
+            };
+            let content_hash: u64 = hash_content(pack_set.get(*location).1, br)?;
+            by_content.entry(content_hash).or_default().push(*location);
+        }
+        if by_content.len() > 1 {
+            log::warn!(
+                "assets with data_hash 0x{:08X} and length {} have different content",
+                group.data_hash,
+                group.length
+            );
+        }
+        result.extend(
+            by_content
+                .into_iter()
+                .filter(|(_, locations)| locations.len() > 1)
+                .map(|(content_hash, locations)| DuplicateGroup {
+                    content_hash: Some(content_hash),
+                    locations,
+                    ..group.clone()
+                }),
+        );
+    }
+    sort_groups(&mut result);
+    Ok(result)
+}
+
+fn hash_content(asset: &Asset, br: &mut BufReader<File>) -> Result<u64> {
+    let mut reader = asset.open(br)?;
+    let mut hasher: crate::crc64::Hasher = crate::crc64::Hasher::new();
+    let mut buf: [u8; 8192] = [0; 8192];
+    loop {
+        let read: usize = reader.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..read]);
+    }
+}
+
+fn sort_groups(groups: &mut [DuplicateGroup]) {
+    groups.sort_by(|a, b| {
+        b.redundant_bytes()
+            .cmp(&a.redundant_bytes())
+            .then_with(|| a.data_hash.cmp(&b.data_hash))
+            .then_with(|| a.locations.cmp(&b.locations))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_duplicates, verify_duplicates, DuplicateGroup};
+    use crate::pack2_writer::Pack2Writer;
+    use crate::pack_set::PackSet;
+    use std::path::PathBuf;
+
+    #[test]
+    fn find_and_verify_duplicates() {
+        let root: PathBuf =
+            std::env::temp_dir().join(format!("nups2_duplicates_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("a.txt", b"same".to_vec())
+            .add_bytes("b.txt", b"same".to_vec())
+            .add_bytes("c.txt", b"other".to_vec());
+        writer.write_to_file(&root.join("one.pack2")).unwrap();
+        let mut writer = Pack2Writer::new();
+        writer.add_raw(1234, b"same".to_vec());
+        writer.write_to_file(&root.join("two.pack2")).unwrap();
+
+        let mut pack_set: PackSet = PackSet::open_directory(&root).unwrap();
+        pack_set.apply_filename_list(&["a.txt".into(), "b.txt".into()]);
+        let groups: Vec<DuplicateGroup> = find_duplicates(&pack_set);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].locations.len(), 3);
+        assert_eq!(groups[0].redundant_bytes(), 8);
+        assert_eq!(groups[0].names(&pack_set), vec!["a.txt", "b.txt"]);
+
+        let verified: Vec<DuplicateGroup> = verify_duplicates(&pack_set, groups).unwrap();
+        assert_eq!(verified.len(), 1);
+        assert_eq!(verified[0].content_hash, Some(crate::crc64::hash(b"same")));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
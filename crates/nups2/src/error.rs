@@ -4,27 +4,118 @@ use std::fmt::Display;
 pub enum Nups2Error {
     IoError(std::io::Error),
     Utf8Error(std::string::FromUtf8Error),
+    /// The data does not start with the magic value of the expected format
+    BadMagic {
+        format: &'static str,
+        expected: u32,
+        found: u32,
+    },
+    /// The data ended while reading the structure at `offset`
+    Truncated {
+        offset: u64,
+    },
+    UnsupportedVersion {
+        format: &'static str,
+        version: u32,
+    },
+    /// A value, which does not match any variant of the enum `name`
+    InvalidEnum {
+        name: &'static str,
+        value: u32,
+    },
+    /// `length` bytes at `offset` do not fit into the file (`file_length` bytes)
+    OffsetOutOfBounds {
+        offset: u64,
+        length: u64,
+        file_length: u64,
+    },
     Other(&'static str),
     OtherDyn(String),
 }
 
+impl Nups2Error {
+    /// turn a `UnexpectedEof` into `Truncated` (other errors are kept as they are)
+    pub(crate) fn from_io(error: std::io::Error, offset: u64) -> Self {
+        if error.kind() == std::io::ErrorKind::UnexpectedEof {
+            Nups2Error::Truncated { offset }
+        } else {
+            Nups2Error::IoError(error)
+        }
+    }
+
+    /// same as `from_io` for errors, which already got converted into a `Nups2Error`
+    #[cfg(any(feature = "dma", feature = "manifests"))]
+    pub(crate) fn truncated_at(self, offset: u64) -> Self {
+        match self {
+            Nups2Error::IoError(error) => Self::from_io(error, offset),
+            other => other,
+        }
+    }
+}
+
 impl Display for Nups2Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Nups2Error::IoError(error) => error.fmt(f),
             Nups2Error::Utf8Error(error) => error.fmt(f),
+            Nups2Error::BadMagic {
+                format,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Error: {format} magic value not found (expected 0x{expected:X}, found 0x{found:X})"
+            ),
+            Nups2Error::Truncated { offset } => {
+                write!(f, "Error: data is truncated (at offset {offset})")
+            }
+            Nups2Error::UnsupportedVersion { format, version } => {
+                write!(f, "Error: unsupported {format} version ({version})")
+            }
+            Nups2Error::InvalidEnum { name, value } => {
+                write!(f, "Error: invalid {name} value ({value})")
+            }
+            Nups2Error::OffsetOutOfBounds {
+                offset,
+                length,
+                file_length,
+            } => write!(
+                f,
+                "Error: {length} bytes at offset {offset} are outside of the file ({file_length} bytes)"
+            ),
             Nups2Error::Other(m) => write!(f, "Error: {}", m),
             Nups2Error::OtherDyn(m) => write!(f, "Error: {}", m),
         }
     }
 }
 
+impl std::error::Error for Nups2Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Nups2Error::IoError(error) => Some(error),
+            Nups2Error::Utf8Error(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
 impl From<std::io::Error> for Nups2Error {
     fn from(value: std::io::Error) -> Self {
         Self::IoError(value)
     }
 }
 
+/// for functions, which still return `std::io::Result`
+/// (the original error can be retrieved with `std::io::Error::get_ref`)
+impl From<Nups2Error> for std::io::Error {
+    fn from(value: Nups2Error) -> Self {
+        match value {
+            Nups2Error::IoError(error) => error,
+            other => std::io::Error::new(std::io::ErrorKind::InvalidData, other),
+        }
+    }
+}
+
 impl From<&'static str> for Nups2Error {
     fn from(value: &'static str) -> Self {
         Self::Other(value)
@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Result};
+use std::path::Path;
+
+/// What to do if a file that is about to be extracted already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// abort the extraction
+    #[default]
+    Error,
+    /// keep the existing file
+    Skip,
+    /// replace the existing file
+    Overwrite,
+    /// keep the existing file if its size and crc32 match the asset, replace it otherwise.
+    /// (assumes `data_hash` is a crc32, see `Asset::calculate_data_hash`)
+    SkipIfIdentical,
+}
+
+/// Summary of a extraction
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    pub extracted: usize,
+    /// existing files, which were kept (`OnConflict::Skip` / `OnConflict::SkipIfIdentical`)
+    pub skipped: usize,
+    /// names, which are not safe to use as a filename (see `is_safe_name`).
+    /// those assets are extracted under their hash instead.
+    pub rejected_names: Vec<String>,
+}
+
+impl ExtractReport {
+    pub fn merge(mut self, other: ExtractReport) -> Self {
+        self.extracted += other.extracted;
+        self.skipped += other.skipped;
+        self.rejected_names.extend(other.rejected_names);
+        self
+    }
+}
+
+/// true if `name` can be joined onto a directory without leaving it
+/// (no `..`, path seperators, absolute paths, drive letters, etc)
+pub fn is_safe_name(name: &str) -> bool {
+    !(name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', ':', '\0']))
+}
+
+/// create `path` according to `on_conflict`.
+/// returns `None` if the existing file should be kept.
+/// `expected_length` and `expected_crc32` describe the (uncompressed) data about to be written.
+pub fn create_output_file(
+    path: &Path,
+    on_conflict: OnConflict,
+    expected_length: u64,
+    expected_crc32: u32,
+) -> Result<Option<File>> {
+    if path.symlink_metadata().is_ok() {
+        match on_conflict {
+            OnConflict::Error => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!("{path:?} already exists"),
+                ))
+            }
+            OnConflict::Skip => return Ok(None),
+            OnConflict::SkipIfIdentical if is_identical(path, expected_length, expected_crc32)? => {
+                return Ok(None)
+            }
+            OnConflict::Overwrite | OnConflict::SkipIfIdentical => {
+                // remove instead of truncating to not write through symlinks
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(Some(File::create_new(path)?))
+}
+
+fn is_identical(path: &Path, expected_length: u64, expected_crc32: u32) -> Result<bool> {
+    if !path.is_file() || std::fs::metadata(path)?.len() != expected_length {
+        return Ok(false);
+    }
+    let mut br: BufReader<File> = BufReader::new(File::open(path)?);
+    let mut hasher: crc32fast::Hasher = crc32fast::Hasher::new();
+    let mut buf: [u8; 8192] = [0; 8192];
+    loop {
+        let read: usize = br.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.finalize() == expected_crc32);
+        }
+        hasher.update(&buf[..read]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_safe_name;
+
+    #[test]
+    fn safe_names() {
+        assert!(is_safe_name("Weapon_NC_Pistol_Lod0.dme"));
+        assert!(is_safe_name("..hidden"));
+        for name in [
+            "",
+            ".",
+            "..",
+            "../x",
+            "a/b",
+            "a\\b",
+            "/etc/passwd",
+            "C:x",
+            "a\0b",
+        ] {
+            assert!(!is_safe_name(name), "{name:?}");
+        }
+    }
+}
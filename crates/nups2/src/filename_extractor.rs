@@ -1,7 +1,8 @@
-use crate::pack2::Pack2;
+use crate::pack2::{Asset, Pack2};
 use regex::Regex;
+use std::borrow::Cow;
 use std::collections::HashSet;
-use std::{fs::File, io::Result};
+use std::io::{Read, Result, Seek};
 
 const INTERRESTING_BYTES: &[u8] = &[
     65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88,
@@ -28,7 +29,7 @@ const FILENAME_REGEX_STRINGS: [&str; 5] = [
     r#"[A-Za-z0-9<>._-]+\.(?i)(adr|agr|ags|apb|apx|bat|bmp|bin|cdt|cnk[0-9]?|crc|crt|cso|cur|dat|db|dds|def|dir|dll|dm[aev]|dsk|dx11efb|dx11rsb|dx11ssb|eco|efb|exe|fbx|fsb|fx|fxh|fxd|fxo|gr2|gfx|gnf|i64|ind|ini|jpg|lst|lua|mrn|nsa|pak|pem|playerstudio|png|prsb|psd|pssb|swf|tga|thm|tome|ttf|txt|vnfo|wav|xlsx|xmd|xml|xrsb|xssb|zone)"#,
 ];
 
-const CONTINENTS: &[&str] = &["Amerish", "Esamir", "Hossin", "Indar", "Oshur"];
+pub const CONTINENTS: &[&str] = &["Amerish", "Esamir", "Hossin", "Indar", "Oshur"];
 
 /// search_mode:
 ///   0: short-regex, all-files => many false positives
@@ -36,13 +37,50 @@ const CONTINENTS: &[&str] = &["Amerish", "Esamir", "Hossin", "Indar", "Oshur"];
 ///   2: (192288) standard-regex, file-filter
 ///   3: (192320) standard-regex + ignorecase, file-filter => slower
 ///   4: standard-regex + ignorecase, all-files => slowest
-pub fn extract_names(
+pub fn extract_names<R: Read + Seek>(
     pack: &Pack2,
-    br: &mut File,
+    br: &mut R,
     filesize_limit: u32,
     search_mode: usize,
     limit_to_files: Option<Vec<u64>>,
 ) -> Result<Vec<String>> {
+    extract_names_with(
+        pack,
+        |asset| Ok(Cow::Owned(asset.extract_bytes(br)?)),
+        filesize_limit,
+        search_mode,
+        limit_to_files,
+    )
+}
+
+/// same as `extract_names`, but reads the assets from the pack2 data (for example a mmap)
+/// without copying uncompressed assets
+pub fn extract_names_from_slice(
+    pack: &Pack2,
+    data: &[u8],
+    filesize_limit: u32,
+    search_mode: usize,
+    limit_to_files: Option<Vec<u64>>,
+) -> Result<Vec<String>> {
+    extract_names_with(
+        pack,
+        |asset| Ok(asset.extract_bytes_from_slice(data)?),
+        filesize_limit,
+        search_mode,
+        limit_to_files,
+    )
+}
+
+fn extract_names_with<'a, F>(
+    pack: &Pack2,
+    mut get_asset_data: F,
+    filesize_limit: u32,
+    search_mode: usize,
+    limit_to_files: Option<Vec<u64>>,
+) -> Result<Vec<String>>
+where
+    F: FnMut(&Asset) -> Result<Cow<'a, [u8]>>,
+{
     // TODO: it misses most / all .fsb files
     let filename_regex: Regex = Regex::new(FILENAME_REGEX_STRINGS[search_mode])
         .expect("Failed to compile filename_extractor filename_regex");
@@ -59,7 +97,7 @@ pub fn extract_names(
         if asset.unzipped_length > filesize_limit || asset.data_length > (filesize_limit as u64) {
             continue 'asset_loop;
         }
-        let asset_data: Vec<u8> = asset.extract_bytes(br)?;
+        let asset_data: Cow<[u8]> = get_asset_data(asset)?;
         if try_skip_binaries {
             if [0x00, 0xFF].contains(asset_data.first().unwrap_or(&0x00u8)) {
                 continue 'asset_loop;
@@ -149,7 +187,7 @@ pub fn extract_names(
 }
 
 /// find text patches in binary data (code contains strings, 3d-models have metadata, etc)
-fn find_text_patches(binary: &Vec<u8>) -> Vec<String> {
+fn find_text_patches(binary: &[u8]) -> Vec<String> {
     let mut output: Vec<String> = Vec::new();
     let mut buffer: String = String::new();
 
@@ -0,0 +1,407 @@
+use crate::bin_utils::{read_u32_be, read_u64_be, read_x_bytes, write_u32_be, CountingReader};
+use crate::pack2_manifest::{
+    diff_two_manifests, render_for_humans, write_count, write_string, Manifest, ManifestDiff,
+    ManifestDiffEntry, MAX_STRING_LENGTH,
+};
+use crate::pack_set::PackSet;
+use crate::Nups2Error;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// "N2IS", at the start of install snapshots
+pub const SNAPSHOT_MAGIC: u32 = 0x4E324953;
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The manifests of all pack2 files of a game install in one file.
+///
+/// File format (all numbers are big endian):
+///   u32 magic (`SNAPSHOT_MAGIC`)
+///   u32 version (1)
+///   u64 creation time (unix timestamp)
+///   u32 length + utf8 game build (empty if unknown)
+///   u32 pack count
+///   one v2 manifest per pack (see `pack2_manifest::read_manifest_file`),
+///     its pack name is the path relative to the install directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallSnapshot {
+    /// unix timestamp (seconds)
+    pub created: u64,
+    pub game_build: Option<String>,
+    /// in `PackSet` order, all with a header
+    pub packs: Vec<Manifest>,
+}
+
+/// An asset, which is no longer in one pack, but now is in another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovedAsset {
+    pub name_hash: u64,
+    pub old_pack: String,
+    pub new_pack: String,
+    pub old_data_hash: u32,
+    pub new_data_hash: u32,
+    /// uncompressed
+    pub old_length: Option<u64>,
+    pub new_length: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct InstallDiff {
+    pub added_packs: Vec<String>,
+    pub removed_packs: Vec<String>,
+    pub moved_assets: Vec<MovedAsset>,
+    /// (pack name, diff) for every pack with changes (not counting moves).
+    /// the assets of added/removed packs show up as created/deleted.
+    pub packs: Vec<(String, ManifestDiff)>,
+}
+
+impl InstallSnapshot {
+    pub fn from_pack_set(pack_set: &PackSet, game_build: Option<String>) -> Self {
+        let packs: Vec<Manifest> = pack_set
+            .packs
+            .iter()
+            .map(|entry| Manifest::from_pack2(&entry.pack2, &entry.name, game_build.clone()))
+            .collect();
+        Self {
+            created: packs
+                .first()
+                .and_then(|i| i.header.as_ref())
+                .map(|i| i.created)
+                .unwrap_or(0),
+            game_build,
+            packs,
+        }
+    }
+
+    pub fn pack_name(manifest: &Manifest) -> &str {
+        manifest
+            .header
+            .as_ref()
+            .map(|i| i.pack_name.as_str())
+            .unwrap_or("")
+    }
+
+    pub fn read(br: &mut dyn Read) -> Result<Self, Nups2Error> {
+        let mut br: CountingReader = CountingReader::new(br);
+        Self::internal_read(&mut br).map_err(|e| e.truncated_at(br.position()))
+    }
+
+    fn internal_read(br: &mut CountingReader) -> Result<Self, Nups2Error> {
+        let magic: u32 = read_u32_be(br)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(Nups2Error::BadMagic {
+                format: "install snapshot",
+                expected: SNAPSHOT_MAGIC,
+                found: magic,
+            });
+        }
+        let version: u32 = read_u32_be(br)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Nups2Error::UnsupportedVersion {
+                format: "install snapshot",
+                version,
+            });
+        }
+        let created: u64 = read_u64_be(br)?;
+        let game_build_length: u32 = read_u32_be(br)?;
+        if game_build_length > MAX_STRING_LENGTH {
+            return Err("install snapshot contains a too long game build".into());
+        }
+        let game_build: String = String::from_utf8(read_x_bytes(br, game_build_length as usize)?)?;
+        let pack_count: u32 = read_u32_be(br)?;
+        let mut packs: Vec<Manifest> = Vec::with_capacity(pack_count.min(0x1000) as usize);
+        for _ in 0..pack_count {
+            let manifest: Manifest = Manifest::read(br)?;
+            if manifest.header.is_none() {
+                return Err("install snapshot contains a manifest without header".into());
+            }
+            packs.push(manifest);
+        }
+        Ok(Self {
+            created,
+            game_build: Some(game_build).filter(|i| !i.is_empty()),
+            packs,
+        })
+    }
+
+    pub fn write(&self, bw: &mut dyn Write) -> std::io::Result<()> {
+        write_u32_be(SNAPSHOT_MAGIC, bw)?;
+        write_u32_be(SNAPSHOT_VERSION, bw)?;
+        bw.write_all(&self.created.to_be_bytes())?;
+        write_string(self.game_build.as_deref().unwrap_or(""), bw)?;
+        write_count(self.packs.len(), "packs", bw)?;
+        for manifest in self.packs.iter() {
+            if manifest.header.is_none() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "all manifests of a install snapshot need a header",
+                ));
+            }
+            manifest.write(bw)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_file(snapshot_file: &Path) -> Result<Self, Nups2Error> {
+        Self::read(&mut BufReader::new(File::open(snapshot_file)?))
+    }
+
+    pub fn write_to_file(&self, snapshot_file: &Path) -> std::io::Result<()> {
+        let mut bw: BufWriter<File> = BufWriter::new(File::create_new(snapshot_file)?);
+        self.write(&mut bw)?;
+        bw.flush()
+    }
+
+    /// what changed from `old` to `self`
+    pub fn diff(&self, old: &InstallSnapshot) -> InstallDiff {
+        let empty: Manifest = Manifest::default();
+        let old_packs: HashMap<&str, &Manifest> = old
+            .packs
+            .iter()
+            .map(|manifest| (Self::pack_name(manifest), manifest))
+            .collect();
+        let new_pack_names: HashSet<&str> = self.packs.iter().map(Self::pack_name).collect();
+
+        let mut result: InstallDiff = InstallDiff::default();
+        for manifest in self.packs.iter() {
+            let name: &str = Self::pack_name(manifest);
+            let old_manifest: &Manifest = match old_packs.get(name) {
+                Some(old_manifest) => old_manifest,
+                None => {
+                    result.added_packs.push(name.to_string());
+                    &empty
+                }
+            };
+            result
+                .packs
+                .push((name.to_string(), diff_two_manifests(old_manifest, manifest)));
+        }
+        for manifest in old.packs.iter() {
+            let name: &str = Self::pack_name(manifest);
+            if !new_pack_names.contains(name) {
+                result.removed_packs.push(name.to_string());
+                result
+                    .packs
+                    .push((name.to_string(), diff_two_manifests(manifest, &empty)));
+            }
+        }
+
+        // a deletion in one pack and a creation in another one is a move
+        let mut deleted: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+        let mut created: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+        for (pack_id, (_, diff)) in result.packs.iter().enumerate() {
+            for (entry_id, entry) in diff.iter().enumerate() {
+                match (entry.old_data_hash, entry.new_data_hash) {
+                    (Some(_), None) => deleted.entry(entry.name_hash).or_default(),
+                    (None, Some(_)) => created.entry(entry.name_hash).or_default(),
+                    _ => continue,
+                }
+                .push((pack_id, entry_id));
+            }
+        }
+        let mut moved: HashSet<(usize, usize)> = HashSet::new();
+        for (name_hash, deletions) in deleted.iter() {
+            let creations: &Vec<(usize, usize)> = match created.get(name_hash) {
+                Some(creations) => creations,
+                None => continue,
+            };
+            for (from, to) in deletions.iter().zip(creations.iter()) {
+                let old_entry: &ManifestDiffEntry = &result.packs[from.0].1[from.1];
+                let new_entry: &ManifestDiffEntry = &result.packs[to.0].1[to.1];
+                result.moved_assets.push(MovedAsset {
+                    name_hash: *name_hash,
+                    old_pack: result.packs[from.0].0.clone(),
+                    new_pack: result.packs[to.0].0.clone(),
+                    old_data_hash: old_entry.old_data_hash.unwrap(),
+                    new_data_hash: new_entry.new_data_hash.unwrap(),
+                    old_length: old_entry.old_length,
+                    new_length: new_entry.new_length,
+                });
+                moved.insert(*from);
+                moved.insert(*to);
+            }
+        }
+        result.moved_assets.sort_by(|a, b| {
+            (&a.old_pack, &a.new_pack, a.name_hash).cmp(&(&b.old_pack, &b.new_pack, b.name_hash))
+        });
+        for (pack_id, (_, diff)) in result.packs.iter_mut().enumerate() {
+            let mut entry_id: usize = 0;
+            diff.retain(|_| {
+                entry_id += 1;
+                !moved.contains(&(pack_id, entry_id - 1))
+            });
+        }
+        result.packs.retain(|(_, diff)| !diff.is_empty());
+        result
+    }
+}
+
+impl InstallDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_packs.is_empty()
+            && self.removed_packs.is_empty()
+            && self.moved_assets.is_empty()
+            && self.packs.is_empty()
+    }
+
+    pub fn render_for_humans(&self, name_hash_lookup_table: &HashMap<u64, String>) -> String {
+        let mut out: Vec<String> = Vec::new();
+        for pack in self.added_packs.iter() {
+            out.push(format!("Added pack: {pack}"));
+        }
+        for pack in self.removed_packs.iter() {
+            out.push(format!("Removed pack: {pack}"));
+        }
+        for moved in self.moved_assets.iter() {
+            out.push(format!(
+                "Moved file: 0x{:X} ({}): {} -> {}{}",
+                moved.name_hash,
+                name_hash_lookup_table
+                    .get(&moved.name_hash)
+                    .map(|i| i.as_str())
+                    .unwrap_or("?"),
+                moved.old_pack,
+                moved.new_pack,
+                if moved.old_data_hash != moved.new_data_hash {
+                    format!(
+                        " and changed 0x{:X} -> 0x{:X}",
+                        moved.old_data_hash, moved.new_data_hash
+                    )
+                } else {
+                    String::new()
+                }
+            ));
+        }
+        for (pack, diff) in self.packs.iter() {
+            out.push(format!(
+                "\n### {pack} ###\n{}",
+                render_for_humans(diff, name_hash_lookup_table)
+            ));
+        }
+        out.join("\n")
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, name_hash_lookup_table: &HashMap<u64, String>) -> String {
+        use crate::json_utils::escape_string;
+        use crate::pack2_manifest::classify_changes;
+
+        let name = |name_hash: u64| -> String {
+            name_hash_lookup_table
+                .get(&name_hash)
+                .map(|i| escape_string(i))
+                .unwrap_or(String::from("null"))
+        };
+        let or_null = |value: Option<u64>| -> String {
+            value.map(|i| i.to_string()).unwrap_or(String::from("null"))
+        };
+        let string_list = |list: &[String]| -> String {
+            list.iter()
+                .map(|i| escape_string(i))
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        format!(
+            r#"{{"added_packs":[{}],"removed_packs":[{}],"moved":[{}],"packs":[{}]}}"#,
+            string_list(&self.added_packs),
+            string_list(&self.removed_packs),
+            self.moved_assets
+                .iter()
+                .map(|moved| format!(
+                    r#"{{"name_hash":{},"name":{},"old_pack":{},"new_pack":{},"old_data_hash":{},"new_data_hash":{},"old_length":{},"new_length":{}}}"#,
+                    moved.name_hash,
+                    name(moved.name_hash),
+                    escape_string(&moved.old_pack),
+                    escape_string(&moved.new_pack),
+                    moved.old_data_hash,
+                    moved.new_data_hash,
+                    or_null(moved.old_length),
+                    or_null(moved.new_length),
+                ))
+                .collect::<Vec<String>>()
+                .join(","),
+            self.packs
+                .iter()
+                .map(|(pack, diff)| format!(
+                    r#"{{"pack":{},"changes":[{}]}}"#,
+                    escape_string(pack),
+                    classify_changes(diff)
+                        .iter()
+                        .map(|change| change.to_json(name_hash_lookup_table))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                ))
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InstallDiff, InstallSnapshot, MovedAsset};
+    use crate::pack2_manifest::{Manifest, ManifestHeader, ManifestRecord, MANIFEST_VERSION};
+
+    fn manifest(pack_name: &str, records: Vec<(u64, u32)>) -> Manifest {
+        Manifest {
+            header: Some(ManifestHeader {
+                version: MANIFEST_VERSION,
+                pack_name: pack_name.to_string(),
+                created: 0,
+                game_build: None,
+            }),
+            records: Manifest::from(records)
+                .records
+                .into_iter()
+                .map(|record| ManifestRecord {
+                    uncompressed_length: Some(10),
+                    compressed_length: Some(10),
+                    flags: Some(0),
+                    ..record
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn snapshot_diff() {
+        let old: InstallSnapshot = InstallSnapshot {
+            created: 1,
+            game_build: Some(String::from("old")),
+            packs: vec![
+                manifest("a.pack2", vec![(1, 0xA), (2, 0xB)]),
+                manifest("gone.pack2", vec![(3, 0xC)]),
+            ],
+        };
+        let new: InstallSnapshot = InstallSnapshot {
+            created: 2,
+            game_build: None,
+            packs: vec![
+                manifest("a.pack2", vec![(1, 0xA)]),
+                manifest("b.pack2", vec![(2, 0xB), (3, 0xCC), (4, 0xD)]),
+            ],
+        };
+        let mut data: Vec<u8> = Vec::new();
+        old.write(&mut data).unwrap();
+        assert_eq!(InstallSnapshot::read(&mut &data[..]).unwrap(), old);
+
+        let diff: InstallDiff = new.diff(&old);
+        assert_eq!(diff.added_packs, vec!["b.pack2"]);
+        assert_eq!(diff.removed_packs, vec!["gone.pack2"]);
+        assert_eq!(
+            diff.moved_assets
+                .iter()
+                .map(|i| (i.name_hash, i.old_pack.as_str(), i.new_pack.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(2, "a.pack2", "b.pack2"), (3, "gone.pack2", "b.pack2")]
+        );
+        let moved: &MovedAsset = &diff.moved_assets[1];
+        assert_eq!((moved.old_data_hash, moved.new_data_hash), (0xC, 0xCC));
+        // only the really new asset is left
+        assert_eq!(diff.packs.len(), 1);
+        assert_eq!(diff.packs[0].0, "b.pack2");
+        assert_eq!(diff.packs[0].1.len(), 1);
+        assert_eq!(diff.packs[0].1[0].name_hash, 4);
+    }
+}
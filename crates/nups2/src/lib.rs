@@ -1,5 +1,7 @@
 #[cfg(feature = "adr")]
 pub mod adr;
+#[cfg(feature = "asset_filter")]
+pub mod asset_filter;
 pub mod bin_utils;
 #[cfg(feature = "cli")]
 pub mod cli;
@@ -9,17 +11,34 @@ pub mod crc64;
 pub mod dma;
 #[cfg(feature = "dme")]
 pub mod dme;
+pub mod duplicates;
 mod error;
+pub mod extract_utils;
 #[cfg(feature = "filename_scraper")]
 pub mod filename_extractor;
+#[cfg(feature = "manifests")]
+pub mod install_snapshot;
 pub mod json_utils;
+#[cfg(feature = "manifests")]
+pub mod manifest_history;
 #[cfg(feature = "pack1")]
 pub mod pack1;
 pub mod pack2;
+#[cfg(feature = "export")]
+pub mod pack2_export;
 #[cfg(feature = "manifests")]
 pub mod pack2_manifest;
+#[cfg(feature = "mmap")]
+pub mod pack2_mmap;
+pub mod pack2_patch;
+pub mod pack2_stats;
+pub mod pack2_writer;
+pub mod pack_set;
+#[cfg(all(feature = "manifests", feature = "filename_scraper"))]
+pub mod patch_notes;
 #[cfg(feature = "rainbow_table")]
 pub mod rainbow_table;
+pub mod sniff;
 pub use error::Nups2Error;
 
 //pub mod jenkins_hash;
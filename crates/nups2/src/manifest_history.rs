@@ -0,0 +1,854 @@
+use crate::bin_utils::{read_u32_be, read_u64_be, read_u8_be, write_u32_be, CountingReader};
+use crate::cli_utils::{format_unix_timestamp, humanise_bytes, parse_timestamp};
+use crate::install_snapshot::InstallSnapshot;
+use crate::pack2_manifest::{read_string, write_count, write_string, Manifest, ManifestChangeKind};
+use crate::Nups2Error;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// "N2MH", at the start of manifest history files
+pub const HISTORY_MAGIC: u32 = 0x4E324D48;
+pub const HISTORY_VERSION: u32 = 1;
+
+const STATE_DELETED: u8 = 0;
+const STATE_PRESENT: u8 = 1;
+const STATE_PRESENT_WITH_LENGTH: u8 = 2;
+
+/// Every version of every asset from many manifests and install snapshots taken over time.
+///
+/// Only changes are stored: an asset gets a new version when it shows up in a pack,
+/// when its data_hash changes and when it disappears from the pack.
+/// The revisions of a pack have to be added in chronological order.
+///
+/// File format (all numbers are big endian):
+///   u32 magic (`HISTORY_MAGIC`)
+///   u32 version (1)
+///   u32 pack count
+///     u32 length + utf8 pack name
+///   u32 revision count
+///     u64 timestamp (unix), u32 length + utf8 game build (empty if unknown),
+///     u32 count + u32 index of every pack in the revision
+///   u32 asset count (sorted by name_hash, then pack index)
+///     u64 name_hash, u32 pack index, u32 version count
+///     17 bytes per version:
+///       u32 revision index, u8 state (0 deleted, 1 present, 2 present with length),
+///       u32 data_hash, u64 uncompressed length
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestHistory {
+    pub packs: Vec<String>,
+    pub revisions: Vec<HistoryRevision>,
+    /// (name_hash, pack index) -> versions, oldest first
+    assets: BTreeMap<(u64, u32), Vec<AssetVersion>>,
+}
+
+/// One manifest or install snapshot (or a group of manifests taken at the same time)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryRevision {
+    /// unix timestamp (seconds)
+    pub timestamp: u64,
+    pub game_build: Option<String>,
+    /// indices into `ManifestHistory::packs`
+    pub packs: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetVersion {
+    /// index into `ManifestHistory::revisions`
+    pub revision: u32,
+    /// `None` if the asset was deleted in this revision
+    pub data_hash: Option<u32>,
+    /// uncompressed, `None` if unknown (v1 manifests)
+    pub length: Option<u64>,
+}
+
+/// A version of an asset with its context (result of the queries)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub name_hash: u64,
+    pub pack: String,
+    pub timestamp: u64,
+    pub game_build: Option<String>,
+    /// `Created`, `Changed` or `Deleted`
+    pub kind: ManifestChangeKind,
+    /// of the previous version
+    pub old_data_hash: Option<u32>,
+    pub data_hash: Option<u32>,
+    pub length: Option<u64>,
+}
+
+impl ManifestHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// number of (name_hash, pack) pairs ever seen
+    pub fn asset_count(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn version_count(&self) -> usize {
+        self.assets.values().map(|i| i.len()).sum()
+    }
+
+    fn pack_index(&mut self, pack_name: &str) -> u32 {
+        match self.packs.iter().position(|i| i == pack_name) {
+            Some(index) => index as u32,
+            None => {
+                self.packs.push(pack_name.to_string());
+                self.packs.len() as u32 - 1
+            }
+        }
+    }
+
+    /// timestamp of the newest revision containing `pack`
+    fn last_timestamp(&self, pack: u32) -> Option<u64> {
+        self.revisions
+            .iter()
+            .filter(|revision| revision.packs.contains(&pack))
+            .map(|revision| revision.timestamp)
+            .max()
+    }
+
+    /// add the state of some packs at `timestamp`.
+    /// packs, which are not part of `manifests`, are left as they are.
+    /// returns the number of new versions (0 if nothing changed, the revision is still recorded).
+    pub fn add_revision(
+        &mut self,
+        timestamp: u64,
+        game_build: Option<String>,
+        manifests: &[(&str, &Manifest)],
+    ) -> Result<usize, Nups2Error> {
+        // check everything before changing anything
+        for (index, (pack_name, _)) in manifests.iter().enumerate() {
+            if manifests[..index].iter().any(|(i, _)| i == pack_name) {
+                return Err(format!("{pack_name} is more than once in the same revision").into());
+            }
+            if let Some(last_timestamp) = self
+                .packs
+                .iter()
+                .position(|i| i == pack_name)
+                .and_then(|pack| self.last_timestamp(pack as u32))
+                .filter(|i| *i >= timestamp)
+            {
+                return Err(format!(
+                    "{pack_name} already has a revision from {}, revisions have to be added in chronological order",
+                    format_unix_timestamp(last_timestamp)
+                )
+                .into());
+            }
+        }
+        let packs: Vec<u32> = manifests
+            .iter()
+            .map(|(pack_name, _)| self.pack_index(pack_name))
+            .collect();
+        let revision: u32 = self.revisions.len() as u32;
+
+        // name_hash -> data_hash of the current (present) assets of each pack
+        let mut current: HashMap<u32, HashMap<u64, u32>> =
+            packs.iter().map(|pack| (*pack, HashMap::new())).collect();
+        for ((name_hash, pack), versions) in self.assets.iter() {
+            if let (Some(assets), Some(data_hash)) = (
+                current.get_mut(pack),
+                versions.last().and_then(|i| i.data_hash),
+            ) {
+                assets.insert(*name_hash, data_hash);
+            }
+        }
+
+        let mut new_versions: usize = 0;
+        for (pack, (_, manifest)) in packs.iter().zip(manifests.iter()) {
+            let mut old_assets: HashMap<u64, u32> = current.remove(pack).unwrap_or_default();
+            let mut seen: HashSet<u64> = HashSet::with_capacity(manifest.records.len());
+            for record in manifest.records.iter() {
+                // same as diff_two_manifests, the first record of a name_hash wins
+                if !seen.insert(record.name_hash) {
+                    continue;
+                }
+                if old_assets.remove(&record.name_hash) == Some(record.data_hash) {
+                    continue;
+                }
+                self.assets
+                    .entry((record.name_hash, *pack))
+                    .or_default()
+                    .push(AssetVersion {
+                        revision,
+                        data_hash: Some(record.data_hash),
+                        length: record.uncompressed_length,
+                    });
+                new_versions += 1;
+            }
+            for name_hash in old_assets.into_keys() {
+                self.assets
+                    .entry((name_hash, *pack))
+                    .or_default()
+                    .push(AssetVersion {
+                        revision,
+                        data_hash: None,
+                        length: None,
+                    });
+                new_versions += 1;
+            }
+        }
+        self.revisions.push(HistoryRevision {
+            timestamp,
+            game_build,
+            packs,
+        });
+        Ok(new_versions)
+    }
+
+    /// see `add_revision`
+    pub fn add_manifest(
+        &mut self,
+        manifest: &Manifest,
+        pack_name: &str,
+        timestamp: u64,
+        game_build: Option<String>,
+    ) -> Result<usize, Nups2Error> {
+        self.add_revision(timestamp, game_build, &[(pack_name, manifest)])
+    }
+
+    /// a snapshot covers the whole install,
+    /// so the assets of known packs, which are not part of it, are deleted
+    pub fn add_snapshot(&mut self, snapshot: &InstallSnapshot) -> Result<usize, Nups2Error> {
+        let empty: Manifest = Manifest::default();
+        let mut manifests: Vec<(&str, &Manifest)> = snapshot
+            .packs
+            .iter()
+            .map(|manifest| (InstallSnapshot::pack_name(manifest), manifest))
+            .collect();
+        let mut removed_packs: HashSet<u32> = HashSet::new();
+        for ((_, pack), versions) in self.assets.iter() {
+            if versions.last().is_some_and(|i| i.data_hash.is_some()) {
+                removed_packs.insert(*pack);
+            }
+        }
+        for (pack, pack_name) in self.packs.iter().enumerate() {
+            if removed_packs.contains(&(pack as u32))
+                && !manifests.iter().any(|(name, _)| name == pack_name)
+            {
+                manifests.push((pack_name, &empty));
+            }
+        }
+        let manifests: Vec<(String, &Manifest)> = manifests
+            .into_iter()
+            .map(|(name, manifest)| (name.to_string(), manifest))
+            .collect();
+        self.add_revision(
+            snapshot.created,
+            snapshot.game_build.clone(),
+            &manifests
+                .iter()
+                .map(|(name, manifest)| (name.as_str(), *manifest))
+                .collect::<Vec<(&str, &Manifest)>>(),
+        )
+    }
+
+    /// Adds manifests and install snapshots (see `read_manifest_or_snapshot`), oldest first.
+    /// Manifests with the same timestamp become one revision.
+    /// `date` and `game_build` replace the ones from the files,
+    /// v1 manifests and manifest directories have no date and otherwise need a `YYYY-MM-DD..` directory name.
+    pub fn add_files(
+        &mut self,
+        files: &[PathBuf],
+        date: Option<u64>,
+        game_build: Option<String>,
+    ) -> Result<usize, Nups2Error> {
+        let mut sources: Vec<(u64, Option<String>, ManifestOrSnapshot)> = files
+            .iter()
+            .map(|path| read_dated(path, date, game_build.clone()))
+            .collect::<Result<Vec<(u64, Option<String>, ManifestOrSnapshot)>, Nups2Error>>()?;
+        sources.sort_by_key(|i| i.0);
+        let mut new_versions: usize = 0;
+        let mut sources = sources.into_iter().peekable();
+        while let Some((timestamp, game_build, source)) = sources.next() {
+            match source {
+                ManifestOrSnapshot::Snapshot(snapshot) => {
+                    new_versions += self.add_snapshot(&snapshot)?;
+                }
+                ManifestOrSnapshot::Manifest(pack_name, manifest) => {
+                    let mut group: Vec<(String, Manifest)> = vec![(pack_name, manifest)];
+                    while let Some((_, _, ManifestOrSnapshot::Manifest(pack_name, manifest))) =
+                        sources.next_if(|i| {
+                            i.0 == timestamp && matches!(i.2, ManifestOrSnapshot::Manifest(..))
+                        })
+                    {
+                        group.push((pack_name, manifest));
+                    }
+                    new_versions += self.add_revision(
+                        timestamp,
+                        game_build,
+                        &group
+                            .iter()
+                            .map(|(name, manifest)| (name.as_str(), manifest))
+                            .collect::<Vec<(&str, &Manifest)>>(),
+                    )?;
+                }
+            }
+        }
+        Ok(new_versions)
+    }
+
+    fn entry(
+        &self,
+        name_hash: u64,
+        pack: u32,
+        versions: &[AssetVersion],
+        index: usize,
+    ) -> HistoryEntry {
+        let version: &AssetVersion = &versions[index];
+        let previous: Option<&AssetVersion> = index.checked_sub(1).map(|i| &versions[i]);
+        let old_data_hash: Option<u32> = previous.and_then(|i| i.data_hash);
+        let revision: &HistoryRevision = &self.revisions[version.revision as usize];
+        HistoryEntry {
+            name_hash,
+            pack: self.packs[pack as usize].clone(),
+            timestamp: revision.timestamp,
+            game_build: revision.game_build.clone(),
+            kind: match (old_data_hash, version.data_hash) {
+                (_, None) => ManifestChangeKind::Deleted,
+                (None, Some(_)) => ManifestChangeKind::Created,
+                (Some(_), Some(_)) => ManifestChangeKind::Changed,
+            },
+            old_data_hash,
+            data_hash: version.data_hash,
+            length: version.length,
+        }
+    }
+
+    /// every version of `name_hash` in every pack, oldest first
+    pub fn versions(&self, name_hash: u64) -> Vec<HistoryEntry> {
+        let mut result: Vec<HistoryEntry> = Vec::new();
+        for ((name_hash, pack), versions) in
+            self.assets.range((name_hash, 0)..=(name_hash, u32::MAX))
+        {
+            for index in 0..versions.len() {
+                result.push(self.entry(*name_hash, *pack, versions, index));
+            }
+        }
+        sort_entries(&mut result);
+        result
+    }
+
+    /// every change after `from` up to (including) `to`, oldest first
+    pub fn changes_between(&self, from: u64, to: u64) -> Vec<HistoryEntry> {
+        let mut result: Vec<HistoryEntry> = Vec::new();
+        for ((name_hash, pack), versions) in self.assets.iter() {
+            for (index, version) in versions.iter().enumerate() {
+                let timestamp: u64 = self.revisions[version.revision as usize].timestamp;
+                if timestamp > from && timestamp <= to {
+                    result.push(self.entry(*name_hash, *pack, versions, index));
+                }
+            }
+        }
+        sort_entries(&mut result);
+        result
+    }
+
+    /// the current version of every asset, which still exists and did not change after `timestamp`
+    /// (oldest first). assets, which were already there in the first revision of their pack,
+    /// can be older than they look.
+    pub fn unchanged_since(&self, timestamp: u64) -> Vec<HistoryEntry> {
+        let mut result: Vec<HistoryEntry> = Vec::new();
+        for ((name_hash, pack), versions) in self.assets.iter() {
+            let last: &AssetVersion = match versions.last() {
+                Some(last) if last.data_hash.is_some() => last,
+                _ => continue,
+            };
+            if self.revisions[last.revision as usize].timestamp <= timestamp {
+                result.push(self.entry(*name_hash, *pack, versions, versions.len() - 1));
+            }
+        }
+        sort_entries(&mut result);
+        result
+    }
+
+    pub fn read(br: &mut dyn Read) -> Result<Self, Nups2Error> {
+        let mut br: CountingReader = CountingReader::new(br);
+        Self::internal_read(&mut br).map_err(|e| e.truncated_at(br.position()))
+    }
+
+    fn internal_read(br: &mut CountingReader) -> Result<Self, Nups2Error> {
+        let magic: u32 = read_u32_be(br)?;
+        if magic != HISTORY_MAGIC {
+            return Err(Nups2Error::BadMagic {
+                format: "manifest history",
+                expected: HISTORY_MAGIC,
+                found: magic,
+            });
+        }
+        let version: u32 = read_u32_be(br)?;
+        if version != HISTORY_VERSION {
+            return Err(Nups2Error::UnsupportedVersion {
+                format: "manifest history",
+                version,
+            });
+        }
+        // don't trust the counts for the allocations, the file might be truncated
+        let pack_count: u32 = read_u32_be(br)?;
+        let mut packs: Vec<String> = Vec::with_capacity(pack_count.min(0x1000) as usize);
+        for _ in 0..pack_count {
+            packs.push(read_string(br)?);
+        }
+        let revision_count: u32 = read_u32_be(br)?;
+        let mut revisions: Vec<HistoryRevision> =
+            Vec::with_capacity(revision_count.min(0x1000) as usize);
+        for _ in 0..revision_count {
+            let timestamp: u64 = read_u64_be(br)?;
+            let game_build: String = read_string(br)?;
+            let revision_pack_count: u32 = read_u32_be(br)?;
+            let mut revision_packs: Vec<u32> =
+                Vec::with_capacity(revision_pack_count.min(0x1000) as usize);
+            for _ in 0..revision_pack_count {
+                let pack: u32 = read_u32_be(br)?;
+                if pack >= pack_count {
+                    return Err(format!("manifest history references unknown pack {pack}").into());
+                }
+                revision_packs.push(pack);
+            }
+            revisions.push(HistoryRevision {
+                timestamp,
+                game_build: Some(game_build).filter(|i| !i.is_empty()),
+                packs: revision_packs,
+            });
+        }
+        let asset_count: u32 = read_u32_be(br)?;
+        let mut assets: BTreeMap<(u64, u32), Vec<AssetVersion>> = BTreeMap::new();
+        let mut record: [u8; 17] = [0; 17];
+        for _ in 0..asset_count {
+            let name_hash: u64 = read_u64_be(br)?;
+            let pack: u32 = read_u32_be(br)?;
+            if pack >= pack_count {
+                return Err(format!("manifest history references unknown pack {pack}").into());
+            }
+            let version_count: u32 = read_u32_be(br)?;
+            let mut versions: Vec<AssetVersion> =
+                Vec::with_capacity(version_count.min(0x1000) as usize);
+            for _ in 0..version_count {
+                br.read_exact(&mut record)?;
+                let mut record: &[u8] = &record;
+                let revision: u32 = read_u32_be(&mut record)?;
+                if revision >= revision_count {
+                    return Err(
+                        format!("manifest history references unknown revision {revision}").into(),
+                    );
+                }
+                let state: u8 = read_u8_be(&mut record)?;
+                let data_hash: u32 = read_u32_be(&mut record)?;
+                let length: u64 = read_u64_be(&mut record)?;
+                versions.push(match state {
+                    STATE_DELETED => AssetVersion {
+                        revision,
+                        data_hash: None,
+                        length: None,
+                    },
+                    STATE_PRESENT | STATE_PRESENT_WITH_LENGTH => AssetVersion {
+                        revision,
+                        data_hash: Some(data_hash),
+                        length: Some(length).filter(|_| state == STATE_PRESENT_WITH_LENGTH),
+                    },
+                    _ => {
+                        return Err(Nups2Error::InvalidEnum {
+                            name: "manifest history version state",
+                            value: state as u32,
+                        })
+                    }
+                });
+            }
+            assets.insert((name_hash, pack), versions);
+        }
+        Ok(Self {
+            packs,
+            revisions,
+            assets,
+        })
+    }
+
+    pub fn write(&self, bw: &mut dyn Write) -> std::io::Result<()> {
+        write_u32_be(HISTORY_MAGIC, bw)?;
+        write_u32_be(HISTORY_VERSION, bw)?;
+        write_count(self.packs.len(), "packs", bw)?;
+        for pack in self.packs.iter() {
+            write_string(pack, bw)?;
+        }
+        write_count(self.revisions.len(), "revisions", bw)?;
+        for revision in self.revisions.iter() {
+            bw.write_all(&revision.timestamp.to_be_bytes())?;
+            write_string(revision.game_build.as_deref().unwrap_or(""), bw)?;
+            write_count(revision.packs.len(), "packs in a revision", bw)?;
+            for pack in revision.packs.iter() {
+                write_u32_be(*pack, bw)?;
+            }
+        }
+        write_count(self.assets.len(), "assets", bw)?;
+        for ((name_hash, pack), versions) in self.assets.iter() {
+            bw.write_all(&name_hash.to_be_bytes())?;
+            write_u32_be(*pack, bw)?;
+            write_count(versions.len(), "versions of an asset", bw)?;
+            for version in versions.iter() {
+                write_u32_be(version.revision, bw)?;
+                bw.write_all(&[match (version.data_hash, version.length) {
+                    (None, _) => STATE_DELETED,
+                    (Some(_), None) => STATE_PRESENT,
+                    (Some(_), Some(_)) => STATE_PRESENT_WITH_LENGTH,
+                }])?;
+                write_u32_be(version.data_hash.unwrap_or(0), bw)?;
+                bw.write_all(&version.length.unwrap_or(0).to_be_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_file(history_file: &Path) -> Result<Self, Nups2Error> {
+        Self::read(&mut BufReader::new(File::open(history_file)?))
+    }
+
+    /// replaces `history_file` (through a temporary file next to it, so it is never half written)
+    pub fn write_to_file(&self, history_file: &Path) -> std::io::Result<()> {
+        let mut tmp_file: PathBuf = history_file.to_path_buf();
+        tmp_file.as_mut_os_string().push(".tmp");
+        let mut bw: BufWriter<File> = BufWriter::new(File::create(&tmp_file)?);
+        self.write(&mut bw)?;
+        bw.flush()?;
+        drop(bw);
+        std::fs::rename(&tmp_file, history_file)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        use crate::json_utils::escape_string;
+
+        format!(
+            r#"{{"packs":{},"assets":{},"versions":{},"revisions":[{}]}}"#,
+            self.packs.len(),
+            self.asset_count(),
+            self.version_count(),
+            self.revisions
+                .iter()
+                .map(|revision| format!(
+                    r#"{{"timestamp":{},"game_build":{},"packs":[{}]}}"#,
+                    revision.timestamp,
+                    revision
+                        .game_build
+                        .as_deref()
+                        .map(escape_string)
+                        .unwrap_or(String::from("null")),
+                    revision
+                        .packs
+                        .iter()
+                        .map(|pack| escape_string(&self.packs[*pack as usize]))
+                        .collect::<Vec<String>>()
+                        .join(",")
+                ))
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        let mut revisions: Vec<&HistoryRevision> = self.revisions.iter().collect();
+        revisions.sort_by_key(|revision| revision.timestamp);
+        let mut lines: Vec<String> = vec![format!(
+            "Manifest history\n  packs: {}\n  assets: {}\n  versions: {}\n  revisions: {}",
+            self.packs.len(),
+            self.asset_count(),
+            self.version_count(),
+            self.revisions.len()
+        )];
+        for revision in revisions {
+            lines.push(format!(
+                "    {} ({}): {} packs",
+                format_unix_timestamp(revision.timestamp),
+                revision.game_build.as_deref().unwrap_or("unknown build"),
+                revision.packs.len()
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+impl HistoryEntry {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, name_hash_lookup_table: &HashMap<u64, String>) -> String {
+        use crate::json_utils::escape_string;
+
+        fn or_null<T: ToString>(value: Option<T>) -> String {
+            value.map(|i| i.to_string()).unwrap_or(String::from("null"))
+        }
+        format!(
+            r#"{{"timestamp":{},"date":"{}","game_build":{},"pack":{},"name_hash":{},"name":{},"kind":"{}","old_data_hash":{},"data_hash":{},"length":{}}}"#,
+            self.timestamp,
+            format_unix_timestamp(self.timestamp),
+            self.game_build
+                .as_deref()
+                .map(escape_string)
+                .unwrap_or(String::from("null")),
+            escape_string(&self.pack),
+            self.name_hash,
+            name_hash_lookup_table
+                .get(&self.name_hash)
+                .map(|i| escape_string(i))
+                .unwrap_or(String::from("null")),
+            self.kind.as_str(),
+            or_null(self.old_data_hash),
+            or_null(self.data_hash),
+            or_null(self.length),
+        )
+    }
+}
+
+#[cfg(feature = "json")]
+pub fn entries_to_json(
+    entries: &[HistoryEntry],
+    name_hash_lookup_table: &HashMap<u64, String>,
+) -> String {
+    format!(
+        "[{}]",
+        entries
+            .iter()
+            .map(|i| i.to_json(name_hash_lookup_table))
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+}
+
+pub fn render_entries_for_humans(
+    entries: &[HistoryEntry],
+    name_hash_lookup_table: &HashMap<u64, String>,
+) -> String {
+    #[cfg(feature = "use_comfy_table")]
+    let mut table = comfy_table::Table::new();
+    #[cfg(feature = "use_comfy_table")]
+    table.set_header(vec![
+        "Date",
+        "Build",
+        "Pack",
+        "Name",
+        "Change",
+        "Data hash",
+        "Size",
+    ]);
+
+    #[cfg(not(feature = "use_comfy_table"))]
+    let mut out: Vec<String> = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let columns: Vec<String> = vec![
+            format_unix_timestamp(entry.timestamp),
+            entry.game_build.clone().unwrap_or_default(),
+            entry.pack.clone(),
+            match name_hash_lookup_table.get(&entry.name_hash) {
+                Some(name) => name.clone(),
+                None => format!("crc64({})", entry.name_hash),
+            },
+            entry.kind.as_str().to_string(),
+            match (entry.old_data_hash, entry.data_hash) {
+                (Some(old), Some(new)) => format!("0x{old:08X} -> 0x{new:08X}"),
+                (None, Some(new)) => format!("0x{new:08X}"),
+                (Some(old), None) => format!("0x{old:08X} -> -"),
+                (None, None) => String::new(),
+            },
+            entry
+                .length
+                .map(|i| humanise_bytes(i as f64))
+                .unwrap_or_default(),
+        ];
+
+        #[cfg(feature = "use_comfy_table")]
+        table.add_row(columns);
+        #[cfg(not(feature = "use_comfy_table"))]
+        out.push(columns.join(" "));
+    }
+
+    #[cfg(feature = "use_comfy_table")]
+    return table.to_string();
+    #[cfg(not(feature = "use_comfy_table"))]
+    return out.join("\n");
+}
+
+/// Result of `read_manifest_or_snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestOrSnapshot {
+    /// (pack name, manifest)
+    Manifest(String, Manifest),
+    Snapshot(InstallSnapshot),
+}
+
+/// A manifest or a install snapshot (told apart by the magic),
+/// a directory with `*.manifest` files is read as one snapshot (`created` 0, v1 manifests get a header).
+pub fn read_manifest_or_snapshot(path: &Path) -> Result<ManifestOrSnapshot, Nups2Error> {
+    use crate::install_snapshot::SNAPSHOT_MAGIC;
+    use crate::pack2_manifest::ManifestHeader;
+
+    if path.is_dir() {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|i| i.path()))
+            .collect::<std::io::Result<Vec<PathBuf>>>()?;
+        paths.retain(|i| i.extension().is_some_and(|i| i == "manifest"));
+        paths.sort();
+        let mut packs: Vec<Manifest> = Vec::with_capacity(paths.len());
+        for manifest_path in paths {
+            let mut manifest: Manifest = crate::pack2_manifest::read_manifest_file(&manifest_path)
+                .map_err(|e| format!("{}: {e}", manifest_path.display()))?;
+            if manifest.header.is_none() {
+                manifest.header = Some(ManifestHeader {
+                    version: 1,
+                    pack_name: manifest_pack_name(&manifest, &manifest_path),
+                    created: 0,
+                    game_build: None,
+                });
+            }
+            packs.push(manifest);
+        }
+        return Ok(ManifestOrSnapshot::Snapshot(InstallSnapshot {
+            created: 0,
+            game_build: None,
+            packs,
+        }));
+    }
+    let mut br: BufReader<File> = BufReader::new(File::open(path)?);
+    if br.fill_buf()?.starts_with(&SNAPSHOT_MAGIC.to_be_bytes()) {
+        return Ok(ManifestOrSnapshot::Snapshot(
+            InstallSnapshot::read(&mut br).map_err(|e| format!("{}: {e}", path.display()))?,
+        ));
+    }
+    let manifest: Manifest =
+        Manifest::read(&mut br).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(ManifestOrSnapshot::Manifest(
+        manifest_pack_name(&manifest, path),
+        manifest,
+    ))
+}
+
+/// the pack name from the header of v2 manifests, the filename without `.manifest` for v1
+fn manifest_pack_name(manifest: &Manifest, path: &Path) -> String {
+    if let Some(ref header) = manifest.header {
+        return header.pack_name.clone();
+    }
+    let filename: String = path
+        .file_name()
+        .map(|i| i.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    filename
+        .strip_suffix(".manifest")
+        .unwrap_or(&filename)
+        .to_string()
+}
+
+/// `read_manifest_or_snapshot` + (timestamp, game build) for `add_files`
+fn read_dated(
+    path: &Path,
+    date: Option<u64>,
+    game_build: Option<String>,
+) -> Result<(u64, Option<String>, ManifestOrSnapshot), Nups2Error> {
+    let date_from_name = |dir: Option<&Path>| -> Option<u64> {
+        parse_timestamp(&dir?.file_name()?.to_string_lossy())
+    };
+    let source: ManifestOrSnapshot = read_manifest_or_snapshot(path)?;
+    let (timestamp, game_build): (Option<u64>, Option<String>) = match source {
+        ManifestOrSnapshot::Snapshot(_) if path.is_dir() => {
+            (date.or_else(|| date_from_name(Some(path))), game_build)
+        }
+        ManifestOrSnapshot::Snapshot(ref snapshot) => (
+            date.or(Some(snapshot.created)),
+            game_build.or(snapshot.game_build.clone()),
+        ),
+        ManifestOrSnapshot::Manifest(_, ref manifest) => match manifest.header {
+            Some(ref header) => (
+                date.or(Some(header.created)),
+                game_build.or(header.game_build.clone()),
+            ),
+            None => (date.or_else(|| date_from_name(path.parent())), game_build),
+        },
+    };
+    let timestamp: u64 = timestamp.ok_or_else(|| {
+        format!(
+            "{}: v1 manifests and manifest directories contain no date, pass one or put them in a YYYY-MM-DD directory",
+            path.display()
+        )
+    })?;
+    let source: ManifestOrSnapshot = match source {
+        ManifestOrSnapshot::Snapshot(mut snapshot) => {
+            snapshot.created = timestamp;
+            snapshot.game_build = game_build.clone();
+            ManifestOrSnapshot::Snapshot(snapshot)
+        }
+        manifest => manifest,
+    };
+    Ok((timestamp, game_build, source))
+}
+
+fn sort_entries(entries: &mut [HistoryEntry]) {
+    entries.sort_by(|a, b| {
+        (a.timestamp, &a.pack, a.name_hash).cmp(&(b.timestamp, &b.pack, b.name_hash))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryEntry, ManifestHistory};
+    use crate::install_snapshot::InstallSnapshot;
+    use crate::pack2_manifest::{Manifest, ManifestChangeKind};
+
+    #[test]
+    fn history_queries() {
+        let day: u64 = 86400;
+        let mut history: ManifestHistory = ManifestHistory::new();
+        let v1: Manifest = Manifest::from(vec![(1, 0xA), (2, 0xB)]);
+        history.add_manifest(&v1, "a.pack2", day, None).unwrap();
+        let v2: Manifest = Manifest::from(vec![(1, 0xAA), (2, 0xB), (3, 0xC)]);
+        assert_eq!(
+            history.add_manifest(&v2, "a.pack2", 2 * day, None).unwrap(),
+            2
+        );
+        // not in chronological order
+        assert!(history.add_manifest(&v1, "a.pack2", day, None).is_err());
+        // a.pack2 is gone, 3 moved to b.pack2
+        let mut snapshot: InstallSnapshot = InstallSnapshot {
+            created: 3 * day,
+            game_build: Some(String::from("build 3")),
+            packs: vec![Manifest::from(vec![(3, 0xC)])],
+        };
+        snapshot.packs[0].header = Some(crate::pack2_manifest::ManifestHeader {
+            version: crate::pack2_manifest::MANIFEST_VERSION,
+            pack_name: String::from("b.pack2"),
+            created: 3 * day,
+            game_build: None,
+        });
+        assert_eq!(history.add_snapshot(&snapshot).unwrap(), 4);
+
+        let mut data: Vec<u8> = Vec::new();
+        history.write(&mut data).unwrap();
+        assert_eq!(ManifestHistory::read(&mut &data[..]).unwrap(), history);
+
+        let versions: Vec<HistoryEntry> = history.versions(1);
+        assert_eq!(
+            versions
+                .iter()
+                .map(|i| (i.timestamp / day, i.kind, i.data_hash))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, ManifestChangeKind::Created, Some(0xA)),
+                (2, ManifestChangeKind::Changed, Some(0xAA)),
+                (3, ManifestChangeKind::Deleted, None),
+            ]
+        );
+        assert_eq!(versions[1].old_data_hash, Some(0xA));
+
+        let changes: Vec<HistoryEntry> = history.changes_between(day, 2 * day);
+        assert_eq!(
+            changes.iter().map(|i| i.name_hash).collect::<Vec<u64>>(),
+            vec![1, 3]
+        );
+
+        let unchanged: Vec<HistoryEntry> = history.unchanged_since(3 * day);
+        assert_eq!(unchanged.len(), 1);
+        assert_eq!(
+            (unchanged[0].name_hash, unchanged[0].pack.as_str()),
+            (3, "b.pack2")
+        );
+        assert!(history.unchanged_since(2 * day).is_empty());
+    }
+}
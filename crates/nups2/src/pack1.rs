@@ -2,11 +2,13 @@ use flate2::bufread::ZlibDecoder;
 
 use crate::bin_utils::{clone_big_x_bytes, read_u32_be, read_x_bytes, write_u32_be};
 use crate::cli_utils::humanise_bytes;
+use crate::extract_utils::{create_output_file, is_safe_name, ExtractReport, OnConflict};
+use crate::Nups2Error;
 use std::fmt::Display;
-use std::io::prelude::Seek;
+use std::io::prelude::{Read, Seek};
+use std::io::SeekFrom;
 use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::{fs::File, io::SeekFrom};
 
 #[derive(Debug)]
 pub struct Pack1 {
@@ -14,17 +16,30 @@ pub struct Pack1 {
 }
 
 impl Pack1 {
-    pub fn load_from_file(br: &mut File) -> std::io::Result<Self> {
+    pub fn load_from_file<R: Read + Seek>(br: &mut R) -> Result<Self, Nups2Error> {
         log::debug!("Loading pack1 file..");
+        let file_length: u64 = br.seek(SeekFrom::End(0))?;
+        br.seek(SeekFrom::Start(0))?;
         let mut chunks: Vec<Pack1Chunk> = Vec::new();
+        let mut chunk_offset: u64 = 0;
         loop {
             log::trace!("Loading pack1 Chunk idx {} headers..", chunks.len());
-            let next_chunk: u32 = read_u32_be(br)?;
-            chunks.push(Pack1Chunk::load_from_br(br)?);
+            let next_chunk: u32 =
+                read_u32_be(br).map_err(|e| Nups2Error::from_io(e, chunk_offset))?;
+            chunks.push(Pack1Chunk::load_from_br(br, file_length)?);
             if next_chunk == 0 {
                 break;
             }
-            br.seek(SeekFrom::Start(next_chunk as u64))?;
+            // chunks only point forward, anything else would loop forever
+            if next_chunk as u64 <= chunk_offset || next_chunk as u64 >= file_length {
+                return Err(Nups2Error::OffsetOutOfBounds {
+                    offset: next_chunk as u64,
+                    length: 4,
+                    file_length,
+                });
+            }
+            chunk_offset = next_chunk as u64;
+            br.seek(SeekFrom::Start(chunk_offset))?;
         }
         Ok(Self { chunks })
     }
@@ -71,30 +86,40 @@ impl Pack1 {
         return out.join("\n");
     }
 
-    pub fn extract_all(
+    pub fn extract_all<R: Read + Seek>(
         &self,
-        br: &mut File,
+        br: &mut R,
         output_directory: &Path,
         subdirs_for_chunks: bool,
-    ) -> std::io::Result<()> {
+        on_conflict: OnConflict,
+    ) -> std::io::Result<ExtractReport> {
         std::fs::create_dir_all(output_directory)?;
 
+        let mut report: ExtractReport = ExtractReport::default();
         for (index, chunk) in self.chunks.iter().enumerate() {
             let chunkdir: PathBuf = if subdirs_for_chunks {
                 let res = output_directory.join(format!("chunk_{}", index));
-                std::fs::create_dir(&res)?;
+                std::fs::create_dir_all(&res)?;
                 res
             } else {
                 output_directory.to_path_buf()
             };
-            chunk.extract_all(br, &chunkdir)?;
+            report = report.merge(chunk.extract_all(br, &chunkdir, on_conflict)?);
         }
-        Ok(())
+        Ok(report)
+    }
+
+    /// names of all assets (in all chunks)
+    pub fn names(&self) -> Vec<String> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.assets.iter().map(|asset| asset.name.clone()))
+            .collect()
     }
 
-    pub fn write(
+    pub fn write<R: Read + Seek>(
         &self,
-        old_pack_file: &mut File,
+        old_pack_file: &mut R,
         target_file: &mut dyn Write,
     ) -> std::io::Result<()> {
         log::debug!("Starting generation of pack1 file");
@@ -220,12 +245,13 @@ pub struct Pack1Chunk {
 }
 
 impl Pack1Chunk {
-    fn load_from_br(br: &mut File) -> std::io::Result<Self> {
+    fn load_from_br<R: Read + Seek>(br: &mut R, file_length: u64) -> Result<Self, Nups2Error> {
         let mut assets: Vec<Pack1Asset> = Vec::new();
-        let asset_count: u32 = read_u32_be(br)?;
+        let chunk_offset: u64 = br.stream_position()?;
+        let asset_count: u32 = read_u32_be(br).map_err(|e| Nups2Error::from_io(e, chunk_offset))?;
         for asset_idx in 0..asset_count {
             log::trace!("Loading asset idx {asset_idx} header");
-            assets.push(Pack1Asset::load_from_br(br)?);
+            assets.push(Pack1Asset::load_from_br(br, file_length)?);
         }
         Ok(Self { assets })
     }
@@ -243,13 +269,41 @@ impl Pack1Chunk {
         )
     }
 
-    pub fn extract_all(&self, br: &mut File, output_directory: &Path) -> std::io::Result<()> {
+    pub fn extract_all<R: Read + Seek>(
+        &self,
+        br: &mut R,
+        output_directory: &Path,
+        on_conflict: OnConflict,
+    ) -> std::io::Result<ExtractReport> {
+        let mut report: ExtractReport = ExtractReport::default();
         for asset in self.assets.iter() {
-            let output_file: PathBuf = output_directory.join(&asset.name);
-            let mut output_stream: File = File::create_new(output_file)?;
-            asset.clone_data(br, &mut output_stream)?;
+            let output_file: PathBuf = if is_safe_name(&asset.name) {
+                output_directory.join(&asset.name)
+            } else {
+                log::warn!(
+                    "{:?} can't be used as a filename, extracting it under its hash",
+                    asset.name
+                );
+                report.rejected_names.push(asset.name.clone());
+                output_directory.join(format!(
+                    "crc_64_{}",
+                    crate::crc64::convert_filename(&asset.name)
+                ))
+            };
+            match create_output_file(
+                &output_file,
+                on_conflict,
+                asset.data_length as u64,
+                asset.file_hash,
+            )? {
+                Some(mut output_stream) => {
+                    asset.clone_data(br, &mut output_stream)?;
+                    report.extracted += 1;
+                }
+                None => report.skipped += 1,
+            }
         }
-        Ok(())
+        Ok(report)
     }
 }
 
@@ -278,14 +332,28 @@ pub struct Pack1Asset {
 }
 
 impl Pack1Asset {
-    fn load_from_br(br: &mut File) -> std::io::Result<Self> {
-        let name_length: u32 = read_u32_be(br)?;
+    fn load_from_br<R: Read + Seek>(br: &mut R, file_length: u64) -> Result<Self, Nups2Error> {
+        let header_offset: u64 = br.stream_position()?;
+        let name_length: u32 =
+            read_u32_be(br).map_err(|e| Nups2Error::from_io(e, header_offset))?;
+        // 4 for the name length + 12 for offset, data_length and file_hash
+        if header_offset + 16 + name_length as u64 > file_length {
+            return Err(Nups2Error::Truncated {
+                offset: header_offset,
+            });
+        }
         let name_bytes: Vec<u8> = read_x_bytes(br, name_length as usize)?;
-        let name: String = String::from_utf8(name_bytes)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let name: String = String::from_utf8(name_bytes)?;
         let offset: u32 = read_u32_be(br)?;
         let data_length: u32 = read_u32_be(br)?;
         let file_hash: u32 = read_u32_be(br)?;
+        if offset as u64 + data_length as u64 > file_length {
+            return Err(Nups2Error::OffsetOutOfBounds {
+                offset: offset as u64,
+                length: data_length as u64,
+                file_length,
+            });
+        }
         Ok(Self {
             name,
             offset,
@@ -304,9 +372,14 @@ impl Pack1Asset {
     //     read_big_x_bytes(pack_file_stream, self.data_length as usize)
     // }
 
-    pub fn clone_data(
+    /// assets from `Pack1::from_pack2` point into the (compressed) data of a pack2 file
+    pub(crate) fn stream_is_pack2_zipped(&self) -> bool {
+        self.stream_is_pack2_zipped
+    }
+
+    pub fn clone_data<R: Read + Seek>(
         &self,
-        pack_file_stream: &mut File,
+        pack_file_stream: &mut R,
         output_stream: &mut dyn Write,
     ) -> std::io::Result<()> {
         log::trace!("cloning asset {}", self);
@@ -343,9 +416,11 @@ impl Pack1Asset {
             name_hash: crate::crc64::convert_filename(&self.name),
             offset: self.offset as u64,
             data_length: self.data_length as u64,
+            flags: 0x00,
             is_zipped: false,
             data_hash: 0,       // FIXME
             unzipped_length: 0, // not zipped and then its 0
+            detected_type: None,
         }
     }
 
@@ -408,3 +483,54 @@ impl Display for Pack1Asset {
         write!(f, "Pack1Asset({name})", name = self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Pack1, UnknownNameHandling};
+    use crate::pack2::Pack2;
+    use crate::pack2_writer::Pack2Writer;
+    use crate::Nups2Error;
+    use std::io::Cursor;
+
+    #[test]
+    fn reject_chunk_loops() {
+        // two empty chunks (next chunk offset + asset count), the second one points at itself
+        let data: Vec<u8> = [8u32, 0, 8, 0]
+            .iter()
+            .flat_map(|i| i.to_be_bytes())
+            .collect();
+        assert!(matches!(
+            Pack1::load_from_file(&mut Cursor::new(data)),
+            Err(Nups2Error::OffsetOutOfBounds { offset: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn pack2_to_pack1_and_back() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("a.txt", b"hello world".to_vec())
+            .add_bytes("b.bin", vec![1, 2, 3]);
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+        pack2.apply_filename_list(&["a.txt".into(), "b.bin".into()]);
+
+        let pack1: Pack1 = Pack1::from_pack2(pack2, &UnknownNameHandling::ReturnError).unwrap();
+        let mut pack1_br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        pack1.write(&mut br, &mut pack1_br).unwrap();
+        let pack1: Pack1 = Pack1::load_from_file(&mut pack1_br).unwrap();
+
+        let mut target: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        let pack2: Pack2 =
+            Pack2::from_pack1(&pack1, &mut pack1_br, &mut target, true, true).unwrap();
+        assert_eq!(pack2.assets.len(), 3);
+        let asset = &pack2.assets[pack2.find_asset_index_by_name("a.txt").unwrap()];
+        assert!(asset.is_zipped);
+        assert_eq!(asset.extract_text(&mut target).unwrap(), "hello world");
+
+        // names survive through the embedded filename list
+        let reloaded: Pack2 = Pack2::load_from_file(&mut target).unwrap();
+        assert!(reloaded.find_asset_index_by_name("b.bin").is_some());
+    }
+}
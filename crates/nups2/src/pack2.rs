@@ -1,14 +1,30 @@
 use crate::bin_utils::*;
 use crate::cli_utils::humanise_bytes;
 use crate::crc64;
+use crate::extract_utils::{create_output_file, is_safe_name, ExtractReport, OnConflict};
+use crate::sniff::{FileType, SNIFF_LENGTH};
+use crate::Nups2Error;
 use flate2::read::ZlibDecoder;
+use std::borrow::Cow;
 use std::collections::HashMap;
+#[cfg(feature = "manifests")]
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::{prelude::*, Result, SeekFrom};
 use std::path::{Path, PathBuf};
 
+pub const PACK2_MAGIC: u32 = 0x50414b01;
+pub const COMPRESSION_MAGIC: u32 = 0xA1B2C3D4;
+/// crc64 of `{NAMELIST}`
+pub const FILENAME_LIST_NAME_HASH: u64 = 0x4137cc65bd97fd30;
+/// name_hash + offset + data_length + flags + data_hash
+pub const MAP_ENTRY_LENGTH: u64 = 32;
+/// magic + asset_count + length + map_offset + checksum + unknown bytes
+pub const HEADER_LENGTH: usize = 156;
+pub const HEADER_UNKNOWN_LENGTH: usize = 128;
+
 const ZIPPED_FLAGS: [u32; 2] = [0x01, 0x11];
 #[allow(dead_code)]
 const UNZIPPED_FLAGS: [u32; 2] = [0x00, 0x10];
@@ -16,26 +32,52 @@ const UNZIPPED_FLAGS: [u32; 2] = [0x00, 0x10];
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct Pack2 {
-    asset_count: u32,
-    length: u64,
-    map_offset: u64,
+    header: Pack2Header,
+    /// actual size of the pack2 data (`length` is only what the header claims)
+    file_length: u64,
+    /// call `rebuild_asset_index` after adding, removing or reordering assets or changing their `name_hash`
     pub assets: Vec<Asset>,
+    /// name_hash -> index in `assets` (the first one, if a hash appears twice)
+    asset_index: HashMap<u64, usize>,
 }
 
 impl Pack2 {
     #[cfg(feature = "json")]
     pub fn to_json(&self) -> String {
         format!(
-            "{o}\"asset_count\": {asset_count}, \"length\": {length}, \"map_offset\": {map_offset}, \"assets\": {assets}{c}",
+            "{o}\"asset_count\": {asset_count}, \"length\": {length}, \"map_offset\": {map_offset}, \"checksum\": {checksum}, \"header_unknown\": \"{header_unknown}\", \"assets\": {assets}{c}",
             o = "{",
             c = "}",
-            asset_count = self.asset_count,
-            length = self.length,
-            map_offset = self.map_offset,
+            asset_count = self.header.asset_count,
+            length = self.header.length,
+            map_offset = self.header.map_offset,
+            checksum = self.header.checksum,
+            header_unknown = to_hex(&self.header.unknown),
             assets = self.ls_assets_as_json(),
         )
     }
 
+    pub fn header(&self) -> &Pack2Header {
+        &self.header
+    }
+
+    /// asset counts, sizes, histograms, etc.
+    /// call `detect_types` first to get a histogram of the detected types.
+    pub fn stats(&self) -> crate::pack2_stats::Pack2Stats {
+        crate::pack2_stats::Pack2Stats::from_pack2(self)
+    }
+
+    /// how many assets use each (raw) flags value, sorted by flags
+    pub fn flag_counts(&self) -> Vec<(u32, usize)> {
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for asset in self.assets.iter() {
+            *counts.entry(asset.flags).or_insert(0) += 1;
+        }
+        let mut counts: Vec<(u32, usize)> = counts.into_iter().collect();
+        counts.sort();
+        counts
+    }
+
     #[cfg(feature = "rainbow_table")]
     pub fn crack_names_with_rainbow_table(&mut self, rainbow_table_file: &Path) -> Result<()> {
         let hashes_to_crack: Vec<u64> = self
@@ -58,50 +100,78 @@ impl Pack2 {
         }
     }
 
+    /// fill in `detected_type` for all assets.
+    /// assets, which can't be read, are logged and marked as `FileType::Unknown`.
+    pub fn detect_types<R: Read + Seek>(&mut self, br: &mut R) {
+        for asset in self.assets.iter_mut() {
+            asset.detected_type = Some(asset.sniff(br).unwrap_or_else(|e| {
+                log::warn!("unable to read {asset}: {e}");
+                FileType::Unknown
+            }));
+        }
+    }
+
+    /// remove all assets not matching the filter
+    #[cfg(feature = "asset_filter")]
+    pub fn retain_assets(&mut self, filter: &crate::asset_filter::AssetFilter) {
+        self.assets.retain(|asset| filter.matches(asset));
+        self.rebuild_asset_index();
+    }
+
+    /// has to be called after adding, removing or reordering `assets`,
+    /// lookups by name or name_hash only go through the index
+    pub fn rebuild_asset_index(&mut self) {
+        self.asset_index = build_asset_index(&self.assets);
+    }
+
     pub fn apply_filename_list(&mut self, filename_list: &[String]) {
         self.apply_filename_lookup_table(&crc64::filename_list_to_lookup_table(filename_list));
     }
 
-    pub fn load_from_file(br: &mut File) -> Result<Self> {
-        // let mut br: File = File::open(file_path.as_str())?;
-        // let mut br: BufReader<File> = BufReader::new(file);
+    /// write the assets of a pack1 file (read from `br`) as a new pack2 file into `target`
+    /// (see `Pack2Writer::write_with_pack1`) and load it again (with the names of the pack1 assets applied).
+    #[cfg(feature = "pack1")]
+    pub fn from_pack1<R: Read + Seek, W: Read + Write + Seek>(
+        pack1: &crate::pack1::Pack1,
+        br: &mut R,
+        target: &mut W,
+        compress: bool,
+        embed_filename_list: bool,
+    ) -> std::result::Result<Self, Nups2Error> {
+        let mut writer: crate::pack2_writer::Pack2Writer = crate::pack2_writer::Pack2Writer::new();
+        writer
+            .set_compression(compress)
+            .set_embed_filename_list(embed_filename_list)
+            .add_pack1(pack1)?;
+        writer.write_with_pack1(target, br)?;
+        let mut pack2: Self = Self::load_from_file(target)?;
+        pack2.apply_filename_list(&pack1.names());
+        Ok(pack2)
+    }
+
+    /// offsets in pack2 files are absolute -> the reader has to start with the pack2 data
+    pub fn load_from_file<R: Read + Seek>(br: &mut R) -> std::result::Result<Self, Nups2Error> {
+        let file_length: u64 = br.seek(SeekFrom::End(0))?;
+        br.seek(SeekFrom::Start(0))?;
+        let header: Pack2Header = Pack2Header::read(br, file_length)?;
 
-        // start of header
-        let magic: u32 = read_u32_be(br)?;
-        assert_eq!(
-            magic, 0x50414b01,
-            "file is missing pack2 magic value \"PAK\""
-        );
-        let asset_count: u32 = read_u32_le(br)?;
-        let length: u64 = read_u64_le(br)?;
-        let map_offset: u64 = read_u64_le(br)?;
-        // unknown: u32_le
-        // unknown: 128bytes
-        // end of header
-
-        br.seek(SeekFrom::Start(map_offset))?;
-        let mut assets: Vec<Asset> = Vec::with_capacity(asset_count as usize);
+        br.seek(SeekFrom::Start(header.map_offset))?;
+        let mut assets: Vec<Asset> = Vec::with_capacity(header.asset_count as usize);
         let mut filenames: Option<Vec<String>> = None;
-        for _asset_id in 1..=asset_count {
+        for _asset_id in 1..=header.asset_count {
             let asset = Asset::load_from_br(br)?;
-            if asset.name_hash == 0x4137cc65bd97fd30 {
+            if asset.name_hash == FILENAME_LIST_NAME_HASH {
                 let old_stream_position: u64 = br.stream_position()?;
                 filenames = Some(asset.extract_text(br)?.lines().map(String::from).collect());
                 br.seek(SeekFrom::Start(old_stream_position))?;
             }
-            // if !asset.is_zipped {
-            //     let old_stream_position: u64 = br.stream_position()?;
-            //     let mut fos: File = File::create_new(format!("EXTRACTED_{}", &asset_id))?;
-            //     asset.raw_dump_to_file(&mut br, &mut fos)?;
-            //     br.seek(SeekFrom::Start(old_stream_position))?;
-            // }
             assets.push(asset);
         }
 
         let mut result = Self {
-            asset_count,
-            length,
-            map_offset,
+            header,
+            file_length,
+            asset_index: build_asset_index(&assets),
             assets,
         };
 
@@ -112,90 +182,379 @@ impl Pack2 {
         Ok(result)
     }
 
-    pub fn find_asset_index_by_name(&self, name: &str) -> Option<usize> {
-        let hash: u64 = crc64::convert_filename(name);
-        for i in self.assets.iter().enumerate() {
-            if i.1.name_hash == hash {
-                return Some(i.0);
+    /// same as `load_from_file`, but parses the header and map directly from the pack2 data
+    /// (for example a memory-mapped file, see `pack2_mmap`)
+    pub fn load_from_slice(data: &[u8]) -> std::result::Result<Self, Nups2Error> {
+        let header: Pack2Header = Pack2Header::read(&mut &data[..], data.len() as u64)?;
+
+        let mut map: &[u8] = slice_at(
+            data,
+            header.map_offset,
+            header.asset_count as u64 * MAP_ENTRY_LENGTH,
+        )?;
+        let mut assets: Vec<Asset> = Vec::with_capacity(header.asset_count as usize);
+        let mut filenames: Option<Vec<String>> = None;
+        for asset_id in 0..(header.asset_count as u64) {
+            let asset = Asset::load_from_slice(
+                &mut map,
+                data,
+                header.map_offset + asset_id * MAP_ENTRY_LENGTH,
+            )?;
+            if asset.name_hash == FILENAME_LIST_NAME_HASH {
+                filenames = Some(
+                    asset
+                        .extract_text_from_slice(data)?
+                        .lines()
+                        .map(String::from)
+                        .collect(),
+                );
             }
+            assets.push(asset);
+        }
+
+        let mut result = Self {
+            header,
+            file_length: data.len() as u64,
+            asset_index: build_asset_index(&assets),
+            assets,
+        };
+
+        if let Some(filenames_unwrapped) = &filenames {
+            result.apply_filename_list(filenames_unwrapped);
         }
-        None
+
+        Ok(result)
     }
 
-    pub fn find_asset_index_by_name_hash(&self, hash: u64) -> Option<usize> {
-        for i in self.assets.iter().enumerate() {
-            if i.1.name_hash == hash {
-                return Some(i.0);
+    /// check the map against the actual pack2 data (corrupted downloads, partial patches, etc).
+    /// an empty result means no problems were found.
+    pub fn validate(&self) -> Vec<Pack2Issue> {
+        let mut issues: Vec<Pack2Issue> = Vec::new();
+        if self.header.length != self.file_length {
+            issues.push(Pack2Issue::LengthMismatch {
+                header_length: self.header.length,
+                file_length: self.file_length,
+            });
+        }
+
+        let map_end: u64 =
+            self.header.map_offset + self.header.asset_count as u64 * MAP_ENTRY_LENGTH;
+        let mut ranges: Vec<(u64, u64, u64)> = Vec::with_capacity(self.assets.len());
+        for asset in self.assets.iter() {
+            if ZIPPED_FLAGS.contains(&asset.flags) && asset.data_length <= 8 {
+                issues.push(Pack2Issue::EmptyCompressedAsset {
+                    name_hash: asset.name_hash,
+                });
+            }
+            if asset.data_length == 0 {
+                continue;
+            }
+            let asset_end: Option<u64> = asset.offset.checked_add(asset.data_length);
+            match asset_end {
+                Some(asset_end) if asset_end <= self.file_length => {
+                    if asset.offset < map_end && self.header.map_offset < asset_end {
+                        issues.push(Pack2Issue::MapInsideAssetData {
+                            map_offset: self.header.map_offset,
+                            name_hash: asset.name_hash,
+                        });
+                    }
+                    ranges.push((asset.offset, asset_end, asset.name_hash));
+                }
+                _ => issues.push(Pack2Issue::AssetOutOfBounds {
+                    name_hash: asset.name_hash,
+                    offset: asset.offset,
+                    data_length: asset.data_length,
+                    file_length: self.file_length,
+                }),
+            }
+        }
+
+        // identical ranges are deduplicated data and not a problem -> only check partial overlaps
+        ranges.sort();
+        ranges.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+        let mut furthest: Option<(u64, u64)> = None; // (end, name_hash)
+        for (start, end, name_hash) in ranges {
+            if let Some((furthest_end, furthest_name_hash)) = furthest {
+                if start < furthest_end {
+                    issues.push(Pack2Issue::OverlappingAssets {
+                        name_hash_a: furthest_name_hash,
+                        name_hash_b: name_hash,
+                    });
+                }
+                if end <= furthest_end {
+                    continue;
+                }
             }
+            furthest = Some((end, name_hash));
         }
-        None
+
+        issues
     }
 
-    pub fn extract_all_named(&self, br: &mut File, output_directory: &Path) -> Result<()> {
-        self.assets.iter().try_for_each(|asset| -> Result<()> {
-            if let Some(ref name) = asset.name {
-                let fp: PathBuf = output_directory.join(name);
-                log::info!("extracting {name} to {fp:?}");
-                let mut fos: File = File::create_new(fp)?;
-                asset.extract_to_file(br, &mut fos)?;
+    /// check all assets against their `data_hash` (multithreaded with the `fast` feature).
+    /// assumes it is a crc32, see `Asset::calculate_data_hash`.
+    /// the file is opened once per thread.
+    pub fn find_corrupted_assets(&self, pack2_file: &Path) -> Result<Vec<CorruptedAsset>> {
+        fn check(br: &mut File, index: usize, asset: &Asset) -> Option<CorruptedAsset> {
+            match asset.calculate_data_hash(br) {
+                Ok(data_hash) if data_hash == asset.data_hash => None,
+                Ok(data_hash) => Some(CorruptedAsset {
+                    index,
+                    found_data_hash: Some(data_hash),
+                }),
+                Err(e) => {
+                    log::debug!("unable to read {asset}: {e}");
+                    Some(CorruptedAsset {
+                        index,
+                        found_data_hash: None,
+                    })
+                }
             }
-            Ok(())
-        })
+        }
+
+        #[cfg(feature = "fast")]
+        {
+            use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+            // fail early if the file can't be opened instead of failing in every thread
+            File::open(pack2_file)?;
+            self.assets
+                .par_iter()
+                .enumerate()
+                .map_init(
+                    || File::open(pack2_file),
+                    |br, (index, asset)| match br {
+                        Ok(br) => Ok(check(br, index, asset)),
+                        Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+                    },
+                )
+                .filter_map(|i| i.transpose())
+                .collect()
+        }
+        #[cfg(not(feature = "fast"))]
+        {
+            let mut br: File = File::open(pack2_file)?;
+            Ok(self
+                .assets
+                .iter()
+                .enumerate()
+                .filter_map(|(index, asset)| check(&mut br, index, asset))
+                .collect())
+        }
+    }
+
+    pub fn find_asset_index_by_name(&self, name: &str) -> Option<usize> {
+        self.find_asset_index_by_name_hash(crc64::convert_filename(name))
+    }
+
+    pub fn find_asset_index_by_name_hash(&self, hash: u64) -> Option<usize> {
+        self.asset_index.get(&hash).copied()
+    }
+
+    pub fn extract_all_named<R: AssetSource + ?Sized>(
+        &self,
+        br: &mut R,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        on_conflict: OnConflict,
+    ) -> Result<ExtractReport> {
+        extract_assets(
+            br,
+            self.assets
+                .iter()
+                .filter(|asset| asset.name.is_some())
+                .map(|asset| (asset, on_conflict)),
+            output_directory,
+            layout,
+        )
     }
 
+    /// skips assets, which did not change since the manifest was generated.
+    /// files of changed assets are always replaced, `on_conflict` only applies to new ones.
     #[cfg(feature = "manifests")]
-    pub fn incremental_extract_all_named(
+    pub fn incremental_extract_all_named<R: AssetSource + ?Sized>(
         &self,
-        br: &mut File,
+        br: &mut R,
         output_directory: &Path,
+        layout: &ExtractLayout,
         manifest: &crate::pack2_manifest::Manifest,
-    ) -> Result<()> {
-        self.assets.iter().try_for_each(|asset| -> Result<()> {
-            if let Some(ref name) = asset.name {
-                if !manifest.contains(&(asset.name_hash, asset.data_hash)) {
-                    let fp: PathBuf = output_directory.join(name);
-                    log::info!("extracting {name} to {fp:?}");
-                    if fp.exists() {
-                        std::fs::remove_file(&fp)?;
-                    }
-                    let mut fos: File = File::create_new(&fp)?;
-                    asset.extract_to_file(br, &mut fos)?;
-                }
-            }
-            Ok(())
+        on_conflict: OnConflict,
+    ) -> Result<ExtractReport> {
+        let previous: HashMap<u64, u32> = manifest.data_hashes();
+        extract_assets(
+            br,
+            self.assets
+                .iter()
+                .filter(|asset| asset.name.is_some())
+                .filter_map(|asset| {
+                    Some((
+                        asset,
+                        incremental_on_conflict(&previous, asset, on_conflict)?,
+                    ))
+                }),
+            output_directory,
+            layout,
+        )
+    }
+
+    pub fn extract_all_unnamed<R: AssetSource + ?Sized>(
+        &self,
+        br: &mut R,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        on_conflict: OnConflict,
+    ) -> Result<ExtractReport> {
+        extract_assets(
+            br,
+            self.assets
+                .iter()
+                .filter(|asset| asset.name.is_none())
+                .map(|asset| (asset, on_conflict)),
+            output_directory,
+            layout,
+        )
+    }
+
+    /// see `incremental_extract_all_named`
+    #[cfg(feature = "manifests")]
+    pub fn incremental_extract_all_unnamed<R: AssetSource + ?Sized>(
+        &self,
+        br: &mut R,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        manifest: &crate::pack2_manifest::Manifest,
+        on_conflict: OnConflict,
+    ) -> Result<ExtractReport> {
+        let previous: HashMap<u64, u32> = manifest.data_hashes();
+        extract_assets(
+            br,
+            self.assets
+                .iter()
+                .filter(|asset| asset.name.is_none())
+                .filter_map(|asset| {
+                    Some((
+                        asset,
+                        incremental_on_conflict(&previous, asset, on_conflict)?,
+                    ))
+                }),
+            output_directory,
+            layout,
+        )
+    }
+
+    /// same as `extract_all_named`, but spread over `jobs` threads (0 = one per cpu).
+    /// `open_reader` is called once per thread, so each thread reads through its own handle.
+    #[cfg(feature = "fast")]
+    pub fn par_extract_all_named<R, F>(
+        &self,
+        open_reader: F,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        on_conflict: OnConflict,
+        jobs: usize,
+    ) -> Result<ExtractReport>
+    where
+        R: AssetSource,
+        F: Fn() -> Result<R> + Sync + Send,
+    {
+        self.par_extract(open_reader, output_directory, layout, jobs, |asset| {
+            asset.name.as_ref().map(|_| on_conflict)
         })
     }
 
-    pub fn extract_all_unnamed(&self, br: &mut File, output_directory: &Path) -> Result<()> {
-        self.assets.iter().try_for_each(|asset| -> Result<()> {
-            if asset.name.is_none() {
-                let fp: PathBuf = output_directory.join(format!("crc_64_{}", asset.name_hash));
-                log::info!("extracting 0x{:X} to {fp:?}", asset.name_hash);
-                let mut fos: File = File::create_new(fp)?;
-                asset.extract_to_file(br, &mut fos)?;
-            }
-            Ok(())
+    #[cfg(all(feature = "fast", feature = "manifests"))]
+    pub fn par_incremental_extract_all_named<R, F>(
+        &self,
+        open_reader: F,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        manifest: &crate::pack2_manifest::Manifest,
+        on_conflict: OnConflict,
+        jobs: usize,
+    ) -> Result<ExtractReport>
+    where
+        R: AssetSource,
+        F: Fn() -> Result<R> + Sync + Send,
+    {
+        let previous: HashMap<u64, u32> = manifest.data_hashes();
+        self.par_extract(open_reader, output_directory, layout, jobs, |asset| {
+            asset.name.as_ref()?;
+            incremental_on_conflict(&previous, asset, on_conflict)
         })
     }
 
-    #[cfg(feature = "manifests")]
-    pub fn incremental_extract_all_unnamed(
+    #[cfg(feature = "fast")]
+    pub fn par_extract_all_unnamed<R, F>(
+        &self,
+        open_reader: F,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        on_conflict: OnConflict,
+        jobs: usize,
+    ) -> Result<ExtractReport>
+    where
+        R: AssetSource,
+        F: Fn() -> Result<R> + Sync + Send,
+    {
+        self.par_extract(open_reader, output_directory, layout, jobs, |asset| {
+            asset.name.is_none().then_some(on_conflict)
+        })
+    }
+
+    #[cfg(all(feature = "fast", feature = "manifests"))]
+    pub fn par_incremental_extract_all_unnamed<R, F>(
         &self,
-        br: &mut File,
+        open_reader: F,
         output_directory: &Path,
+        layout: &ExtractLayout,
         manifest: &crate::pack2_manifest::Manifest,
-    ) -> Result<()> {
-        self.assets.iter().try_for_each(|asset| -> Result<()> {
-            if asset.name.is_none() && !manifest.contains(&(asset.name_hash, asset.data_hash)) {
-                let fp: PathBuf = output_directory.join(format!("crc_64_{}", asset.name_hash));
-                log::info!("extracting 0x{:X} to {fp:?}", asset.name_hash);
-                if fp.exists() {
-                    std::fs::remove_file(&fp)?;
-                }
-                let mut fos: File = File::create_new(&fp)?;
-                asset.extract_to_file(br, &mut fos)?;
+        on_conflict: OnConflict,
+        jobs: usize,
+    ) -> Result<ExtractReport>
+    where
+        R: AssetSource,
+        F: Fn() -> Result<R> + Sync + Send,
+    {
+        let previous: HashMap<u64, u32> = manifest.data_hashes();
+        self.par_extract(open_reader, output_directory, layout, jobs, |asset| {
+            if asset.name.is_some() {
+                return None;
             }
-            Ok(())
+            incremental_on_conflict(&previous, asset, on_conflict)
+        })
+    }
+
+    /// `select` returns the conflict policy for assets, which should be extracted
+    #[cfg(feature = "fast")]
+    fn par_extract<R, F>(
+        &self,
+        open_reader: F,
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        jobs: usize,
+        select: impl Fn(&Asset) -> Option<OnConflict> + Sync + Send,
+    ) -> Result<ExtractReport>
+    where
+        R: AssetSource,
+        F: Fn() -> Result<R> + Sync + Send,
+    {
+        use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+        // fail early if the file can't be opened instead of failing in every thread
+        open_reader()?;
+        let pool: rayon::ThreadPool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build()
+            .map_err(std::io::Error::other)?;
+        pool.install(|| {
+            self.assets
+                .par_iter()
+                .filter_map(|asset| Some((asset, select(asset)?)))
+                .map_init(&open_reader, |br, (asset, on_conflict)| match br {
+                    Ok(br) => {
+                        extract_into_directory(asset, br, output_directory, layout, on_conflict)
+                    }
+                    Err(e) => Err(std::io::Error::new(e.kind(), e.to_string())),
+                })
+                .try_reduce(ExtractReport::default, |a, b| Ok(a.merge(b)))
         })
     }
 
@@ -203,47 +562,85 @@ impl Pack2 {
     pub fn incremental_extract_delete_old_files(
         &self,
         output_directory: &Path,
+        layout: &ExtractLayout,
         manifest: &crate::pack2_manifest::Manifest,
         filename_lookup_table: &HashMap<u64, String>,
     ) -> Result<()> {
-        self.diff_with_manifest(manifest)
+        let deleted: Vec<u64> = self
+            .diff_with_manifest(manifest)
             .into_iter()
             .filter(|de| de.new_data_hash.is_none())
-            .try_for_each(|i| -> Result<()> {
-                let fp: PathBuf = output_directory.join(format!("crc_64_{}", &i.name_hash));
-                if fp.is_file() {
-                    std::fs::remove_file(fp)?;
-                }
-                if let Some(name) = filename_lookup_table.get(&i.name_hash) {
-                    let fp: PathBuf = output_directory.join(name);
-                    if fp.is_file() {
-                        std::fs::remove_file(fp)?;
-                    }
+            .map(|de| de.name_hash)
+            .collect();
+        if deleted.is_empty() {
+            return Ok(());
+        }
+        let deleted_names: HashSet<&str> = deleted
+            .iter()
+            .filter_map(|name_hash| filename_lookup_table.get(name_hash))
+            .map(|name| name.as_str())
+            .collect();
+        let deleted: HashSet<u64> = deleted.into_iter().collect();
+        // the directory (and for unnamed assets the extension) can depend on the content,
+        // which is not known anymore -> look through all places the layout might have put it
+        for directory in layout.possible_directories(output_directory)? {
+            for entry in std::fs::read_dir(directory)? {
+                let fp: PathBuf = entry?.path();
+                let filename: &str = match fp.file_name().and_then(|i| i.to_str()) {
+                    Some(filename) => filename,
+                    None => continue,
+                };
+                let is_deleted: bool = match crate::pack2_writer::parse_unnamed_filename(filename) {
+                    Some(name_hash) => deleted.contains(&name_hash),
+                    None => deleted_names.contains(filename),
+                };
+                if is_deleted && fp.is_file() {
+                    std::fs::remove_file(&fp)?;
                 }
-                Ok(())
-            })
+            }
+        }
+        Ok(())
+    }
+
+    /// find an asset by name, `0x` prefixed hex hash or decimal hash.
+    /// names are compared by their hash, so no filename list is needed.
+    pub fn find_asset(&self, name_or_hash: &str) -> Option<&Asset> {
+        candidate_name_hashes(name_or_hash)
+            .into_iter()
+            .find_map(|hash| self.find_asset_index_by_name_hash(hash))
+            .map(|index| &self.assets[index])
     }
 
-    pub fn extract_file(
+    /// `file_to_extract` (see `find_asset`) is also used as filename
+    pub fn extract_file<R: Read + Seek>(
         &self,
-        br: &mut File,
+        br: &mut R,
         file_to_extract: String,
         output_directory: &Path,
+        on_conflict: OnConflict,
     ) -> Result<()> {
-        for asset in self.assets.iter() {
-            if asset.name.clone().unwrap_or("".into()) == file_to_extract
-                || format!("0x{:X}", asset.name_hash) == file_to_extract
-                || format!("{}", asset.name_hash) == file_to_extract
-            {
-                let mut fos: File = File::create_new(output_directory.join(file_to_extract))?;
-                asset.extract_to_file(br, &mut fos)?;
-                return Ok(());
+        let asset: &Asset = self.find_asset(&file_to_extract).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Asset not found in pack2")
+        })?;
+        if !is_safe_name(&file_to_extract) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{file_to_extract:?} can't be used as a filename"),
+            ));
+        }
+        let fp: PathBuf = output_directory.join(&file_to_extract);
+        match create_output_file(
+            &fp,
+            on_conflict,
+            asset.uncompressed_length(),
+            asset.data_hash,
+        )? {
+            Some(mut fos) => asset.extract_to_file(br, &mut fos),
+            None => {
+                log::info!("skipping {fp:?} (already exists)");
+                Ok(())
             }
         }
-        Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Asset not found in pack2",
-        ))
     }
 
     #[cfg(feature = "json")]
@@ -258,17 +655,18 @@ impl Pack2 {
         )
     }
 
+    /// the "Detected type" column is empty unless `detect_types` was called
     pub fn ls_assets_for_humans(&self) -> String {
         #[cfg(feature = "use_comfy_table")]
         let mut table = comfy_table::Table::new();
         #[cfg(feature = "use_comfy_table")]
-        table.set_header(vec!["Name", "Size"]);
+        table.set_header(vec!["Name", "Size", "Detected type"]);
 
         #[cfg(not(feature = "use_comfy_table"))]
         let mut out: Vec<String> = Vec::with_capacity(self.assets.len());
 
         for asset in self.assets.iter() {
-            let mut columns: Vec<String> = Vec::with_capacity(2);
+            let mut columns: Vec<String> = Vec::with_capacity(3);
 
             if asset.name.is_some() {
                 columns.push(asset.name.clone().unwrap());
@@ -285,6 +683,13 @@ impl Pack2 {
                 columns.push(humanise_bytes(asset.data_length as f64));
             }
 
+            columns.push(
+                asset
+                    .detected_type
+                    .map(|i| i.name().to_string())
+                    .unwrap_or_default(),
+            );
+
             #[cfg(feature = "use_comfy_table")]
             table.add_row(columns);
             #[cfg(not(feature = "use_comfy_table"))]
@@ -297,15 +702,17 @@ impl Pack2 {
         return out.join("\n");
     }
 
+    /// writes a v2 manifest (see `pack2_manifest::read_manifest_file`).
+    /// `pack_name` should be the filename of the pack2 file.
     #[cfg(feature = "manifests")]
-    pub fn write_manifest_file(&self, manifst_file: &Path) -> Result<()> {
-        let mut br: File = File::create_new(manifst_file)?;
-        for asset in self.assets.iter() {
-            br.write_all(&asset.name_hash.to_be_bytes())?;
-            br.write_all(&asset.data_hash.to_be_bytes())?;
-        }
-        br.flush()?;
-        Ok(())
+    pub fn write_manifest_file(
+        &self,
+        manifst_file: &Path,
+        pack_name: &str,
+        game_build: Option<String>,
+    ) -> Result<()> {
+        crate::pack2_manifest::Manifest::from_pack2(self, pack_name, game_build)
+            .write_to_file(manifst_file)
     }
 
     #[cfg(feature = "manifests")]
@@ -315,50 +722,262 @@ impl Pack2 {
     ) -> crate::pack2_manifest::ManifestDiff {
         use crate::pack2_manifest::{ManifestDiff, ManifestDiffEntry};
         let mut result: ManifestDiff = Vec::new();
-        for manifest_entry in manifest {
-            match self.find_asset_index_by_name_hash(manifest_entry.0) {
+        for record in manifest.records.iter() {
+            match self.find_asset_index_by_name_hash(record.name_hash) {
                 Some(asset_index) => {
-                    if manifest_entry.1 != self.assets[asset_index].data_hash {
-                        result.push(ManifestDiffEntry::new(
-                            manifest_entry.0,
-                            Some(manifest_entry.1),
-                            Some((self.assets[asset_index]).data_hash),
-                        ));
+                    let asset: &Asset = &self.assets[asset_index];
+                    if record.data_hash != asset.data_hash {
+                        result.push(
+                            ManifestDiffEntry::new(
+                                record.name_hash,
+                                Some(record.data_hash),
+                                Some(asset.data_hash),
+                            )
+                            .with_lengths(
+                                record.uncompressed_length,
+                                Some(asset.uncompressed_length()),
+                            ),
+                        );
                     }
                 }
-                None => result.push(ManifestDiffEntry::new(
-                    manifest_entry.0,
-                    Some(manifest_entry.1),
-                    None,
-                )),
+                None => result.push(
+                    ManifestDiffEntry::new(record.name_hash, Some(record.data_hash), None)
+                        .with_lengths(record.uncompressed_length, None),
+                ),
             }
         }
-        'asset_loop: for asset in self.assets.iter() {
-            for manifest_entry in manifest {
-                if manifest_entry.0 == asset.name_hash {
-                    continue 'asset_loop;
-                }
+        let in_manifest: HashSet<u64> = manifest.records.iter().map(|i| i.name_hash).collect();
+        for asset in self
+            .assets
+            .iter()
+            .filter(|asset| !in_manifest.contains(&asset.name_hash))
+        {
+            result.push(
+                ManifestDiffEntry::new(asset.name_hash, None, Some(asset.data_hash))
+                    .with_lengths(None, Some(asset.uncompressed_length())),
+            );
+        }
+        result
+    }
+}
+
+/// A problem found by `Pack2::validate`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Pack2Issue {
+    /// the `length` in the header does not match the actual size
+    LengthMismatch {
+        header_length: u64,
+        file_length: u64,
+    },
+    /// the asset data (partially) lies behind the end of the file
+    AssetOutOfBounds {
+        name_hash: u64,
+        offset: u64,
+        data_length: u64,
+        file_length: u64,
+    },
+    /// two assets share some, but not all, of their data
+    OverlappingAssets { name_hash_a: u64, name_hash_b: u64 },
+    /// flagged as compressed, but there is no room for compressed data
+    EmptyCompressedAsset { name_hash: u64 },
+    /// the map is located inside of a assets data
+    MapInsideAssetData { map_offset: u64, name_hash: u64 },
+}
+
+impl Pack2Issue {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        match self {
+            Pack2Issue::LengthMismatch {
+                header_length,
+                file_length,
+            } => format!(
+                r#"{{"kind":"length_mismatch","header_length":{header_length},"file_length":{file_length}}}"#
+            ),
+            Pack2Issue::AssetOutOfBounds {
+                name_hash,
+                offset,
+                data_length,
+                file_length,
+            } => format!(
+                r#"{{"kind":"asset_out_of_bounds","name_hash":{name_hash},"offset":{offset},"data_length":{data_length},"file_length":{file_length}}}"#
+            ),
+            Pack2Issue::OverlappingAssets {
+                name_hash_a,
+                name_hash_b,
+            } => format!(
+                r#"{{"kind":"overlapping_assets","name_hash_a":{name_hash_a},"name_hash_b":{name_hash_b}}}"#
+            ),
+            Pack2Issue::EmptyCompressedAsset { name_hash } => {
+                format!(r#"{{"kind":"empty_compressed_asset","name_hash":{name_hash}}}"#)
             }
-            result.push(ManifestDiffEntry::new(
-                asset.name_hash,
-                None,
-                Some(asset.data_hash),
-            ));
+            Pack2Issue::MapInsideAssetData {
+                map_offset,
+                name_hash,
+            } => format!(
+                r#"{{"kind":"map_inside_asset_data","map_offset":{map_offset},"name_hash":{name_hash}}}"#
+            ),
         }
+    }
+}
+
+impl Display for Pack2Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pack2Issue::LengthMismatch {
+                header_length,
+                file_length,
+            } => write!(
+                f,
+                "Header length ({header_length}) does not match the file size ({file_length})"
+            ),
+            Pack2Issue::AssetOutOfBounds {
+                name_hash,
+                offset,
+                data_length,
+                file_length,
+            } => write!(
+                f,
+                "Asset 0x{name_hash:X} ({data_length} bytes at offset {offset}) ends after the end of the file ({file_length})"
+            ),
+            Pack2Issue::OverlappingAssets {
+                name_hash_a,
+                name_hash_b,
+            } => write!(
+                f,
+                "Assets 0x{name_hash_a:X} and 0x{name_hash_b:X} have overlapping data"
+            ),
+            Pack2Issue::EmptyCompressedAsset { name_hash } => write!(
+                f,
+                "Asset 0x{name_hash:X} is flagged as compressed, but has no compressed data"
+            ),
+            Pack2Issue::MapInsideAssetData {
+                map_offset,
+                name_hash,
+            } => write!(
+                f,
+                "The map (offset {map_offset}) is inside the data of asset 0x{name_hash:X}"
+            ),
+        }
+    }
+}
+
+/// The fixed-size start of a pack2 file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pack2Header {
+    pub asset_count: u32,
+    /// length of the whole pack2 file according to the header
+    pub length: u64,
+    pub map_offset: u64,
+    /// probably a checksum (the algorithm is unknown, so it is not verified)
+    pub checksum: u32,
+    /// no known purpose (kept for research and to write files byte for byte)
+    pub unknown: [u8; HEADER_UNKNOWN_LENGTH],
+}
+
+impl Pack2Header {
+    /// reads and checks the header (magic, map inside of the file)
+    pub fn read(br: &mut dyn Read, file_length: u64) -> std::result::Result<Self, Nups2Error> {
+        let mut header: [u8; 24] = [0; 24];
+        br.read_exact(&mut header)
+            .map_err(|e| Nups2Error::from_io(e, 0))?;
+        let mut header: &[u8] = &header;
+        let magic: u32 = read_u32_be(&mut header)?;
+        if magic != PACK2_MAGIC {
+            return Err(Nups2Error::BadMagic {
+                format: "pack2",
+                expected: PACK2_MAGIC,
+                found: magic,
+            });
+        }
+        let asset_count: u32 = read_u32_le(&mut header)?;
+        let length: u64 = read_u64_le(&mut header)?;
+        let map_offset: u64 = read_u64_le(&mut header)?;
+
+        let map_length: u64 = asset_count as u64 * MAP_ENTRY_LENGTH;
+        if !matches!(map_offset.checked_add(map_length), Some(map_end) if map_end <= file_length) {
+            return Err(Nups2Error::OffsetOutOfBounds {
+                offset: map_offset,
+                length: map_length,
+                file_length,
+            });
+        }
+
+        let checksum: u32 = read_u32_le(br).map_err(|e| Nups2Error::from_io(e, 24))?;
+        let mut unknown: [u8; HEADER_UNKNOWN_LENGTH] = [0; HEADER_UNKNOWN_LENGTH];
+        br.read_exact(&mut unknown)
+            .map_err(|e| Nups2Error::from_io(e, 28))?;
+        Ok(Self {
+            asset_count,
+            length,
+            map_offset,
+            checksum,
+            unknown,
+        })
+    }
+
+    /// the header exactly as it was read
+    pub fn to_bytes(&self) -> [u8; HEADER_LENGTH] {
+        let mut result: [u8; HEADER_LENGTH] = [0; HEADER_LENGTH];
+        result[0..4].copy_from_slice(&PACK2_MAGIC.to_be_bytes());
+        result[4..8].copy_from_slice(&self.asset_count.to_le_bytes());
+        result[8..16].copy_from_slice(&self.length.to_le_bytes());
+        result[16..24].copy_from_slice(&self.map_offset.to_le_bytes());
+        result[24..28].copy_from_slice(&self.checksum.to_le_bytes());
+        result[28..].copy_from_slice(&self.unknown);
         result
     }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"asset_count":{asset_count},"length":{length},"map_offset":{map_offset},"checksum":{checksum},"unknown":"{unknown}"}}"#,
+            asset_count = self.asset_count,
+            length = self.length,
+            map_offset = self.map_offset,
+            checksum = self.checksum,
+            unknown = to_hex(&self.unknown),
+        )
+    }
+}
+
+impl Display for Pack2Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Asset count: {}", self.asset_count)?;
+        writeln!(
+            f,
+            "Length: {} ({})",
+            self.length,
+            humanise_bytes(self.length as f64)
+        )?;
+        writeln!(f, "Map offset: 0x{:X}", self.map_offset)?;
+        writeln!(f, "Checksum: 0x{:08X}", self.checksum)?;
+        write!(f, "Unknown bytes:")?;
+        for line in self.unknown.chunks(16) {
+            write!(f, "\n  {}", to_hex(line))?;
+        }
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|i| format!("{i:02x}")).collect()
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Asset {
     pub name: Option<String>,
     pub name_hash: u64, // of uppercase filename
     pub offset: u64,
     pub data_length: u64,
+    /// raw flags from the map (see `is_zipped`)
+    pub flags: u32,
     pub is_zipped: bool,
     pub data_hash: u32,
     pub unzipped_length: u32,
+    /// guessed from the content (see `Pack2::detect_types`)
+    pub detected_type: Option<FileType>,
 }
 
 impl Asset {
@@ -367,58 +986,141 @@ impl Asset {
         use crate::json_utils::escape_string;
 
         format!(
-            "{o}\"name\": {name}, \"name_hash\": {name_hash}, \"offset\": {offset}, \"data_length\": {data_length}, \"is_zipped\": {is_zipped}, \"data_hash\": {data_hash}, \"unzipped_length\": {unzipped_length}{c}",
+            "{o}\"name\": {name}, \"name_hash\": {name_hash}, \"offset\": {offset}, \"data_length\": {data_length}, \"flags\": {flags}, \"is_zipped\": {is_zipped}, \"data_hash\": {data_hash}, \"unzipped_length\": {unzipped_length}, \"detected_type\": {detected_type}{c}",
             o = "{",
             c = "}",
             name = if self.name.is_some() {escape_string(self.name.clone().unwrap().as_str())} else {String::from("null")},
             name_hash = self.name_hash,
             offset = self.offset,
             data_length = self.data_length,
+            flags = self.flags,
             data_hash = self.data_hash,
             unzipped_length = self.unzipped_length,
             is_zipped = if self.is_zipped {"true"} else {"false"},
+            detected_type = match self.detected_type {Some(t) => escape_string(t.name()), None => String::from("null")},
         )
     }
 
-    pub fn load_from_br(br: &mut File) -> Result<Self> {
-        let name_hash: u64 = read_u64_le(br)?;
-        let offset: u64 = read_u64_le(br)?;
-        let data_length: u64 = read_u64_le(br)?;
-        let zipped_flag: u32 = read_u32_le(br)?;
-        let data_hash: u32 = read_u32_le(br)?;
-
-        let is_zipped: bool = ZIPPED_FLAGS.contains(&zipped_flag) && data_length > 0;
+    pub fn load_from_br<R: Read + Seek>(br: &mut R) -> std::result::Result<Self, Nups2Error> {
+        let map_entry_offset: u64 = br.stream_position()?;
+        let mut map_entry: [u8; MAP_ENTRY_LENGTH as usize] = [0; MAP_ENTRY_LENGTH as usize];
+        br.read_exact(&mut map_entry)
+            .map_err(|e| Nups2Error::from_io(e, map_entry_offset))?;
+        let mut asset: Self = Self::parse_map_entry(&mut &map_entry[..])?;
 
-        let unzipped_length: u32 = if is_zipped {
+        if asset.is_zipped {
             let old_stream_position: u64 = br.stream_position()?;
-            br.seek(SeekFrom::Start(offset))?;
-            let zip_magic: u32 = read_u32_be(br)?;
-            assert_eq!(
-                zip_magic, 0xA1B2C3D4,
-                "Asset compression magic value (prefix) not found (\"A1B2C3D4\")"
-            );
-            let unzipped_length: u32 = read_u32_be(br)?;
+            br.seek(SeekFrom::Start(asset.offset))?;
+            let mut zip_header: [u8; 8] = [0; 8];
+            br.read_exact(&mut zip_header)
+                .map_err(|e| Nups2Error::from_io(e, asset.offset))?;
+            asset.unzipped_length = read_zip_header(&mut &zip_header[..])?;
             br.seek(SeekFrom::Start(old_stream_position))?;
-            unzipped_length
-        } else {
-            0
-        };
+        }
+
+        Ok(asset)
+    }
+
+    /// parse a map entry from `map_entry` (advancing it) and read the compression header from `data`.
+    /// `map_entry_offset` is only used for error messages.
+    pub fn load_from_slice(
+        map_entry: &mut &[u8],
+        data: &[u8],
+        map_entry_offset: u64,
+    ) -> std::result::Result<Self, Nups2Error> {
+        if map_entry.len() < MAP_ENTRY_LENGTH as usize {
+            return Err(Nups2Error::Truncated {
+                offset: map_entry_offset,
+            });
+        }
+        let mut asset: Self = Self::parse_map_entry(map_entry)?;
+        if asset.is_zipped {
+            asset.unzipped_length = read_zip_header(&mut slice_at(data, asset.offset, 8)?)?;
+        }
+        Ok(asset)
+    }
+
+    /// `unzipped_length` is not part of the map and is left at 0
+    fn parse_map_entry(map_entry: &mut &[u8]) -> Result<Self> {
+        let name_hash: u64 = read_u64_le(map_entry)?;
+        let offset: u64 = read_u64_le(map_entry)?;
+        let data_length: u64 = read_u64_le(map_entry)?;
+        let flags: u32 = read_u32_le(map_entry)?;
+        let data_hash: u32 = read_u32_le(map_entry)?;
+
+        let is_zipped: bool = ZIPPED_FLAGS.contains(&flags) && data_length > 0;
 
         Ok(Self {
             name: None,
             name_hash,
             offset,
             data_length,
+            flags,
             is_zipped,
             data_hash,
-            unzipped_length,
+            unzipped_length: 0,
+            detected_type: None,
         })
     }
 
-    fn raw_dump_to_file(
+    /// the same bytes `raw_bytes` returns, but borrowed from the pack2 data
+    pub fn raw_slice<'a>(&self, data: &'a [u8]) -> std::result::Result<&'a [u8], Nups2Error> {
+        let (offset, length): (u64, u64) = self.raw_range();
+        slice_at(data, offset, length)
+    }
+
+    /// offset and length of the stored data, without the 8 byte compression header.
+    /// `data_length` includes that header
+    fn raw_range(&self) -> (u64, u64) {
+        if self.is_zipped {
+            (self.offset + 8, self.data_length.saturating_sub(8))
+        } else {
+            (self.offset, self.data_length)
+        }
+    }
+
+    /// uncompressed assets are borrowed, compressed ones get decompressed straight from `data`
+    pub fn extract_bytes_from_slice<'a>(
+        &self,
+        data: &'a [u8],
+    ) -> std::result::Result<Cow<'a, [u8]>, Nups2Error> {
+        let raw_bytes: &[u8] = self.raw_slice(data)?;
+        if !self.is_zipped {
+            return Ok(Cow::Borrowed(raw_bytes));
+        }
+        let mut d = ZlibDecoder::new(raw_bytes);
+        let mut buf: Vec<u8> = Vec::with_capacity(self.unzipped_length as usize);
+        d.read_to_end(&mut buf)?;
+        Ok(Cow::Owned(buf))
+    }
+
+    pub fn extract_slice_to_file(
+        &self,
+        data: &[u8],
+        output_file_steam: &mut dyn Write,
+    ) -> std::result::Result<(), Nups2Error> {
+        log::trace!("extracing asset {}", self);
+        let raw_bytes: &[u8] = self.raw_slice(data)?;
+        if self.is_zipped {
+            let mut d = ZlibDecoder::new(raw_bytes);
+            clone_big_x_bytes(&mut d, output_file_steam, self.unzipped_length as usize)?;
+        } else {
+            output_file_steam.write_all(raw_bytes)?;
+        }
+        output_file_steam.flush()?;
+        Ok(())
+    }
+
+    pub fn extract_text_from_slice(&self, data: &[u8]) -> std::result::Result<String, Nups2Error> {
+        Ok(String::from_utf8(
+            self.extract_bytes_from_slice(data)?.into_owned(),
+        )?)
+    }
+
+    fn raw_dump_to_file<R: Read + Seek>(
         &self,
-        pack_file_stream: &mut File,
-        output_file_steam: &mut File,
+        pack_file_stream: &mut R,
+        output_file_steam: &mut dyn Write,
     ) -> Result<()> {
         pack_file_stream.seek(SeekFrom::Start(
             self.offset + if self.is_zipped { 8 } else { 0 },
@@ -432,7 +1134,7 @@ impl Asset {
         Ok(())
     }
 
-    pub fn extract_bytes(&self, pack_file_stream: &mut File) -> Result<Vec<u8>> {
+    pub fn extract_bytes<R: Read + Seek>(&self, pack_file_stream: &mut R) -> Result<Vec<u8>> {
         let raw_bytes: Vec<u8> = self.raw_bytes(pack_file_stream)?;
         if !self.is_zipped {
             return Ok(raw_bytes);
@@ -443,10 +1145,10 @@ impl Asset {
         Ok(buf)
     }
 
-    pub fn extract_to_file(
+    pub fn extract_to_file<R: Read + Seek>(
         &self,
-        pack_file_stream: &mut File,
-        output_file_steam: &mut File,
+        pack_file_stream: &mut R,
+        output_file_steam: &mut dyn Write,
     ) -> Result<()> {
         log::trace!("extracing asset {}", self);
         if self.is_zipped {
@@ -456,10 +1158,10 @@ impl Asset {
         }
     }
 
-    fn extract_compressed_to_file(
+    fn extract_compressed_to_file<R: Read + Seek>(
         &self,
-        pack_file_stream: &mut File,
-        output_file_steam: &mut File,
+        pack_file_stream: &mut R,
+        output_file_steam: &mut dyn Write,
     ) -> Result<()> {
         pack_file_stream.seek(SeekFrom::Start(
             self.offset + if self.is_zipped { 8 } else { 0 },
@@ -472,15 +1174,14 @@ impl Asset {
         Ok(())
     }
 
-    pub fn raw_bytes(&self, pack_file_stream: &mut File) -> Result<Vec<u8>> {
-        pack_file_stream.seek(SeekFrom::Start(
-            self.offset + if self.is_zipped { 8 } else { 0 },
-        ))?;
+    pub fn raw_bytes<R: Read + Seek>(&self, pack_file_stream: &mut R) -> Result<Vec<u8>> {
+        let (offset, length): (u64, u64) = self.raw_range();
+        pack_file_stream.seek(SeekFrom::Start(offset))?;
 
-        read_big_x_bytes(pack_file_stream, self.data_length as usize)
+        read_big_x_bytes(pack_file_stream, length as usize)
     }
 
-    pub fn extract_text(&self, pack_file_stream: &mut File) -> Result<String> {
+    pub fn extract_text<R: Read + Seek>(&self, pack_file_stream: &mut R) -> Result<String> {
         let raw_bytes: Vec<u8> = self.raw_bytes(pack_file_stream)?;
         if self.is_zipped {
             let mut d = ZlibDecoder::new(raw_bytes.as_slice());
@@ -497,6 +1198,358 @@ impl Asset {
             }
         }
     }
+
+    /// the (uncompressed) asset data as a stream instead of a `Vec`.
+    /// compressed assets get decompressed while reading, at most `unzipped_length` bytes are returned.
+    pub fn open<'a, R: Read + Seek>(&self, pack_file_stream: &'a mut R) -> Result<impl Read + 'a> {
+        self.open_owned(pack_file_stream)
+    }
+
+    /// same as `open`, but takes ownership of the reader (for example a freshly opened `File`)
+    pub fn open_owned<R: Read + Seek>(&self, mut pack_file_stream: R) -> Result<impl Read> {
+        let (offset, length): (u64, u64) = self.raw_range();
+        pack_file_stream.seek(SeekFrom::Start(offset))?;
+        let raw: std::io::Take<R> = pack_file_stream.take(length);
+        Ok(if self.is_zipped {
+            AssetReader::Zipped(
+                ZlibDecoder::new(BufReader::new(raw)).take(self.unzipped_length as u64),
+            )
+        } else {
+            AssetReader::Raw(raw)
+        })
+    }
+
+    pub fn uncompressed_length(&self) -> u64 {
+        if self.is_zipped {
+            self.unzipped_length as u64
+        } else {
+            self.data_length
+        }
+    }
+
+    /// guess the type from the start of the (uncompressed) data
+    pub fn sniff<R: Read + Seek>(&self, pack_file_stream: &mut R) -> Result<FileType> {
+        let mut head: Vec<u8> = Vec::with_capacity(SNIFF_LENGTH);
+        self.open(pack_file_stream)?
+            .take(SNIFF_LENGTH as u64)
+            .read_to_end(&mut head)?;
+        Ok(crate::sniff::sniff(&head))
+    }
+
+    /// crc32 (the same one zlib uses) of the uncompressed data.
+    /// that this is what the game stores in `data_hash` is an assumption (`Pack2Writer` writes it that way),
+    /// it has not been checked against files from the game.
+    pub fn calculate_data_hash<R: Read + Seek>(&self, pack_file_stream: &mut R) -> Result<u32> {
+        let mut asset_reader = self.open(pack_file_stream)?;
+        let mut hasher: crc32fast::Hasher = crc32fast::Hasher::new();
+        let mut buf: [u8; 8192] = [0; 8192];
+        loop {
+            let read: usize = asset_reader.read(&mut buf)?;
+            if read == 0 {
+                return Ok(hasher.finalize());
+            }
+            hasher.update(&buf[..read]);
+        }
+    }
+
+    /// check if the data still matches `data_hash` (see `calculate_data_hash` for why this is unverified)
+    pub fn verify<R: Read + Seek>(&self, pack_file_stream: &mut R) -> Result<bool> {
+        Ok(self.calculate_data_hash(pack_file_stream)? == self.data_hash)
+    }
+}
+
+/// A asset found by `Pack2::find_corrupted_assets`
+#[derive(Debug)]
+pub struct CorruptedAsset {
+    /// index in `Pack2.assets`
+    pub index: usize,
+    /// `None` if the data could not be read or decompressed
+    pub found_data_hash: Option<u32>,
+}
+
+fn build_asset_index(assets: &[Asset]) -> HashMap<u64, usize> {
+    let mut asset_index: HashMap<u64, usize> = HashMap::with_capacity(assets.len());
+    for (index, asset) in assets.iter().enumerate() {
+        asset_index.entry(asset.name_hash).or_insert(index);
+    }
+    asset_index
+}
+
+/// returned by `Asset::open`
+enum AssetReader<R: Read> {
+    Raw(std::io::Take<R>),
+    Zipped(std::io::Take<ZlibDecoder<BufReader<std::io::Take<R>>>>),
+}
+
+impl<R: Read> Read for AssetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            AssetReader::Raw(r) => r.read(buf),
+            AssetReader::Zipped(r) => r.read(buf),
+        }
+    }
+}
+
+/// the name hashes `name_or_hash` could refer to (see `Pack2::find_asset`), most likely first
+pub fn candidate_name_hashes(name_or_hash: &str) -> Vec<u64> {
+    let hex_hash: Option<u64> = name_or_hash
+        .strip_prefix("0x")
+        .and_then(|i| u64::from_str_radix(i, 16).ok());
+    [
+        Some(crc64::convert_filename(name_or_hash)),
+        hex_hash,
+        name_or_hash.parse::<u64>().ok(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// `crc_64_<name_hash>` with the extension of the detected type (if any)
+pub fn unnamed_filename(name_hash: u64, file_type: FileType) -> String {
+    match file_type.extension() {
+        Some(extension) => format!("crc_64_{name_hash}.{extension}"),
+        None => format!("crc_64_{name_hash}"),
+    }
+}
+
+/// Where `extract_all_*` put the files inside the output directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ExtractLayout {
+    /// everything directly in the output directory
+    #[default]
+    Flat,
+    /// `dds/`, `dme/`, ... (`no_extension/` for files without one)
+    ByExtension,
+    /// by the detected content type (`dds/`, `text/`, `unknown/`, ...)
+    ByType,
+    /// by the part of the name before the first `_` (`Weapon/`, `Amerish/`, ...).
+    /// names without one go into `other/`, unnamed assets into `unnamed/`.
+    ByNamePrefix,
+    /// `<pack name>/` (for example `assets_x64_0`)
+    ByPack(String),
+}
+
+impl ExtractLayout {
+    /// path relative to the output directory
+    /// `name` is the (safe) asset name, `None` for unnamed assets
+    pub fn relative_path(
+        &self,
+        name: Option<&str>,
+        filename: &str,
+        file_type: FileType,
+    ) -> PathBuf {
+        let directory: &str = match self {
+            ExtractLayout::Flat => return PathBuf::from(filename),
+            ExtractLayout::ByExtension => {
+                return match Path::new(filename).extension().and_then(|i| i.to_str()) {
+                    Some(extension) => Path::new(&extension.to_lowercase()).join(filename),
+                    None => Path::new("no_extension").join(filename),
+                };
+            }
+            ExtractLayout::ByType => file_type.name(),
+            ExtractLayout::ByNamePrefix => match name {
+                Some(name) => match name.split_once('_') {
+                    Some((prefix, _)) if !prefix.is_empty() => prefix,
+                    _ => "other",
+                },
+                None => "unnamed",
+            },
+            ExtractLayout::ByPack(pack_name) => pack_name,
+        };
+        Path::new(directory).join(filename)
+    }
+
+    /// all directories files might have been extracted to
+    pub fn possible_directories(&self, output_directory: &Path) -> Result<Vec<PathBuf>> {
+        match self {
+            ExtractLayout::Flat => Ok(vec![output_directory.to_path_buf()]),
+            ExtractLayout::ByPack(pack_name) => {
+                let directory: PathBuf = output_directory.join(pack_name);
+                Ok(if directory.is_dir() {
+                    vec![directory]
+                } else {
+                    vec![]
+                })
+            }
+            _ => {
+                let mut directories: Vec<PathBuf> = Vec::new();
+                for entry in std::fs::read_dir(output_directory)? {
+                    let path: PathBuf = entry?.path();
+                    if path.is_dir() {
+                        directories.push(path);
+                    }
+                }
+                Ok(directories)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "manifests")]
+/// `None` if the asset did not change since the manifest, the policy for its file otherwise
+fn incremental_on_conflict(
+    previous: &HashMap<u64, u32>,
+    asset: &Asset,
+    on_conflict: OnConflict,
+) -> Option<OnConflict> {
+    match previous.get(&asset.name_hash) {
+        Some(data_hash) if *data_hash == asset.data_hash => None,
+        // the existing file is outdated
+        Some(_) => Some(OnConflict::Overwrite),
+        None => Some(on_conflict),
+    }
+}
+
+/// Where the extract functions read asset data from.
+/// Any `Read + Seek` (`File`, `Cursor`, ..) is one, `MappedPack2` decompresses straight from the mapping.
+pub trait AssetSource {
+    fn sniff_asset(&mut self, asset: &Asset) -> Result<FileType>;
+
+    fn extract_asset_to_file(
+        &mut self,
+        asset: &Asset,
+        output_file_steam: &mut dyn Write,
+    ) -> Result<()>;
+}
+
+impl<R: Read + Seek> AssetSource for R {
+    fn sniff_asset(&mut self, asset: &Asset) -> Result<FileType> {
+        asset.sniff(self)
+    }
+
+    fn extract_asset_to_file(
+        &mut self,
+        asset: &Asset,
+        output_file_steam: &mut dyn Write,
+    ) -> Result<()> {
+        asset.extract_to_file(self, output_file_steam)
+    }
+}
+
+/// for when the source is only known at runtime
+impl AssetSource for Box<dyn AssetSource + '_> {
+    fn sniff_asset(&mut self, asset: &Asset) -> Result<FileType> {
+        (**self).sniff_asset(asset)
+    }
+
+    fn extract_asset_to_file(
+        &mut self,
+        asset: &Asset,
+        output_file_steam: &mut dyn Write,
+    ) -> Result<()> {
+        (**self).extract_asset_to_file(asset, output_file_steam)
+    }
+}
+
+fn extract_assets<'a, R: AssetSource + ?Sized>(
+    br: &mut R,
+    assets: impl Iterator<Item = (&'a Asset, OnConflict)>,
+    output_directory: &Path,
+    layout: &ExtractLayout,
+) -> Result<ExtractReport> {
+    assets.into_iter().try_fold(
+        ExtractReport::default(),
+        |report, (asset, on_conflict)| -> Result<ExtractReport> {
+            Ok(report.merge(extract_into_directory(
+                asset,
+                br,
+                output_directory,
+                layout,
+                on_conflict,
+            )?))
+        },
+    )
+}
+
+/// named assets keep their name, unnamed ones (and ones with unsafe names) get a `unnamed_filename`
+pub(crate) fn extract_into_directory<R: AssetSource + ?Sized>(
+    asset: &Asset,
+    br: &mut R,
+    output_directory: &Path,
+    layout: &ExtractLayout,
+    on_conflict: OnConflict,
+) -> Result<ExtractReport> {
+    let mut report: ExtractReport = ExtractReport::default();
+    let name: Option<&str> = match asset.name {
+        Some(ref name) if is_safe_name(name) => Some(name),
+        Some(ref name) => {
+            log::warn!(
+                "{name:?} can't be used as a filename, extracting 0x{:X} without its name",
+                asset.name_hash
+            );
+            report.rejected_names.push(name.clone());
+            None
+        }
+        None => None,
+    };
+    let file_type: FileType = match asset.detected_type {
+        Some(file_type) => file_type,
+        None if name.is_none() || *layout == ExtractLayout::ByType => br.sniff_asset(asset)?,
+        None => FileType::Unknown,
+    };
+    let filename: String = match name {
+        Some(name) => name.to_string(),
+        None => unnamed_filename(asset.name_hash, file_type),
+    };
+    let fp: PathBuf = output_directory.join(layout.relative_path(name, &filename, file_type));
+    match name {
+        Some(name) => log::info!("extracting {name} to {fp:?}"),
+        None => {
+            log::info!("extracting 0x{:X} to {fp:?}", asset.name_hash);
+            if on_conflict == OnConflict::Overwrite && *layout == ExtractLayout::Flat {
+                // from before unnamed assets got an extension
+                let old_fp: PathBuf = output_directory.join(format!("crc_64_{}", asset.name_hash));
+                if old_fp != fp && old_fp.exists() {
+                    std::fs::remove_file(&old_fp)?;
+                }
+            }
+        }
+    }
+    if let Some(parent) = fp.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match create_output_file(
+        &fp,
+        on_conflict,
+        asset.uncompressed_length(),
+        asset.data_hash,
+    )? {
+        Some(mut fos) => {
+            br.extract_asset_to_file(asset, &mut fos)?;
+            report.extracted += 1;
+        }
+        None => {
+            log::info!("skipping {fp:?} (already exists)");
+            report.skipped += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// returns the uncompressed length
+fn read_zip_header(zip_header: &mut &[u8]) -> std::result::Result<u32, Nups2Error> {
+    let zip_magic: u32 = read_u32_be(zip_header)?;
+    if zip_magic != COMPRESSION_MAGIC {
+        return Err(Nups2Error::BadMagic {
+            format: "pack2 asset compression",
+            expected: COMPRESSION_MAGIC,
+            found: zip_magic,
+        });
+    }
+    Ok(read_u32_be(zip_header)?)
+}
+
+fn slice_at(data: &[u8], offset: u64, length: u64) -> std::result::Result<&[u8], Nups2Error> {
+    usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(length).ok())
+        .and_then(|(start, length)| data.get(start..start.checked_add(length)?))
+        .ok_or(Nups2Error::OffsetOutOfBounds {
+            offset,
+            length,
+            file_length: data.len() as u64,
+        })
 }
 
 impl Display for Asset {
@@ -510,3 +1563,206 @@ impl Display for Asset {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{unnamed_filename, ExtractLayout, Pack2};
+    use crate::sniff::FileType;
+    use crate::Nups2Error;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    #[test]
+    fn malformed_pack2_errors() {
+        assert!(matches!(
+            Pack2::load_from_file(&mut Cursor::new(b"PAK".to_vec())),
+            Err(Nups2Error::Truncated { offset: 0 })
+        ));
+        assert!(matches!(
+            Pack2::load_from_slice(&[0u8; 24]),
+            Err(Nups2Error::BadMagic { found: 0, .. })
+        ));
+
+        // 1 asset, map at offset 0x100 -> outside of the 24 byte file
+        let mut header: Vec<u8> = vec![0x50, 0x41, 0x4b, 0x01, 1, 0, 0, 0];
+        header.extend_from_slice(&24u64.to_le_bytes());
+        header.extend_from_slice(&0x100u64.to_le_bytes());
+        assert!(matches!(
+            Pack2::load_from_file(&mut Cursor::new(header)),
+            Err(Nups2Error::OffsetOutOfBounds {
+                offset: 0x100,
+                length: 32,
+                file_length: 24
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_pack2() {
+        use super::Pack2Issue;
+        use crate::pack2_writer::Pack2Writer;
+
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("a.txt", b"aaaa".to_vec())
+            .add_bytes("b.txt", b"bbbb".to_vec());
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut data: Vec<u8> = br.into_inner();
+        assert_eq!(Pack2::load_from_slice(&data).unwrap().validate(), vec![]);
+
+        data.extend_from_slice(&[0u8; 4]);
+        let pack2: Pack2 = Pack2::load_from_slice(&data).unwrap();
+        assert_eq!(
+            pack2.validate(),
+            vec![Pack2Issue::LengthMismatch {
+                header_length: data.len() as u64 - 4,
+                file_length: data.len() as u64,
+            }]
+        );
+    }
+
+    #[test]
+    fn verify_data_hash() {
+        use crate::pack2_writer::Pack2Writer;
+
+        let mut writer = Pack2Writer::new();
+        writer.set_compression(false).add_bytes(
+            "a.txt",
+            b"The quick brown fox jumps over the lazy dog".to_vec(),
+        );
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+        assert_eq!(pack2.assets[0].data_hash, 0x414fa339);
+        assert!(pack2.assets[0].verify(&mut br).unwrap());
+
+        let offset: usize = pack2.assets[0].offset as usize;
+        br.get_mut()[offset] = b't';
+        assert!(!pack2.assets[0].verify(&mut br).unwrap());
+    }
+
+    #[test]
+    fn open_asset_stream() {
+        use crate::pack2_writer::Pack2Writer;
+        use std::io::Read;
+
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("zipped.txt", b"hello world".repeat(100))
+            .add_bytes("empty.txt", Vec::new());
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+
+        let mut buf: Vec<u8> = Vec::new();
+        let asset = pack2.find_asset("zipped.txt").unwrap();
+        assert!(asset.is_zipped);
+        asset.open(&mut br).unwrap().read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"hello world".repeat(100));
+
+        buf.clear();
+        let asset = pack2.find_asset("empty.txt").unwrap();
+        asset.open(&mut br).unwrap().read_to_end(&mut buf).unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn compressed_asset_at_end_of_file() {
+        use crate::pack2_writer::Pack2Writer;
+
+        let mut writer = Pack2Writer::new();
+        writer.add_bytes("zipped.txt", b"hello world".repeat(100));
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut data: Vec<u8> = br.into_inner();
+
+        // move the map into the header padding, so the compressed data ends the file
+        let map_offset: usize = u64::from_le_bytes(data[16..24].try_into().unwrap()) as usize;
+        let map: Vec<u8> = data.split_off(map_offset);
+        data[0x100..0x100 + map.len()].copy_from_slice(&map);
+        let length: u64 = data.len() as u64;
+        data[8..16].copy_from_slice(&length.to_le_bytes());
+        data[16..24].copy_from_slice(&0x100u64.to_le_bytes());
+
+        let pack2: Pack2 = Pack2::load_from_slice(&data).unwrap();
+        let asset = pack2.find_asset("zipped.txt").unwrap();
+        assert!(asset.is_zipped);
+        assert_eq!(asset.offset + asset.data_length, length);
+        assert_eq!(
+            asset.extract_bytes_from_slice(&data).unwrap().as_ref(),
+            b"hello world".repeat(100)
+        );
+        assert_eq!(
+            asset.extract_bytes(&mut Cursor::new(&data)).unwrap(),
+            b"hello world".repeat(100)
+        );
+    }
+
+    #[test]
+    fn extract_unsafe_names_and_conflicts() {
+        use crate::extract_utils::{ExtractReport, OnConflict};
+        use crate::pack2_writer::Pack2Writer;
+
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("../evil.txt", b"evil".to_vec())
+            .add_bytes("good.txt", b"good".to_vec());
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+        pack2.apply_filename_list(&["../evil.txt".into(), "good.txt".into()]);
+
+        let root: PathBuf = std::env::temp_dir().join(format!("nups2_test_{}", std::process::id()));
+        let output_directory: PathBuf = root.join("out");
+        let _ = std::fs::remove_dir_all(&root);
+        let extract = |pack2: &Pack2, br: &mut Cursor<Vec<u8>>, on_conflict: OnConflict| {
+            pack2.extract_all_named(br, &output_directory, &ExtractLayout::Flat, on_conflict)
+        };
+        let report: ExtractReport = extract(&pack2, &mut br, OnConflict::Error).unwrap();
+        assert_eq!(report.extracted, 2);
+        assert_eq!(report.rejected_names, vec![String::from("../evil.txt")]);
+        assert!(!root.join("evil.txt").exists());
+        let hash: u64 = crate::crc64::convert_filename("../evil.txt");
+        assert!(output_directory
+            .join(unnamed_filename(hash, FileType::Text))
+            .exists());
+
+        assert!(extract(&pack2, &mut br, OnConflict::Error).is_err());
+        let report: ExtractReport = extract(&pack2, &mut br, OnConflict::SkipIfIdentical).unwrap();
+        assert_eq!((report.extracted, report.skipped), (0, 2));
+        std::fs::write(output_directory.join("good.txt"), b"bad!").unwrap();
+        let report: ExtractReport = extract(&pack2, &mut br, OnConflict::SkipIfIdentical).unwrap();
+        assert_eq!((report.extracted, report.skipped), (1, 1));
+        assert_eq!(
+            std::fs::read(output_directory.join("good.txt")).unwrap(),
+            b"good"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn keep_raw_header_and_flags() {
+        use super::HEADER_LENGTH;
+        use crate::pack2_writer::Pack2Writer;
+
+        let mut writer = Pack2Writer::new();
+        writer.add_bytes("a.txt", b"aaaa".to_vec());
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut data: Vec<u8> = br.into_inner();
+        data[24..28].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+        data[100] = 0x42;
+        // flags of the only map entry
+        let flags_offset: usize = data.len() - 8;
+        data[flags_offset..flags_offset + 4].copy_from_slice(&0x11u32.to_le_bytes());
+
+        let pack2: Pack2 = Pack2::load_from_slice(&data).unwrap();
+        assert_eq!(pack2.header().checksum, 0xDEADBEEF);
+        assert_eq!(pack2.header().unknown[100 - 28], 0x42);
+        assert_eq!(pack2.header().to_bytes()[..], data[..HEADER_LENGTH]);
+        assert_eq!(pack2.assets[0].flags, 0x11);
+        assert!(pack2.assets[0].is_zipped);
+        assert_eq!(pack2.flag_counts(), vec![(0x11, 1)]);
+    }
+}
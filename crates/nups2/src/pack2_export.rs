@@ -0,0 +1,245 @@
+use crate::bin_utils::CountingReader;
+use crate::extract_utils::{is_safe_name, ExtractReport};
+use crate::pack2::{unnamed_filename, Asset, ExtractLayout, Pack2};
+use crate::sniff::FileType;
+use std::io::{Read, Result, Seek, Write};
+use std::path::PathBuf;
+
+/// name of the (optional) json index inside of the archive
+pub const INDEX_FILENAME: &str = "nups2_index.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Tar,
+    /// deflate compressed
+    Zip,
+}
+
+/// Writes the assets of a pack2 file into a tar or zip archive.
+///
+/// Assets are streamed from the pack2 file into the archive (compressed ones get decompressed
+/// on the way), nothing is written to disk first.
+/// Paths inside of the archive are the same `extract_all_*` would use.
+#[derive(Debug)]
+pub struct Pack2Exporter {
+    format: ExportFormat,
+    layout: ExtractLayout,
+    include_index: bool,
+}
+
+impl Pack2Exporter {
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            layout: ExtractLayout::Flat,
+            include_index: false,
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: ExtractLayout) -> &mut Self {
+        self.layout = layout;
+        self
+    }
+
+    /// add `INDEX_FILENAME` with the path and `Asset::to_json` of every exported asset
+    #[cfg(feature = "json")]
+    pub fn set_include_index(&mut self, include_index: bool) -> &mut Self {
+        self.include_index = include_index;
+        self
+    }
+
+    pub fn export<R: Read + Seek, W: Write + Seek>(
+        &self,
+        pack2: &Pack2,
+        br: &mut R,
+        target: W,
+    ) -> Result<ExtractReport> {
+        let mut archive: ArchiveWriter<W> = match self.format {
+            ExportFormat::Tar => ArchiveWriter::Tar(tar::Builder::new(target)),
+            ExportFormat::Zip => ArchiveWriter::Zip(Box::new(zip::ZipWriter::new(target))),
+        };
+        let mut report: ExtractReport = ExtractReport::default();
+        #[allow(unused_mut, unused_variables)]
+        let mut index: Vec<String> = Vec::new();
+
+        for asset in pack2.assets.iter() {
+            let path: String = self.archive_path(asset, br, &mut report)?;
+            log::info!("exporting 0x{:X} as {path}", asset.name_hash);
+            archive.add(&path, asset.uncompressed_length(), &mut asset.open(br)?)?;
+            report.extracted += 1;
+            #[cfg(feature = "json")]
+            if self.include_index {
+                index.push(format!(
+                    r#"{{"path":{},"asset":{}}}"#,
+                    crate::json_utils::escape_string(&path),
+                    asset.to_json()
+                ));
+            }
+        }
+
+        if self.include_index {
+            let index: Vec<u8> = format!("[{}]", index.join(",")).into_bytes();
+            archive.add(INDEX_FILENAME, index.len() as u64, &mut &index[..])?;
+        }
+        archive.finish()?;
+        Ok(report)
+    }
+
+    /// `/` separated, same rules as `extract_all_*` (unsafe names are replaced by the hash)
+    fn archive_path<R: Read + Seek>(
+        &self,
+        asset: &Asset,
+        br: &mut R,
+        report: &mut ExtractReport,
+    ) -> Result<String> {
+        let name: Option<&str> = match asset.name {
+            Some(ref name) if is_safe_name(name) => Some(name),
+            Some(ref name) => {
+                log::warn!(
+                    "{name:?} can't be used as a filename, exporting 0x{:X} without its name",
+                    asset.name_hash
+                );
+                report.rejected_names.push(name.clone());
+                None
+            }
+            None => None,
+        };
+        let file_type: FileType = match asset.detected_type {
+            Some(file_type) => file_type,
+            None if name.is_none() || self.layout == ExtractLayout::ByType => asset.sniff(br)?,
+            None => FileType::Unknown,
+        };
+        let filename: String = match name {
+            Some(name) => name.to_string(),
+            None => unnamed_filename(asset.name_hash, file_type),
+        };
+        let path: PathBuf = self.layout.relative_path(name, &filename, file_type);
+        Ok(path
+            .iter()
+            .map(|i| i.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+}
+
+enum ArchiveWriter<W: Write + Seek> {
+    Tar(tar::Builder<W>),
+    /// boxed because it is a lot bigger than the tar builder
+    Zip(Box<zip::ZipWriter<W>>),
+}
+
+impl<W: Write + Seek> ArchiveWriter<W> {
+    fn add(&mut self, path: &str, size: u64, data: &mut dyn Read) -> Result<()> {
+        match self {
+            ArchiveWriter::Tar(builder) => {
+                let mut header: tar::Header = tar::Header::new_gnu();
+                header.set_size(size);
+                header.set_mode(0o644);
+                header.set_entry_type(tar::EntryType::Regular);
+                // the size is in the header before the data, so it has to be exact
+                let mut counting: CountingReader = CountingReader::new(data);
+                builder.append_data(&mut header, path, (&mut counting).take(size))?;
+                let copied: u64 = counting.position();
+                let too_long: bool = copied == size && data.read(&mut [0u8; 1])? != 0;
+                if copied != size || too_long {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "{path}: expected {size} bytes, got {}",
+                            if too_long {
+                                "more".to_string()
+                            } else {
+                                copied.to_string()
+                            }
+                        ),
+                    ));
+                }
+                Ok(())
+            }
+            ArchiveWriter::Zip(writer) => {
+                let options: zip::write::SimpleFileOptions =
+                    zip::write::SimpleFileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated)
+                        .large_file(size >= u32::MAX as u64);
+                writer
+                    .start_file(path, options)
+                    .map_err(std::io::Error::other)?;
+                std::io::copy(data, writer)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ArchiveWriter::Tar(builder) => builder.into_inner()?.flush(),
+            ArchiveWriter::Zip(writer) => {
+                (*writer).finish().map_err(std::io::Error::other)?.flush()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveWriter, ExportFormat, Pack2Exporter};
+    use crate::pack2::Pack2;
+    use crate::pack2_writer::Pack2Writer;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn export_tar_and_zip() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("a.txt", b"hello world".repeat(10))
+            .add_raw(1234, b"DDS |".to_vec());
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+        pack2.apply_filename_list(&["a.txt".into()]);
+
+        let mut tar: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        Pack2Exporter::new(ExportFormat::Tar)
+            .export(&pack2, &mut br, &mut tar)
+            .unwrap();
+        tar.set_position(0);
+        let mut archive = tar::Archive::new(tar);
+        let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut data: Vec<u8> = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            entries.push((entry.path().unwrap().to_string_lossy().into_owned(), data));
+        }
+        assert_eq!(
+            entries,
+            vec![
+                (String::from("crc_64_1234.dds"), b"DDS |".to_vec()),
+                (String::from("a.txt"), b"hello world".repeat(10)),
+            ]
+        );
+
+        let mut zip: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        Pack2Exporter::new(ExportFormat::Zip)
+            .export(&pack2, &mut br, &mut zip)
+            .unwrap();
+        let mut archive = zip::ZipArchive::new(zip).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        archive
+            .by_name("a.txt")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"hello world".repeat(10));
+    }
+
+    #[test]
+    fn tar_rejects_wrong_size() {
+        let mut tar = ArchiveWriter::Tar(tar::Builder::new(Cursor::new(Vec::new())));
+        tar.add("ok.txt", 5, &mut &b"hello"[..]).unwrap();
+        let short = tar.add("short.txt", 6, &mut &b"hello"[..]).unwrap_err();
+        assert_eq!(short.kind(), std::io::ErrorKind::InvalidData);
+        let long = tar.add("long.txt", 4, &mut &b"hello"[..]).unwrap_err();
+        assert_eq!(long.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -1,104 +1,599 @@
-use std::collections::HashMap;
-use std::{fs::File, io::ErrorKind, io::Result, path::Path};
-
-use crate::bin_utils::{read_u32_be, read_u64_be};
-
-/// Manifest file format:
-///   12bytes per asset, no seperator:
-///     8bits: u64_BE filename_hash (from the pack2 index)
-///     4bits: u32_BE data_hash (from the pack2 index)
-pub fn read_manifest_file(manifest_file: &Path) -> Result<Manifest> {
-    let mut br: File = File::open(manifest_file)?;
-    let mut manifest_assets: Manifest = Vec::new();
-    'asset_loop: loop {
-        match read_u64_be(&mut br) {
-            Ok(name_hash) => {
-                let data_hash: u32 = read_u32_be(&mut br)?;
-                manifest_assets.push((name_hash, data_hash));
+use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::{fs::File, path::Path};
+
+use crate::bin_utils::{read_u32_be, read_u64_be, read_x_bytes, write_u32_be, CountingReader};
+use crate::cli_utils::humanise_bytes;
+use crate::Nups2Error;
+
+/// "N2MF", at the start of v2 manifests (v1 manifests have no header)
+pub const MANIFEST_MAGIC: u32 = 0x4E324D46;
+pub const MANIFEST_VERSION: u32 = 2;
+/// for the strings in the header (anything longer is most likely not a manifest)
+pub(crate) const MAX_STRING_LENGTH: u32 = 0xFFFF;
+
+/// The assets of a pack2 file at one point in time (for diffs between updates, incremental extraction, etc).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// `None` for v1 manifests (they have no header)
+    pub header: Option<ManifestHeader>,
+    pub records: Vec<ManifestRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestHeader {
+    pub version: u32,
+    /// filename of the pack2 file
+    pub pack_name: String,
+    /// unix timestamp (seconds)
+    pub created: u64,
+    pub game_build: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManifestRecord {
+    pub name_hash: u64,
+    pub data_hash: u32,
+    /// `None` in v1 manifests (same for the other fields below)
+    pub uncompressed_length: Option<u64>,
+    /// the length within the pack2 file
+    pub compressed_length: Option<u64>,
+    pub flags: Option<u32>,
+}
+
+impl Manifest {
+    /// a v2 manifest of the current state of `pack2`
+    pub fn from_pack2(
+        pack2: &crate::pack2::Pack2,
+        pack_name: &str,
+        game_build: Option<String>,
+    ) -> Self {
+        Self {
+            header: Some(ManifestHeader {
+                version: MANIFEST_VERSION,
+                pack_name: pack_name.to_string(),
+                created: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|i| i.as_secs())
+                    .unwrap_or(0),
+                game_build,
+            }),
+            records: pack2
+                .assets
+                .iter()
+                .map(|asset| ManifestRecord {
+                    name_hash: asset.name_hash,
+                    data_hash: asset.data_hash,
+                    uncompressed_length: Some(asset.uncompressed_length()),
+                    compressed_length: Some(asset.data_length),
+                    flags: Some(asset.flags),
+                })
+                .collect(),
+        }
+    }
+
+    /// name_hash -> data_hash
+    pub fn data_hashes(&self) -> HashMap<u64, u32> {
+        self.records
+            .iter()
+            .map(|record| (record.name_hash, record.data_hash))
+            .collect()
+    }
+
+    /// see `read_manifest_file` for the format
+    pub fn read(br: &mut dyn Read) -> std::result::Result<Self, Nups2Error> {
+        let mut br: CountingReader = CountingReader::new(br);
+        Self::internal_read(&mut br).map_err(|e| e.truncated_at(br.position()))
+    }
+
+    fn internal_read(br: &mut CountingReader) -> std::result::Result<Self, Nups2Error> {
+        let mut start: [u8; 8] = [0; 8];
+        let mut start_length: usize = 0;
+        while start_length < start.len() {
+            match br.read(&mut start[start_length..])? {
+                0 => break,
+                read => start_length += read,
             }
-            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
-                break 'asset_loop;
+        }
+        if start_length == 8 && start[..4] == MANIFEST_MAGIC.to_be_bytes() {
+            let version: u32 = u32::from_be_bytes([start[4], start[5], start[6], start[7]]);
+            if version != MANIFEST_VERSION {
+                return Err(Nups2Error::UnsupportedVersion {
+                    format: "manifest",
+                    version,
+                });
             }
-            Err(e) => {
-                return Err(e);
+            return read_v2(br);
+        }
+        read_v1(&mut (&start[..start_length]).chain(br))
+    }
+
+    /// v2, or v1 if there is no header
+    pub fn write(&self, bw: &mut dyn Write) -> std::io::Result<()> {
+        let header: &ManifestHeader = match self.header {
+            Some(ref header) => header,
+            None => {
+                for record in self.records.iter() {
+                    bw.write_all(&record.name_hash.to_be_bytes())?;
+                    bw.write_all(&record.data_hash.to_be_bytes())?;
+                }
+                return Ok(());
             }
+        };
+        write_u32_be(MANIFEST_MAGIC, bw)?;
+        write_u32_be(MANIFEST_VERSION, bw)?;
+        bw.write_all(&header.created.to_be_bytes())?;
+        write_string(&header.pack_name, bw)?;
+        write_string(header.game_build.as_deref().unwrap_or(""), bw)?;
+        write_count(self.records.len(), "records", bw)?;
+        for record in self.records.iter() {
+            bw.write_all(&record.name_hash.to_be_bytes())?;
+            bw.write_all(&record.data_hash.to_be_bytes())?;
+            bw.write_all(&record.uncompressed_length.unwrap_or(0).to_be_bytes())?;
+            bw.write_all(&record.compressed_length.unwrap_or(0).to_be_bytes())?;
+            write_u32_be(record.flags.unwrap_or(0), bw)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, manifest_file: &Path) -> std::io::Result<()> {
+        let mut bw: BufWriter<File> = BufWriter::new(File::create_new(manifest_file)?);
+        self.write(&mut bw)?;
+        bw.flush()
+    }
+
+    #[cfg(feature = "json")]
+    pub fn header_to_json(&self) -> String {
+        use crate::json_utils::escape_string;
+
+        match self.header {
+            Some(ref header) => format!(
+                r#"{{"version":{},"pack_name":{},"created":{},"game_build":{},"record_count":{}}}"#,
+                header.version,
+                escape_string(&header.pack_name),
+                header.created,
+                header
+                    .game_build
+                    .as_deref()
+                    .map(escape_string)
+                    .unwrap_or(String::from("null")),
+                self.records.len()
+            ),
+            None => format!(
+                r#"{{"version":1,"pack_name":null,"created":null,"game_build":null,"record_count":{}}}"#,
+                self.records.len()
+            ),
+        }
+    }
+
+    pub fn describe_header(&self) -> String {
+        match self.header {
+            Some(ref header) => format!(
+                "Manifest v{}\n  pack: {}\n  created: {}\n  game build: {}\n  records: {}",
+                header.version,
+                header.pack_name,
+                crate::cli_utils::format_unix_timestamp(header.created),
+                header.game_build.as_deref().unwrap_or("unknown"),
+                self.records.len()
+            ),
+            None => format!("Manifest v1 (no header)\n  records: {}", self.records.len()),
         }
     }
-    Ok(manifest_assets)
 }
 
-pub fn render_for_humans(
-    manifest_diff: &ManifestDiff,
-    name_hash_lookup_table: &HashMap<u64, String>,
-) -> String {
-    let mut out: Vec<String> = Vec::new();
-    let mut already_included: Vec<u64> = Vec::new();
-    'diff_entry_loop: for d in manifest_diff {
-        if d.new_data_hash.is_none() {
-            continue;
+/// (name_hash, data_hash) pairs, like a v1 manifest
+impl From<Vec<(u64, u32)>> for Manifest {
+    fn from(value: Vec<(u64, u32)>) -> Self {
+        Self {
+            header: None,
+            records: value
+                .into_iter()
+                .map(|(name_hash, data_hash)| ManifestRecord {
+                    name_hash,
+                    data_hash,
+                    uncompressed_length: None,
+                    compressed_length: None,
+                    flags: None,
+                })
+                .collect(),
         }
-        if d.old_data_hash.is_none() {
-            for i in manifest_diff {
-                if i.old_data_hash.eq(&Some(d.new_data_hash.unwrap())) {
-                    already_included.push(i.name_hash);
-                    out.push(format!(
-                        "Renamed file: 0x{:X} ({}) -> 0x{:X} ({})",
-                        i.name_hash,
-                        name_hash_lookup_table
-                            .get(&i.name_hash)
-                            .unwrap_or(&String::from("?")),
-                        d.name_hash,
-                        name_hash_lookup_table
-                            .get(&d.name_hash)
-                            .unwrap_or(&String::from("?")),
-                    ));
-                    continue 'diff_entry_loop;
-                }
-            }
-            out.push(format!(
-                "Created file: 0x{:X} ({}) with data_hash 0x{:X}",
-                d.name_hash,
-                name_hash_lookup_table
-                    .get(&d.name_hash)
-                    .unwrap_or(&String::from("?")),
-                d.new_data_hash.unwrap()
-            ));
-            continue 'diff_entry_loop;
+    }
+}
+
+/// Manifest file format (all numbers are big endian):
+///   v2:
+///     header:
+///       u32 magic (`MANIFEST_MAGIC`)
+///       u32 version (2)
+///       u64 creation time (unix timestamp)
+///       u32 length + utf8 filename of the pack2 file
+///       u32 length + utf8 game build (empty if unknown)
+///       u32 record count
+///     32 bytes per asset:
+///       u64 name_hash, u32 data_hash (both from the pack2 map),
+///       u64 uncompressed length, u64 length in the pack2 file, u32 flags (from the map)
+///   v1 (no header):
+///     12 bytes per asset, no seperator:
+///       u64 name_hash, u32 data_hash
+pub fn read_manifest_file(manifest_file: &Path) -> std::result::Result<Manifest, Nups2Error> {
+    let mut br: BufReader<File> = BufReader::new(File::open(manifest_file)?);
+    Manifest::read(&mut br)
+}
+
+fn read_v1(br: &mut dyn Read) -> std::result::Result<Manifest, Nups2Error> {
+    let mut manifest_assets: Vec<(u64, u32)> = Vec::new();
+    let mut record: [u8; 12] = [0; 12];
+    'asset_loop: loop {
+        let record_offset: u64 = manifest_assets.len() as u64 * 12;
+        match br.read(&mut record[..1])? {
+            0 => break 'asset_loop,
+            _ => br
+                .read_exact(&mut record[1..])
+                .map_err(|e| Nups2Error::from_io(e, record_offset))?,
         }
-        out.push(format!(
-            "Changed file: 0x{:X} ({}): 0x{:X} -> 0x{:X}",
-            d.name_hash,
-            name_hash_lookup_table
-                .get(&d.name_hash)
-                .unwrap_or(&String::from("?")),
-            d.old_data_hash.unwrap(),
-            d.new_data_hash.unwrap(),
+        let mut record: &[u8] = &record;
+        let name_hash: u64 = read_u64_be(&mut record)?;
+        let data_hash: u32 = read_u32_be(&mut record)?;
+        manifest_assets.push((name_hash, data_hash));
+    }
+    Ok(manifest_assets.into())
+}
+
+/// `br` is right after the magic and version
+fn read_v2(br: &mut CountingReader) -> std::result::Result<Manifest, Nups2Error> {
+    let created: u64 = read_u64_be(br)?;
+    let pack_name: String = read_string(br)?;
+    let game_build: String = read_string(br)?;
+    let record_count: u32 = read_u32_be(br)?;
+    // don't trust the count for the allocation, the file might be truncated
+    let mut records: Vec<ManifestRecord> = Vec::with_capacity(record_count.min(0x10000) as usize);
+    let mut record: [u8; 32] = [0; 32];
+    for _ in 0..record_count {
+        br.read_exact(&mut record)?;
+        let mut record: &[u8] = &record;
+        records.push(ManifestRecord {
+            name_hash: read_u64_be(&mut record)?,
+            data_hash: read_u32_be(&mut record)?,
+            uncompressed_length: Some(read_u64_be(&mut record)?),
+            compressed_length: Some(read_u64_be(&mut record)?),
+            flags: Some(read_u32_be(&mut record)?),
+        });
+    }
+    Ok(Manifest {
+        header: Some(ManifestHeader {
+            version: MANIFEST_VERSION,
+            pack_name,
+            created,
+            game_build: Some(game_build).filter(|i| !i.is_empty()),
+        }),
+        records,
+    })
+}
+
+pub(crate) fn read_string(br: &mut dyn Read) -> std::result::Result<String, Nups2Error> {
+    let length: u32 = read_u32_be(br)?;
+    if length > MAX_STRING_LENGTH {
+        return Err(Nups2Error::OtherDyn(format!(
+            "manifest header contains a {length} bytes long string"
+        )));
+    }
+    Ok(String::from_utf8(read_x_bytes(br, length as usize)?)?)
+}
+
+/// fails for strings `read_string` would reject
+pub(crate) fn write_string(text: &str, bw: &mut dyn Write) -> std::io::Result<()> {
+    if text.len() > MAX_STRING_LENGTH as usize {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{} bytes long string (more than {MAX_STRING_LENGTH})",
+                text.len()
+            ),
         ));
     }
+    write_u32_be(text.len() as u32, bw)?;
+    bw.write_all(text.as_bytes())
+}
+
+/// a u32 count of `what`, fails instead of silently truncating
+pub(crate) fn write_count(count: usize, what: &str, bw: &mut dyn Write) -> std::io::Result<()> {
+    let count: u32 = u32::try_from(count).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{count} {what} (more than {})", u32::MAX),
+        )
+    })?;
+    write_u32_be(count, bw)
+}
+
+/// What happened to an asset between two manifests (see `classify_changes`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestChangeKind {
+    Created,
+    Changed,
+    /// deleted and created again with the same data_hash under a different name
+    Renamed,
+    Deleted,
+}
+
+impl ManifestChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ManifestChangeKind::Created => "created",
+            ManifestChangeKind::Changed => "changed",
+            ManifestChangeKind::Renamed => "renamed",
+            ManifestChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestChange {
+    pub kind: ManifestChangeKind,
+    /// the new name_hash for renames
+    pub name_hash: u64,
+    /// only set for renames
+    pub old_name_hash: Option<u64>,
+    pub old_data_hash: Option<u32>,
+    pub new_data_hash: Option<u32>,
+    /// uncompressed, `None` if unknown (v1 manifests)
+    pub old_length: Option<u64>,
+    pub new_length: Option<u64>,
+}
+
+impl ManifestChange {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, name_hash_lookup_table: &HashMap<u64, String>) -> String {
+        use crate::json_utils::escape_string;
+
+        fn or_null<T: ToString>(value: Option<T>) -> String {
+            value.map(|i| i.to_string()).unwrap_or(String::from("null"))
+        }
+        let name = |name_hash: Option<u64>| -> String {
+            name_hash
+                .and_then(|i| name_hash_lookup_table.get(&i))
+                .map(|i| escape_string(i))
+                .unwrap_or(String::from("null"))
+        };
+        format!(
+            r#"{{"kind":"{}","name_hash":{},"name":{},"old_name_hash":{},"old_name":{},"old_data_hash":{},"new_data_hash":{},"old_length":{},"new_length":{}}}"#,
+            self.kind.as_str(),
+            self.name_hash,
+            name(Some(self.name_hash)),
+            or_null(self.old_name_hash),
+            name(self.old_name_hash),
+            or_null(self.old_data_hash),
+            or_null(self.new_data_hash),
+            or_null(self.old_length),
+            or_null(self.new_length),
+        )
+    }
+
+    /// see `CSV_HEADER`
+    pub fn to_csv(&self, name_hash_lookup_table: &HashMap<u64, String>) -> String {
+        fn or_empty<T: ToString>(value: Option<T>) -> String {
+            value.map(|i| i.to_string()).unwrap_or_default()
+        }
+        let name = |name_hash: Option<u64>| -> String {
+            name_hash
+                .and_then(|i| name_hash_lookup_table.get(&i))
+                .map(|i| escape_csv(i))
+                .unwrap_or_default()
+        };
+        [
+            self.kind.as_str().to_string(),
+            self.name_hash.to_string(),
+            name(Some(self.name_hash)),
+            or_empty(self.old_name_hash),
+            name(self.old_name_hash),
+            or_empty(self.old_data_hash),
+            or_empty(self.new_data_hash),
+            or_empty(self.old_length),
+            or_empty(self.new_length),
+        ]
+        .join(",")
+    }
+}
+
+pub const CSV_HEADER: &str =
+    "kind,name_hash,name,old_name_hash,old_name,old_data_hash,new_data_hash,old_length,new_length";
+
+/// Output formats for `render`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffFormat {
+    /// `render_for_humans`
+    #[default]
+    Human,
+    /// one array of `ManifestChange::to_json`
+    #[cfg(feature = "json")]
+    Json,
+    /// one `ManifestChange::to_json` per line
+    #[cfg(feature = "json")]
+    Ndjson,
+    /// `CSV_HEADER` and one `ManifestChange::to_csv` per line
+    Csv,
+}
+
+/// sort the diff into created, changed, renamed and deleted assets.
+/// deletions come last, everything else is in the order of the diff.
+pub fn classify_changes(manifest_diff: &ManifestDiff) -> Vec<ManifestChange> {
+    let mut out: Vec<ManifestChange> = Vec::new();
+    let mut already_included: HashSet<u64> = HashSet::new();
+    // old data_hash -> first entry with it (for rename detection)
+    let mut by_old_data_hash: HashMap<u32, &ManifestDiffEntry> = HashMap::new();
+    for d in manifest_diff {
+        if let Some(old_data_hash) = d.old_data_hash {
+            by_old_data_hash.entry(old_data_hash).or_insert(d);
+        }
+    }
+    for d in manifest_diff {
+        let new_data_hash: u32 = match d.new_data_hash {
+            Some(new_data_hash) => new_data_hash,
+            None => continue,
+        };
+        let change: ManifestChange = ManifestChange {
+            kind: ManifestChangeKind::Changed,
+            name_hash: d.name_hash,
+            old_name_hash: None,
+            old_data_hash: d.old_data_hash,
+            new_data_hash: Some(new_data_hash),
+            old_length: d.old_length,
+            new_length: d.new_length,
+        };
+        out.push(
+            match (d.old_data_hash, by_old_data_hash.get(&new_data_hash)) {
+                (None, Some(i)) => {
+                    already_included.insert(i.name_hash);
+                    ManifestChange {
+                        kind: ManifestChangeKind::Renamed,
+                        old_name_hash: Some(i.name_hash),
+                        old_data_hash: i.old_data_hash,
+                        old_length: i.old_length,
+                        ..change
+                    }
+                }
+                (None, None) => ManifestChange {
+                    kind: ManifestChangeKind::Created,
+                    ..change
+                },
+                (Some(_), _) => change,
+            },
+        );
+    }
     for d in manifest_diff {
         if d.new_data_hash.is_some() || already_included.contains(&d.name_hash) {
             continue;
         }
-        out.push(format!(
-            "Deleted file: 0x{:X} ({}) with data_hash 0x{:X}",
-            d.name_hash,
-            name_hash_lookup_table
-                .get(&d.name_hash)
-                .unwrap_or(&String::from("?")),
-            d.old_data_hash.unwrap(),
-        ));
+        out.push(ManifestChange {
+            kind: ManifestChangeKind::Deleted,
+            name_hash: d.name_hash,
+            old_name_hash: None,
+            old_data_hash: d.old_data_hash,
+            new_data_hash: None,
+            old_length: d.old_length,
+            new_length: None,
+        });
+    }
+    out
+}
+
+pub fn render(
+    manifest_diff: &ManifestDiff,
+    name_hash_lookup_table: &HashMap<u64, String>,
+    format: DiffFormat,
+) -> String {
+    if format == DiffFormat::Human {
+        return render_for_humans(manifest_diff, name_hash_lookup_table);
+    }
+    let changes: Vec<ManifestChange> = classify_changes(manifest_diff);
+    match format {
+        DiffFormat::Human => unreachable!(),
+        #[cfg(feature = "json")]
+        DiffFormat::Json => format!(
+            "[{}]",
+            changes
+                .iter()
+                .map(|i| i.to_json(name_hash_lookup_table))
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        #[cfg(feature = "json")]
+        DiffFormat::Ndjson => changes
+            .iter()
+            .map(|i| i.to_json(name_hash_lookup_table))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        DiffFormat::Csv => std::iter::once(CSV_HEADER.to_string())
+            .chain(changes.iter().map(|i| i.to_csv(name_hash_lookup_table)))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+pub fn render_for_humans(
+    manifest_diff: &ManifestDiff,
+    name_hash_lookup_table: &HashMap<u64, String>,
+) -> String {
+    let name = |name_hash: u64| -> &str {
+        name_hash_lookup_table
+            .get(&name_hash)
+            .map(|i| i.as_str())
+            .unwrap_or("?")
+    };
+    classify_changes(manifest_diff)
+        .into_iter()
+        .map(|change| match change.kind {
+            ManifestChangeKind::Created => format!(
+                "Created file: 0x{:X} ({}) with data_hash 0x{:X}{}",
+                change.name_hash,
+                name(change.name_hash),
+                change.new_data_hash.unwrap(),
+                render_length(change.new_length),
+            ),
+            ManifestChangeKind::Changed => format!(
+                "Changed file: 0x{:X} ({}): 0x{:X} -> 0x{:X}{}",
+                change.name_hash,
+                name(change.name_hash),
+                change.old_data_hash.unwrap(),
+                change.new_data_hash.unwrap(),
+                match (change.old_length, change.new_length) {
+                    (Some(old_length), Some(new_length)) => format!(
+                        " ({} -> {})",
+                        humanise_bytes(old_length as f64),
+                        humanise_bytes(new_length as f64)
+                    ),
+                    _ => String::new(),
+                },
+            ),
+            ManifestChangeKind::Renamed => {
+                let old_name_hash: u64 = change.old_name_hash.unwrap();
+                format!(
+                    "Renamed file: 0x{:X} ({}) -> 0x{:X} ({})",
+                    old_name_hash,
+                    name(old_name_hash),
+                    change.name_hash,
+                    name(change.name_hash),
+                )
+            }
+            ManifestChangeKind::Deleted => format!(
+                "Deleted file: 0x{:X} ({}) with data_hash 0x{:X}{}",
+                change.name_hash,
+                name(change.name_hash),
+                change.old_data_hash.unwrap(),
+                render_length(change.old_length),
+            ),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// quoted if it contains a `,`, `"` or newline
+fn escape_csv(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// ` (<size>)` if known
+fn render_length(length: Option<u64>) -> String {
+    match length {
+        Some(length) => format!(" ({})", humanise_bytes(length as f64)),
+        None => String::new(),
     }
-    out.join("\n")
 }
 
-/// (namehash, content-hash)
-pub type Manifest = Vec<(u64, u32)>;
 pub type ManifestDiff = Vec<ManifestDiffEntry>;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ManifestDiffEntry {
     pub name_hash: u64,
     pub old_data_hash: Option<u32>,
     pub new_data_hash: Option<u32>,
+    /// uncompressed, `None` if unknown (v1 manifests)
+    pub old_length: Option<u64>,
+    pub new_length: Option<u64>,
 }
 
 impl ManifestDiffEntry {
@@ -107,40 +602,196 @@ impl ManifestDiffEntry {
             name_hash,
             old_data_hash,
             new_data_hash,
+            old_length: None,
+            new_length: None,
         }
     }
+
+    pub fn with_lengths(mut self, old_length: Option<u64>, new_length: Option<u64>) -> Self {
+        self.old_length = old_length;
+        self.new_length = new_length;
+        self
+    }
 }
 
 pub fn diff_two_manifests(old_manifest: &Manifest, new_manifest: &Manifest) -> ManifestDiff {
     let mut result: ManifestDiff = ManifestDiff::new();
-    for old_manifest_entry in old_manifest.iter() {
-        match new_manifest.iter().find(|i| i.0 == old_manifest_entry.0) {
-            Some(new_manifest_entry) => {
-                if old_manifest_entry.1 != new_manifest_entry.1 {
-                    result.push(ManifestDiffEntry::new(
-                        old_manifest_entry.0,
-                        Some(old_manifest_entry.1),
-                        Some(new_manifest_entry.1),
-                    ));
+    let mut new_records: HashMap<u64, &ManifestRecord> =
+        HashMap::with_capacity(new_manifest.records.len());
+    for new_record in new_manifest.records.iter() {
+        new_records
+            .entry(new_record.name_hash)
+            .or_insert(new_record);
+    }
+    for old_record in old_manifest.records.iter() {
+        match new_records.get(&old_record.name_hash) {
+            Some(new_record) => {
+                if old_record.data_hash != new_record.data_hash {
+                    result.push(
+                        ManifestDiffEntry::new(
+                            old_record.name_hash,
+                            Some(old_record.data_hash),
+                            Some(new_record.data_hash),
+                        )
+                        .with_lengths(
+                            old_record.uncompressed_length,
+                            new_record.uncompressed_length,
+                        ),
+                    );
                 } // else identical -> no diff
             }
-            None => result.push(ManifestDiffEntry::new(
-                old_manifest_entry.0,
-                Some(old_manifest_entry.1),
-                None,
-            )),
+            None => result.push(
+                ManifestDiffEntry::new(old_record.name_hash, Some(old_record.data_hash), None)
+                    .with_lengths(old_record.uncompressed_length, None),
+            ),
         }
     }
-    for new_manifest_entry in new_manifest.iter().filter(|new_manifest_entry| {
-        !old_manifest
-            .iter()
-            .any(|old_manifest_entry| old_manifest_entry.0 == new_manifest_entry.0)
-    }) {
-        result.push(ManifestDiffEntry::new(
-            new_manifest_entry.0,
-            None,
-            Some(new_manifest_entry.1),
-        ));
+    let old_name_hashes: HashSet<u64> = old_manifest.records.iter().map(|i| i.name_hash).collect();
+    for new_record in new_manifest
+        .records
+        .iter()
+        .filter(|new_record| !old_name_hashes.contains(&new_record.name_hash))
+    {
+        result.push(
+            ManifestDiffEntry::new(new_record.name_hash, None, Some(new_record.data_hash))
+                .with_lengths(None, new_record.uncompressed_length),
+        );
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        diff_two_manifests, render, render_for_humans, write_count, DiffFormat, Manifest,
+        ManifestDiff, ManifestHeader, ManifestRecord, MANIFEST_VERSION,
+    };
+    use crate::Nups2Error;
+    use std::collections::HashMap;
+
+    #[test]
+    fn read_and_write_manifests() {
+        let manifest: Manifest = Manifest {
+            header: Some(ManifestHeader {
+                version: MANIFEST_VERSION,
+                pack_name: String::from("assets_x64_0.pack2"),
+                created: 1700000000,
+                game_build: None,
+            }),
+            records: vec![ManifestRecord {
+                name_hash: 1,
+                data_hash: 0xA,
+                uncompressed_length: Some(100),
+                compressed_length: Some(50),
+                flags: Some(0x01),
+            }],
+        };
+        let mut v2: Vec<u8> = Vec::new();
+        manifest.write(&mut v2).unwrap();
+        assert_eq!(Manifest::read(&mut &v2[..]).unwrap(), manifest);
+
+        let v1_manifest: Manifest = vec![(1, 0xA), (2, 0xB)].into();
+        let mut v1: Vec<u8> = Vec::new();
+        v1_manifest.write(&mut v1).unwrap();
+        assert_eq!(v1.len(), 24);
+        assert_eq!(Manifest::read(&mut &v1[..]).unwrap(), v1_manifest);
+        assert_eq!(Manifest::read(&mut &[][..]).unwrap(), Manifest::default());
+
+        v2[7] = 3;
+        assert!(matches!(
+            Manifest::read(&mut &v2[..]),
+            Err(Nups2Error::UnsupportedVersion { version: 3, .. })
+        ));
+        v2[7] = 2;
+        assert!(matches!(
+            Manifest::read(&mut &v2[..v2.len() - 1]),
+            Err(Nups2Error::Truncated { .. })
+        ));
+
+        let mut too_long: Manifest = manifest.clone();
+        too_long.header.as_mut().unwrap().game_build = Some("x".repeat(0x10000));
+        assert_eq!(
+            too_long.write(&mut Vec::new()).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            write_count(u32::MAX as usize + 1, "records", &mut Vec::new())
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+
+        let diff: ManifestDiff = diff_two_manifests(
+            &manifest,
+            &Manifest {
+                records: vec![ManifestRecord {
+                    data_hash: 0xB,
+                    uncompressed_length: Some(2048),
+                    ..manifest.records[0]
+                }],
+                ..manifest.clone()
+            },
+        );
+        assert_eq!(
+            render_for_humans(&diff, &HashMap::new()),
+            "Changed file: 0x1 (?): 0xA -> 0xB (100Bytes -> 2.00KB)"
+        );
+    }
+
+    #[test]
+    fn diff_and_render_manifests() {
+        let old_manifest: Manifest = vec![(1, 0xA), (2, 0xB), (3, 0xC), (4, 0xD)].into();
+        let new_manifest: Manifest = vec![(1, 0xA), (2, 0xBB), (5, 0xC), (6, 0xE)].into();
+        let diff: ManifestDiff = diff_two_manifests(&old_manifest, &new_manifest);
+        let diff_tuples: Vec<(u64, Option<u32>, Option<u32>)> = diff
+            .iter()
+            .map(|i| (i.name_hash, i.old_data_hash, i.new_data_hash))
+            .collect();
+        assert_eq!(
+            diff_tuples,
+            vec![
+                (2, Some(0xB), Some(0xBB)),
+                (3, Some(0xC), None),
+                (4, Some(0xD), None),
+                (5, None, Some(0xC)),
+                (6, None, Some(0xE)),
+            ]
+        );
+        assert_eq!(
+            render_for_humans(&diff, &HashMap::new()),
+            [
+                "Changed file: 0x2 (?): 0xB -> 0xBB",
+                "Renamed file: 0x3 (?) -> 0x5 (?)",
+                "Created file: 0x6 (?) with data_hash 0xE",
+                "Deleted file: 0x4 (?) with data_hash 0xD",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn render_structured_diffs() {
+        let old_manifest: Manifest = vec![(1, 0xA), (3, 0xC)].into();
+        let new_manifest: Manifest = vec![(1, 0xAA), (5, 0xC)].into();
+        let diff: ManifestDiff = diff_two_manifests(&old_manifest, &new_manifest);
+        let names: HashMap<u64, String> =
+            HashMap::from([(3, String::from("a,\"b\".txt")), (5, String::from("c.txt"))]);
+        assert_eq!(
+            render(&diff, &names, DiffFormat::Csv),
+            [
+                super::CSV_HEADER,
+                "changed,1,,,,10,170,,",
+                "renamed,5,c.txt,3,\"a,\"\"b\"\".txt\",12,12,,",
+            ]
+            .join("\n")
+        );
+        #[cfg(feature = "json")]
+        assert_eq!(
+            render(&diff, &names, DiffFormat::Ndjson).lines().nth(1),
+            Some(
+                r#"{"kind":"renamed","name_hash":5,"name":"c.txt","old_name_hash":3,"old_name":"a,\"b\".txt","old_data_hash":12,"new_data_hash":12,"old_length":null,"new_length":null}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::pack2::{Asset, AssetSource, Pack2};
+use crate::sniff::FileType;
+use crate::Nups2Error;
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Cursor, Result, Write};
+use std::path::Path;
+
+/// A pack2 file, which is memory-mapped instead of read through `File`.
+///
+/// Raw asset data is borrowed from the mapping and decompression reads straight from it.
+/// `&MappedPack2` is an `AssetSource`, so it can be passed to `extract_all_named`, etc.
+pub struct MappedPack2 {
+    mmap: Mmap,
+    pub pack2: Pack2,
+}
+
+impl MappedPack2 {
+    /// # Safety
+    /// same as `map_file`: the file must not be modified or truncated while the `MappedPack2` exists
+    pub unsafe fn open(path: &Path) -> std::result::Result<Self, Nups2Error> {
+        // SAFETY: passed on to our caller
+        let mmap: Mmap = unsafe { map_file(path)? };
+        let pack2: Pack2 = Pack2::load_from_slice(&mmap)?;
+        Ok(Self { mmap, pack2 })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// a `Read + Seek` view of the mapping (no syscalls, no file handle)
+    pub fn cursor(&self) -> Cursor<&[u8]> {
+        Cursor::new(&self.mmap)
+    }
+
+    pub fn raw_bytes(&self, asset: &Asset) -> std::result::Result<&[u8], Nups2Error> {
+        asset.raw_slice(&self.mmap)
+    }
+
+    pub fn extract_bytes(&self, asset: &Asset) -> std::result::Result<Cow<'_, [u8]>, Nups2Error> {
+        asset.extract_bytes_from_slice(&self.mmap)
+    }
+
+    pub fn extract_to_file(
+        &self,
+        asset: &Asset,
+        output_file_steam: &mut dyn Write,
+    ) -> std::result::Result<(), Nups2Error> {
+        asset.extract_slice_to_file(&self.mmap, output_file_steam)
+    }
+}
+
+impl AssetSource for &MappedPack2 {
+    fn sniff_asset(&mut self, asset: &Asset) -> Result<FileType> {
+        asset.sniff(&mut self.cursor())
+    }
+
+    fn extract_asset_to_file(
+        &mut self,
+        asset: &Asset,
+        output_file_steam: &mut dyn Write,
+    ) -> Result<()> {
+        Ok(self.extract_to_file(asset, output_file_steam)?)
+    }
+}
+
+/// `Cursor::new(map_file(path)?)` can be used with all functions taking a pack2 reader
+///
+/// # Safety
+/// The caller has to make sure the file is not modified or truncated (e.g. by the game launcher
+/// updating it) while the mapping exists. Changed contents are undefined behaviour for the
+/// `&[u8]` handed out, and reading a page past a truncated end raises SIGBUS.
+pub unsafe fn map_file(path: &Path) -> Result<Mmap> {
+    let file: File = File::open(path)?;
+    // SAFETY: the caller guarantees the file stays unchanged while mapped
+    unsafe { Mmap::map(&file) }
+}
@@ -0,0 +1,304 @@
+use crate::cli_utils::humanise_bytes;
+use crate::pack2::{candidate_name_hashes, Asset, Pack2, Pack2Header, MAP_ENTRY_LENGTH};
+use crate::pack2_writer::{
+    encode_data, parse_unnamed_filename, write_map_entry, Pack2WriterSource,
+};
+use crate::Nups2Error;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Replaces assets of an existing pack2 file in place.
+///
+/// The new data is appended to the end of the file, followed by a new map.
+/// The data of untouched assets is not moved and the old map is left behind as unused bytes.
+/// The header is written last, so the file still has its old content if patching fails halfway.
+#[derive(Debug, Default)]
+pub struct Pack2Patcher {
+    /// (name or hash, new content)
+    replacements: Vec<(String, Pack2WriterSource)>,
+    compression: Option<bool>,
+}
+
+/// What `Pack2Patcher::patch` changed (or would change with `dry_run`)
+#[derive(Debug)]
+pub struct Pack2Patch {
+    pub old_header: Pack2Header,
+    pub new_header: Pack2Header,
+    /// (before, after) of each replaced asset, in map order
+    pub changes: Vec<(Asset, Asset)>,
+}
+
+impl Pack2Patcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `Some(true)` zlib compresses all replacements, `Some(false)` stores them uncompressed.
+    /// by default (`None`) each replacement is stored the same way as the asset it replaces.
+    pub fn set_compression(&mut self, compression: Option<bool>) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+
+    /// `name_or_hash` works like in `Pack2::find_asset`
+    pub fn add_bytes(&mut self, name_or_hash: &str, data: Vec<u8>) -> &mut Self {
+        self.push_replacement(name_or_hash, Pack2WriterSource::Bytes(data))
+    }
+
+    /// the file is only read once `patch` is called
+    pub fn add_file(&mut self, name_or_hash: &str, path: &Path) -> &mut Self {
+        self.push_replacement(name_or_hash, Pack2WriterSource::File(path.to_path_buf()))
+    }
+
+    /// the filename is used as name (`crc_64_<hash>` filenames as hash, see `Pack2Writer::add_file_by_filename`)
+    pub fn add_file_by_filename(&mut self, path: &Path) -> std::io::Result<&mut Self> {
+        let filename: &str = path.file_name().and_then(|i| i.to_str()).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unable to determine a asset name for {path:?}"),
+            )
+        })?;
+        Ok(match parse_unnamed_filename(filename) {
+            Some(name_hash) => self.add_file(&name_hash.to_string(), path),
+            None => self.add_file(filename, path),
+        })
+    }
+
+    fn push_replacement(&mut self, name_or_hash: &str, source: Pack2WriterSource) -> &mut Self {
+        self.replacements.push((name_or_hash.to_string(), source));
+        self
+    }
+
+    /// patch `file` (a complete pack2 file).
+    /// with `dry_run` nothing is written, but the returned changes are the same.
+    pub fn patch<F: Read + Write + Seek>(
+        &self,
+        file: &mut F,
+        dry_run: bool,
+    ) -> Result<Pack2Patch, Nups2Error> {
+        let pack2: Pack2 = Pack2::load_from_file(file)?;
+        let old_header: Pack2Header = pack2.header().clone();
+
+        // (asset index, name, new content); a later replacement of the same asset wins
+        let mut replacements: Vec<(usize, Option<String>, &Pack2WriterSource)> = Vec::new();
+        for (name_or_hash, source) in self.replacements.iter() {
+            let index: usize = candidate_name_hashes(name_or_hash)
+                .into_iter()
+                .find_map(|hash| pack2.find_asset_index_by_name_hash(hash))
+                .ok_or_else(|| format!("{name_or_hash:?} not found in the pack2 file"))?;
+            let asset: &Asset = &pack2.assets[index];
+            let name: Option<String> =
+                if crate::crc64::convert_filename(name_or_hash) == asset.name_hash {
+                    Some(name_or_hash.clone())
+                } else {
+                    asset.name.clone()
+                };
+            if let Some(pos) = replacements.iter().position(|i| i.0 == index) {
+                log::warn!("replacing 0x{:X} more than once", asset.name_hash);
+                replacements.remove(pos);
+            }
+            replacements.push((index, name, source));
+        }
+        replacements.sort_by_key(|i| i.0);
+        if replacements.is_empty() {
+            return Ok(Pack2Patch {
+                new_header: old_header.clone(),
+                old_header,
+                changes: Vec::new(),
+            });
+        }
+
+        let mut assets: Vec<Asset> = pack2.assets.clone();
+        let mut changes: Vec<(Asset, Asset)> = Vec::with_capacity(replacements.len());
+        let mut offset: u64 = file.seek(SeekFrom::End(0))?;
+        log::trace!("Writing data");
+        for (index, name, source) in replacements {
+            let old: &Asset = &pack2.assets[index];
+            let data: Vec<u8> = source.load(None)?;
+            let data_hash: u32 = crc32fast::hash(&data);
+            let unzipped_length: u32 = data.len() as u32;
+            let (flags, data): (u32, Vec<u8>) = encode_data(
+                old.name_hash,
+                data,
+                self.compression.unwrap_or(old.is_zipped),
+            )?;
+            let is_zipped: bool = flags != 0x00;
+            if !dry_run {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(&data)?;
+            }
+            let new: Asset = Asset {
+                name: name.clone(),
+                name_hash: old.name_hash,
+                offset,
+                data_length: data.len() as u64,
+                // keep unknown bits of the flags if possible
+                flags: if is_zipped == old.is_zipped {
+                    old.flags
+                } else {
+                    flags
+                },
+                is_zipped,
+                data_hash,
+                unzipped_length: if is_zipped { unzipped_length } else { 0 },
+                detected_type: None,
+            };
+            offset += new.data_length;
+            assets[index] = new.clone();
+            changes.push((
+                Asset {
+                    name,
+                    ..old.clone()
+                },
+                new,
+            ));
+        }
+
+        let new_header: Pack2Header = Pack2Header {
+            length: offset + MAP_ENTRY_LENGTH * assets.len() as u64,
+            map_offset: offset,
+            ..old_header.clone()
+        };
+        if !dry_run {
+            log::trace!("Writing map");
+            let mut map: Vec<u8> = Vec::with_capacity(MAP_ENTRY_LENGTH as usize * assets.len());
+            for asset in assets.iter() {
+                write_map_entry(
+                    &mut map,
+                    asset.name_hash,
+                    asset.offset,
+                    asset.data_length,
+                    asset.flags,
+                    asset.data_hash,
+                )?;
+            }
+            file.seek(SeekFrom::Start(new_header.map_offset))?;
+            file.write_all(&map)?;
+            log::trace!("Writing header");
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&new_header.to_bytes())?;
+            file.flush()?;
+        }
+        Ok(Pack2Patch {
+            old_header,
+            new_header,
+            changes,
+        })
+    }
+}
+
+impl Pack2Patch {
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"old_header":{},"new_header":{},"changes":[{}]}}"#,
+            self.old_header.to_json(),
+            self.new_header.to_json(),
+            self.changes
+                .iter()
+                .map(|(old, new)| format!(r#"{{"old":{},"new":{}}}"#, old.to_json(), new.to_json()))
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    pub fn render_for_humans(&self) -> String {
+        #[cfg(feature = "use_comfy_table")]
+        let mut table = comfy_table::Table::new();
+        #[cfg(feature = "use_comfy_table")]
+        table.set_header(vec!["Name", "Offset", "Length", "Flags", "Data hash"]);
+
+        #[cfg(not(feature = "use_comfy_table"))]
+        let mut out: Vec<String> = Vec::with_capacity(self.changes.len());
+
+        for (old, new) in self.changes.iter() {
+            let columns: Vec<String> = vec![
+                match old.name {
+                    Some(ref name) => name.clone(),
+                    None => format!("crc64({})", old.name_hash),
+                },
+                format!("{} -> {}", old.offset, new.offset),
+                format!(
+                    "{} -> {}",
+                    humanise_bytes(old.data_length as f64),
+                    humanise_bytes(new.data_length as f64)
+                ),
+                format!("0x{:02X} -> 0x{:02X}", old.flags, new.flags),
+                format!("0x{:08X} -> 0x{:08X}", old.data_hash, new.data_hash),
+            ];
+
+            #[cfg(feature = "use_comfy_table")]
+            table.add_row(columns);
+            #[cfg(not(feature = "use_comfy_table"))]
+            out.push(columns.join(" "));
+        }
+
+        #[cfg(feature = "use_comfy_table")]
+        let changes: String = table.to_string();
+        #[cfg(not(feature = "use_comfy_table"))]
+        let changes: String = out.join("\n");
+
+        format!(
+            "{changes}\nmap_offset: {} -> {}\nlength: {} -> {}",
+            self.old_header.map_offset,
+            self.new_header.map_offset,
+            self.old_header.length,
+            self.new_header.length
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pack2Patch, Pack2Patcher};
+    use crate::pack2::Pack2;
+    use crate::pack2_writer::Pack2Writer;
+    use std::io::Cursor;
+
+    #[test]
+    fn patch_pack2() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("a.txt", b"old a".to_vec())
+            .add_bytes("b.txt", b"old b".to_vec())
+            .add_raw(1234, b"old raw".to_vec());
+        let mut file: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut file).unwrap();
+        let original: Vec<u8> = file.get_ref().clone();
+        let old: Pack2 = Pack2::load_from_file(&mut file).unwrap();
+
+        let mut patcher = Pack2Patcher::new();
+        patcher
+            .add_bytes("a.txt", b"new a, which is longer".to_vec())
+            .add_bytes("0x4D2", b"new raw".to_vec());
+        let dry_run: Pack2Patch = patcher.patch(&mut file, true).unwrap();
+        assert_eq!(file.get_ref(), &original);
+        let patch: Pack2Patch = patcher
+            .set_compression(Some(false))
+            .patch(&mut file, false)
+            .unwrap();
+        assert_eq!(dry_run.changes.len(), 2);
+        assert_eq!(patch.changes.len(), 2);
+        assert_eq!(patch.new_header.length, file.get_ref().len() as u64);
+        assert!(patch
+            .changes
+            .iter()
+            .any(|(_, new)| new.name.as_deref() == Some("a.txt")));
+
+        let new: Pack2 = Pack2::load_from_file(&mut file).unwrap();
+        let a = &new.assets[new.find_asset_index_by_name("a.txt").unwrap()];
+        assert!(!a.is_zipped);
+        assert_eq!(a.extract_text(&mut file).unwrap(), "new a, which is longer");
+        let raw = &new.assets[new.find_asset_index_by_name_hash(1234).unwrap()];
+        assert_eq!(raw.extract_bytes(&mut file).unwrap(), b"new raw".to_vec());
+        // untouched data stays where it was
+        let b = new.find_asset_index_by_name("b.txt").unwrap();
+        assert_eq!(new.assets[b].offset, old.assets[b].offset);
+        assert_eq!(new.assets[b].extract_text(&mut file).unwrap(), "old b");
+
+        assert!(Pack2Patcher::new()
+            .add_bytes("missing.txt", Vec::new())
+            .patch(&mut file, true)
+            .is_err());
+    }
+}
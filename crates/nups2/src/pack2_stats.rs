@@ -0,0 +1,391 @@
+use crate::cli_utils::humanise_bytes;
+use crate::pack2::{Asset, Pack2};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// how many assets `Pack2Stats::largest_assets` keeps
+pub const LARGEST_ASSET_COUNT: usize = 10;
+/// upper bounds (in percent of the uncompressed size) of the `compression_ratios` buckets.
+/// the last bucket is everything above (compression made it bigger).
+pub const COMPRESSION_RATIO_BUCKETS: [u64; 5] = [10, 25, 50, 75, 100];
+
+/// Numbers about the content of one or more pack2 files (see `Pack2::stats`)
+#[derive(Debug, Clone, Default)]
+pub struct Pack2Stats {
+    pub pack_count: usize,
+    pub asset_count: usize,
+    pub named_count: usize,
+    pub compressed_count: usize,
+    /// size inside of the pack2 file(s)
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    /// lowercase extension (`""` if there is none) -> named assets with it
+    pub extensions: BTreeMap<String, HistogramEntry>,
+    /// only contains assets, for which `Pack2::detect_types` was called
+    pub detected_types: BTreeMap<String, HistogramEntry>,
+    /// compressed assets per `COMPRESSION_RATIO_BUCKETS` bucket (+ 1 for bigger than uncompressed)
+    pub compression_ratios: [usize; COMPRESSION_RATIO_BUCKETS.len() + 1],
+    /// by uncompressed size, biggest first
+    pub largest_assets: Vec<LargeAsset>,
+    /// data ranges (offset + length), which are used by more than one asset
+    pub duplicate_range_count: usize,
+    /// assets using one of those ranges (all of them, including the first one)
+    pub duplicate_range_asset_count: usize,
+    /// how much bigger the pack2 files would be without the shared ranges
+    pub duplicate_range_saved_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistogramEntry {
+    pub count: usize,
+    /// uncompressed
+    pub size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LargeAsset {
+    /// set through `Pack2Stats::with_pack_name`
+    pub pack: Option<String>,
+    pub name: Option<String>,
+    pub name_hash: u64,
+    pub uncompressed_size: u64,
+    pub compressed_size: u64,
+}
+
+impl Pack2Stats {
+    pub fn from_pack2(pack2: &Pack2) -> Self {
+        let mut result: Self = Self {
+            pack_count: 1,
+            ..Default::default()
+        };
+        let mut ranges: HashMap<(u64, u64), usize> = HashMap::new();
+        for asset in pack2.assets.iter() {
+            result.add_asset(asset);
+            if asset.data_length > 0 {
+                *ranges.entry((asset.offset, asset.data_length)).or_insert(0) += 1;
+            }
+        }
+        for ((_, length), users) in ranges {
+            if users > 1 {
+                result.duplicate_range_count += 1;
+                result.duplicate_range_asset_count += users;
+                result.duplicate_range_saved_bytes += length * (users as u64 - 1);
+            }
+        }
+        result
+    }
+
+    fn add_asset(&mut self, asset: &Asset) {
+        let uncompressed_size: u64 = asset.uncompressed_length();
+        self.asset_count += 1;
+        self.compressed_size += asset.data_length;
+        self.uncompressed_size += uncompressed_size;
+        if let Some(ref name) = asset.name {
+            self.named_count += 1;
+            let extension: String = Path::new(name)
+                .extension()
+                .map(|i| i.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            self.extensions
+                .entry(extension)
+                .or_default()
+                .add(uncompressed_size);
+        }
+        if let Some(file_type) = asset.detected_type {
+            self.detected_types
+                .entry(file_type.name().to_string())
+                .or_default()
+                .add(uncompressed_size);
+        }
+        if asset.is_zipped {
+            self.compressed_count += 1;
+            let percent: u64 = asset.data_length * 100 / uncompressed_size.max(1);
+            let bucket: usize = COMPRESSION_RATIO_BUCKETS
+                .iter()
+                .position(|max| percent <= *max)
+                .unwrap_or(COMPRESSION_RATIO_BUCKETS.len());
+            self.compression_ratios[bucket] += 1;
+        }
+        self.add_large_assets([LargeAsset {
+            pack: None,
+            name: asset.name.clone(),
+            name_hash: asset.name_hash,
+            uncompressed_size,
+            compressed_size: asset.data_length,
+        }]);
+    }
+
+    fn add_large_assets(&mut self, assets: impl IntoIterator<Item = LargeAsset>) {
+        self.largest_assets.extend(assets);
+        self.largest_assets
+            .sort_by_key(|i| std::cmp::Reverse(i.uncompressed_size));
+        self.largest_assets.truncate(LARGEST_ASSET_COUNT);
+    }
+
+    /// remember which pack the `largest_assets` are from (before `merge`ing multiple packs)
+    pub fn with_pack_name(mut self, pack_name: &str) -> Self {
+        for asset in self.largest_assets.iter_mut() {
+            asset.pack = Some(pack_name.to_string());
+        }
+        self
+    }
+
+    /// add the numbers of another pack (set)
+    pub fn merge(&mut self, other: Pack2Stats) {
+        self.pack_count += other.pack_count;
+        self.asset_count += other.asset_count;
+        self.named_count += other.named_count;
+        self.compressed_count += other.compressed_count;
+        self.compressed_size += other.compressed_size;
+        self.uncompressed_size += other.uncompressed_size;
+        for (target, source) in [
+            (&mut self.extensions, other.extensions),
+            (&mut self.detected_types, other.detected_types),
+        ] {
+            for (key, entry) in source {
+                let target_entry: &mut HistogramEntry = target.entry(key).or_default();
+                target_entry.count += entry.count;
+                target_entry.size += entry.size;
+            }
+        }
+        for (target, source) in self
+            .compression_ratios
+            .iter_mut()
+            .zip(other.compression_ratios)
+        {
+            *target += source;
+        }
+        self.add_large_assets(other.largest_assets);
+        self.duplicate_range_count += other.duplicate_range_count;
+        self.duplicate_range_asset_count += other.duplicate_range_asset_count;
+        self.duplicate_range_saved_bytes += other.duplicate_range_saved_bytes;
+    }
+
+    /// 0.0 - 1.0 (1.0 for empty packs)
+    pub fn named_ratio(&self) -> f64 {
+        if self.asset_count == 0 {
+            return 1.0;
+        }
+        self.named_count as f64 / self.asset_count as f64
+    }
+
+    fn compression_ratio_labels() -> Vec<String> {
+        let mut result: Vec<String> = Vec::with_capacity(COMPRESSION_RATIO_BUCKETS.len() + 1);
+        let mut previous: u64 = 0;
+        for max in COMPRESSION_RATIO_BUCKETS {
+            result.push(format!("{previous}-{max}%"));
+            previous = max;
+        }
+        result.push(format!(">{previous}%"));
+        result
+    }
+
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        use crate::json_utils::escape_string;
+
+        let histogram = |histogram: &BTreeMap<String, HistogramEntry>| -> String {
+            histogram
+                .iter()
+                .map(|(key, entry)| {
+                    format!(
+                        r#"{}:{{"count":{},"size":{}}}"#,
+                        escape_string(key),
+                        entry.count,
+                        entry.size
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        format!(
+            r#"{{"pack_count":{pack_count},"asset_count":{asset_count},"named_count":{named_count},"named_ratio":{named_ratio},"compressed_count":{compressed_count},"compressed_size":{compressed_size},"uncompressed_size":{uncompressed_size},"extensions":{{{extensions}}},"detected_types":{{{detected_types}}},"compression_ratios":{{{compression_ratios}}},"largest_assets":[{largest_assets}],"duplicate_ranges":{{"count":{duplicate_range_count},"asset_count":{duplicate_range_asset_count},"saved_bytes":{duplicate_range_saved_bytes}}}}}"#,
+            pack_count = self.pack_count,
+            asset_count = self.asset_count,
+            named_count = self.named_count,
+            named_ratio = self.named_ratio(),
+            compressed_count = self.compressed_count,
+            compressed_size = self.compressed_size,
+            uncompressed_size = self.uncompressed_size,
+            extensions = histogram(&self.extensions),
+            detected_types = histogram(&self.detected_types),
+            compression_ratios = Self::compression_ratio_labels()
+                .iter()
+                .zip(self.compression_ratios)
+                .map(|(label, count)| format!("{}:{count}", escape_string(label)))
+                .collect::<Vec<String>>()
+                .join(","),
+            largest_assets = self
+                .largest_assets
+                .iter()
+                .map(|i| format!(
+                    r#"{{"pack":{},"name":{},"name_hash":{},"uncompressed_size":{},"compressed_size":{}}}"#,
+                    i.pack.as_deref().map(escape_string).unwrap_or(String::from("null")),
+                    i.name.as_deref().map(escape_string).unwrap_or(String::from("null")),
+                    i.name_hash,
+                    i.uncompressed_size,
+                    i.compressed_size,
+                ))
+                .collect::<Vec<String>>()
+                .join(","),
+            duplicate_range_count = self.duplicate_range_count,
+            duplicate_range_asset_count = self.duplicate_range_asset_count,
+            duplicate_range_saved_bytes = self.duplicate_range_saved_bytes,
+        )
+    }
+
+    pub fn render_for_humans(&self) -> String {
+        let mut sections: Vec<String> = Vec::new();
+
+        sections.push(render_table(
+            vec!["", ""],
+            vec![
+                vec![String::from("Packs"), self.pack_count.to_string()],
+                vec![String::from("Assets"), self.asset_count.to_string()],
+                vec![
+                    String::from("Named"),
+                    format!("{} ({:.1}%)", self.named_count, self.named_ratio() * 100.0),
+                ],
+                vec![
+                    String::from("Compressed"),
+                    self.compressed_count.to_string(),
+                ],
+                vec![
+                    String::from("Size in pack"),
+                    humanise_bytes(self.compressed_size as f64),
+                ],
+                vec![
+                    String::from("Uncompressed size"),
+                    humanise_bytes(self.uncompressed_size as f64),
+                ],
+                vec![
+                    String::from("Shared data ranges"),
+                    format!(
+                        "{} ({} assets, {} saved)",
+                        self.duplicate_range_count,
+                        self.duplicate_range_asset_count,
+                        humanise_bytes(self.duplicate_range_saved_bytes as f64)
+                    ),
+                ],
+            ],
+        ));
+
+        for (title, histogram) in [
+            ("Extension", &self.extensions),
+            ("Detected type", &self.detected_types),
+        ] {
+            if histogram.is_empty() {
+                continue;
+            }
+            let mut entries: Vec<(&String, &HistogramEntry)> = histogram.iter().collect();
+            entries.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+            sections.push(render_table(
+                vec![title, "Assets", "Size"],
+                entries
+                    .into_iter()
+                    .map(|(key, entry)| {
+                        vec![
+                            key.clone(),
+                            entry.count.to_string(),
+                            humanise_bytes(entry.size as f64),
+                        ]
+                    })
+                    .collect(),
+            ));
+        }
+
+        if self.compressed_count > 0 {
+            sections.push(render_table(
+                vec!["Compressed to", "Assets"],
+                Self::compression_ratio_labels()
+                    .into_iter()
+                    .zip(self.compression_ratios)
+                    .map(|(label, count)| vec![label, count.to_string()])
+                    .collect(),
+            ));
+        }
+
+        if !self.largest_assets.is_empty() {
+            sections.push(render_table(
+                vec!["Largest assets", "Pack", "Size", "Size in pack"],
+                self.largest_assets
+                    .iter()
+                    .map(|i| {
+                        vec![
+                            i.name
+                                .clone()
+                                .unwrap_or_else(|| format!("crc64({})", i.name_hash)),
+                            i.pack.clone().unwrap_or_default(),
+                            humanise_bytes(i.uncompressed_size as f64),
+                            humanise_bytes(i.compressed_size as f64),
+                        ]
+                    })
+                    .collect(),
+            ));
+        }
+
+        sections.join("\n\n")
+    }
+}
+
+impl HistogramEntry {
+    fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.size += size;
+    }
+}
+
+fn render_table(header: Vec<&str>, rows: Vec<Vec<String>>) -> String {
+    #[cfg(feature = "use_comfy_table")]
+    {
+        let mut table = comfy_table::Table::new();
+        table.set_header(header);
+        for row in rows {
+            table.add_row(row);
+        }
+        table.to_string()
+    }
+    #[cfg(not(feature = "use_comfy_table"))]
+    {
+        std::iter::once(header.join(" "))
+            .chain(rows.into_iter().map(|row| row.join(" ")))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pack2Stats;
+    use crate::pack2::Pack2;
+    use crate::pack2_writer::Pack2Writer;
+    use std::io::Cursor;
+
+    #[test]
+    fn pack2_stats() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .add_bytes("a.txt", b"a".repeat(1000))
+            .add_bytes("b.TXT", b"b".repeat(10))
+            .add_bytes("c.dme", vec![1, 2, 3])
+            .add_raw(1234, vec![0; 100]);
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let mut pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+        pack2.apply_filename_list(&["a.txt".into(), "b.TXT".into(), "c.dme".into()]);
+
+        let mut stats: Pack2Stats = pack2.stats().with_pack_name("test.pack2");
+        assert_eq!((stats.asset_count, stats.named_count), (4, 3));
+        assert_eq!(stats.uncompressed_size, 1113);
+        assert_eq!(stats.extensions["txt"].count, 2);
+        assert_eq!(stats.extensions["txt"].size, 1010);
+        assert_eq!(stats.largest_assets[0].name.as_deref(), Some("a.txt"));
+        assert_eq!(stats.largest_assets[0].pack.as_deref(), Some("test.pack2"));
+        assert_eq!(stats.duplicate_range_count, 0);
+
+        stats.merge(pack2.stats());
+        assert_eq!((stats.pack_count, stats.asset_count), (2, 8));
+        assert_eq!(stats.extensions["dme"].count, 2);
+        assert_eq!(stats.compression_ratios.iter().sum::<usize>(), 8);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::bin_utils::{write_u32_be, write_u32_le, write_u64_le, ReadSeek};
+use crate::crc64;
+use crate::pack2::{
+    Pack2Header, COMPRESSION_MAGIC, FILENAME_LIST_NAME_HASH, HEADER_UNKNOWN_LENGTH,
+    MAP_ENTRY_LENGTH,
+};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{prelude::*, Result, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// the header itself is only `pack2::HEADER_LENGTH` (156) bytes, the rest is zero-padding.
+const FIRST_ASSET_OFFSET: u64 = 0x200;
+
+/// Builds a new pack2 file.
+///
+/// Assets are written in the order they were added, the map is sorted by name hash.
+/// Adding a second asset with the same name (hash) replaces the first one (at its position).
+#[derive(Debug)]
+pub struct Pack2Writer {
+    entries: Vec<Pack2WriterEntry>,
+    /// name_hash -> index into `entries`
+    entry_index: HashMap<u64, usize>,
+    compress: bool,
+    embed_filename_list: bool,
+}
+
+#[derive(Debug)]
+struct Pack2WriterEntry {
+    name: Option<String>,
+    name_hash: u64,
+    source: Pack2WriterSource,
+}
+
+#[derive(Debug)]
+pub(crate) enum Pack2WriterSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+    /// a pack1 asset, read from the reader passed to `write_with_pack1`
+    #[cfg(feature = "pack1")]
+    Pack1 {
+        offset: u64,
+        length: u64,
+    },
+}
+
+impl Pack2WriterSource {
+    #[allow(unused_variables)]
+    pub(crate) fn load(&self, pack1_reader: Option<&mut (dyn ReadSeek + '_)>) -> Result<Vec<u8>> {
+        match self {
+            Pack2WriterSource::Bytes(bytes) => Ok(bytes.clone()),
+            Pack2WriterSource::File(path) => std::fs::read(path),
+            #[cfg(feature = "pack1")]
+            Pack2WriterSource::Pack1 { offset, length } => {
+                let br: &mut dyn ReadSeek = pack1_reader.ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "assets added with add_pack1 have to be written with write_with_pack1",
+                    )
+                })?;
+                br.seek(SeekFrom::Start(*offset))?;
+                let mut data: Vec<u8> = Vec::with_capacity(*length as usize);
+                br.take(*length).read_to_end(&mut data)?;
+                if data.len() as u64 != *length {
+                    return Err(std::io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(data)
+            }
+        }
+    }
+}
+
+impl Default for Pack2Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pack2Writer {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            entry_index: HashMap::new(),
+            compress: true,
+            embed_filename_list: false,
+        }
+    }
+
+    /// zlib compress assets (with the `0xA1B2C3D4` prefix). enabled by default.
+    /// empty assets are always stored uncompressed.
+    pub fn set_compression(&mut self, compress: bool) -> &mut Self {
+        self.compress = compress;
+        self
+    }
+
+    /// add a `{NAMELIST}` asset containing the names of all named assets
+    pub fn set_embed_filename_list(&mut self, embed_filename_list: bool) -> &mut Self {
+        self.embed_filename_list = embed_filename_list;
+        self
+    }
+
+    pub fn add_bytes(&mut self, name: &str, data: Vec<u8>) -> &mut Self {
+        self.push_entry(
+            Some(name.into()),
+            crc64::convert_filename(name),
+            Pack2WriterSource::Bytes(data),
+        )
+    }
+
+    pub fn add_raw(&mut self, name_hash: u64, data: Vec<u8>) -> &mut Self {
+        self.push_entry(None, name_hash, Pack2WriterSource::Bytes(data))
+    }
+
+    /// the file is only read once `write` is called
+    pub fn add_file(&mut self, name: &str, path: &Path) -> &mut Self {
+        self.push_entry(
+            Some(name.into()),
+            crc64::convert_filename(name),
+            Pack2WriterSource::File(path.to_path_buf()),
+        )
+    }
+
+    /// add a file from disk using its filename as asset name.
+    /// files named `crc_64_<hash>` or `crc_64_<hash>.<ext>` (the way unnamed assets get extracted) are added with that hash.
+    pub fn add_file_by_filename(&mut self, path: &Path) -> Result<&mut Self> {
+        let filename: &str = path.file_name().and_then(|i| i.to_str()).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unable to determine a asset name for {path:?}"),
+            )
+        })?;
+        Ok(match parse_unnamed_filename(filename) {
+            Some(name_hash) => {
+                self.push_entry(None, name_hash, Pack2WriterSource::File(path.to_path_buf()))
+            }
+            None => self.add_file(filename, path),
+        })
+    }
+
+    /// add all files in a directory (not recursive, since pack2 has no directories)
+    pub fn add_directory(&mut self, directory: &Path) -> Result<&mut Self> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<PathBuf>>>()?;
+        paths.sort();
+        for path in paths {
+            if path.is_file() {
+                self.add_file_by_filename(&path)?;
+            } else {
+                log::warn!("skipping {path:?} (not a file)");
+            }
+        }
+        Ok(self)
+    }
+
+    /// add all assets of a pack1 file (loaded with `Pack1::load_from_file`).
+    /// only their offsets are kept, the data is read when calling `write_with_pack1`.
+    /// if a name appears in multiple chunks the last one wins.
+    /// `crc_64_<hash>` names (see `UnknownNameHandling::GenerateName`) are added with that hash.
+    #[cfg(feature = "pack1")]
+    pub fn add_pack1(&mut self, pack1: &crate::pack1::Pack1) -> Result<&mut Self> {
+        for asset in pack1.chunks.iter().flat_map(|chunk| chunk.assets.iter()) {
+            if asset.stream_is_pack2_zipped() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} is part of a pack2 file, not a pack1 file", asset.name),
+                ));
+            }
+            let source: Pack2WriterSource = Pack2WriterSource::Pack1 {
+                offset: asset.offset as u64,
+                length: asset.data_length as u64,
+            };
+            match parse_unnamed_filename(&asset.name) {
+                Some(name_hash) => self.push_entry(None, name_hash, source),
+                None => self.push_entry(
+                    Some(asset.name.clone()),
+                    crc64::convert_filename(&asset.name),
+                    source,
+                ),
+            };
+        }
+        Ok(self)
+    }
+
+    fn push_entry(
+        &mut self,
+        name: Option<String>,
+        name_hash: u64,
+        source: Pack2WriterSource,
+    ) -> &mut Self {
+        let entry: Pack2WriterEntry = Pack2WriterEntry {
+            name,
+            name_hash,
+            source,
+        };
+        match self.entry_index.get(&name_hash) {
+            Some(index) => {
+                log::warn!("replacing already added asset 0x{name_hash:X}");
+                self.entries[*index] = entry;
+            }
+            None => {
+                self.entry_index.insert(name_hash, self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+        self
+    }
+
+    fn filename_list(&self) -> Vec<u8> {
+        self.entries
+            .iter()
+            .filter(|i| i.name_hash != FILENAME_LIST_NAME_HASH)
+            .filter_map(|i| i.name.clone())
+            .collect::<Vec<String>>()
+            .join("\n")
+            .into_bytes()
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let mut bw: File = File::create_new(path)?;
+        self.write(&mut bw)
+    }
+
+    /// the target has to be empty (offsets are relative to its start)
+    pub fn write<W: Write + Seek>(&self, target: &mut W) -> Result<()> {
+        self.write_entries(target, None)
+    }
+
+    /// same as `write`, assets added with `add_pack1` are read from `pack1_reader`
+    #[cfg(feature = "pack1")]
+    pub fn write_with_pack1<W: Write + Seek, R: Read + Seek>(
+        &self,
+        target: &mut W,
+        pack1_reader: &mut R,
+    ) -> Result<()> {
+        self.write_entries(target, Some(pack1_reader))
+    }
+
+    fn write_entries<W: Write + Seek>(
+        &self,
+        target: &mut W,
+        mut pack1_reader: Option<&mut dyn ReadSeek>,
+    ) -> Result<()> {
+        log::debug!("Starting generation of pack2 file");
+        target.seek(SeekFrom::Start(0))?;
+        target.write_all(&[0u8; FIRST_ASSET_OFFSET as usize])?;
+
+        let filename_list: Option<Pack2WriterEntry> = if self.embed_filename_list {
+            Some(Pack2WriterEntry {
+                name: None,
+                name_hash: FILENAME_LIST_NAME_HASH,
+                source: Pack2WriterSource::Bytes(self.filename_list()),
+            })
+        } else {
+            None
+        };
+
+        log::trace!("Writing data");
+        let mut map: Vec<(u64, u64, u64, u32, u32)> = Vec::with_capacity(self.entries.len() + 1);
+        let mut offset: u64 = FIRST_ASSET_OFFSET;
+        for entry in self
+            .entries
+            .iter()
+            .filter(|i| !(self.embed_filename_list && i.name_hash == FILENAME_LIST_NAME_HASH))
+            .chain(filename_list.iter())
+        {
+            let data: Vec<u8> = entry.source.load(pack1_reader.as_deref_mut())?;
+            let data_hash: u32 = crc32fast::hash(&data);
+            let (flags, data): (u32, Vec<u8>) = encode_data(entry.name_hash, data, self.compress)?;
+            target.write_all(&data)?;
+            let data_length: u64 = data.len() as u64;
+            map.push((entry.name_hash, offset, data_length, flags, data_hash));
+            offset += data_length;
+        }
+
+        log::trace!("Writing map");
+        let map_offset: u64 = offset;
+        map.sort_by_key(|i| i.0);
+        for (name_hash, asset_offset, data_length, flags, data_hash) in map.iter() {
+            write_map_entry(
+                target,
+                *name_hash,
+                *asset_offset,
+                *data_length,
+                *flags,
+                *data_hash,
+            )?;
+        }
+
+        log::trace!("Writing header");
+        let asset_count: u32 = u32::try_from(map.len()).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} assets are too many for pack2", map.len()),
+            )
+        })?;
+        let length: u64 = map_offset + MAP_ENTRY_LENGTH * map.len() as u64;
+        let header: Pack2Header = Pack2Header {
+            asset_count,
+            length,
+            map_offset,
+            // the checksum algorithm is unknown, so it is left at 0. this crate does not check it
+            // when reading, whether the game accepts such files has not been tested
+            checksum: 0,
+            unknown: [0; HEADER_UNKNOWN_LENGTH],
+        };
+        target.seek(SeekFrom::Start(0))?;
+        target.write_all(&header.to_bytes())?;
+        target.seek(SeekFrom::Start(length))?;
+        target.flush()?;
+        Ok(())
+    }
+}
+
+/// the data as it is stored in the pack2 file and its flags.
+/// compressed data gets the `0xA1B2C3D4` + uncompressed length prefix.
+/// empty assets are always stored uncompressed.
+pub(crate) fn encode_data(name_hash: u64, data: Vec<u8>, compress: bool) -> Result<(u32, Vec<u8>)> {
+    let unzipped_length: u32 = u32::try_from(data.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Asset 0x{name_hash:X} is too big for pack2"),
+        )
+    })?;
+    if !compress || data.is_empty() {
+        return Ok((0x00, data));
+    }
+    let mut result: Vec<u8> = Vec::with_capacity(data.len() / 2 + 8);
+    write_u32_be(COMPRESSION_MAGIC, &mut result)?;
+    write_u32_be(unzipped_length, &mut result)?;
+    let mut encoder = ZlibEncoder::new(result, Compression::default());
+    encoder.write_all(&data)?;
+    Ok((0x01, encoder.finish()?))
+}
+
+pub(crate) fn write_map_entry<W: Write>(
+    target: &mut W,
+    name_hash: u64,
+    offset: u64,
+    data_length: u64,
+    flags: u32,
+    data_hash: u32,
+) -> Result<()> {
+    write_u64_le(name_hash, target)?;
+    write_u64_le(offset, target)?;
+    write_u64_le(data_length, target)?;
+    write_u32_le(flags, target)?;
+    write_u32_le(data_hash, target)
+}
+
+/// `crc_64_<decimal hash>` (optionally with a extension) -> hash
+pub fn parse_unnamed_filename(filename: &str) -> Option<u64> {
+    filename
+        .strip_prefix("crc_64_")?
+        .split('.')
+        .next()?
+        .parse::<u64>()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pack2Writer;
+    use crate::pack2::Pack2;
+    use std::io::Cursor;
+
+    #[test]
+    fn write_and_read_pack2() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .set_embed_filename_list(true)
+            .add_bytes("test.txt", b"hello world".to_vec())
+            .add_bytes("empty.bin", Vec::new())
+            .add_raw(1234, vec![1, 2, 3, 4]);
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+
+        let pack2: Pack2 = Pack2::load_from_file(&mut br).unwrap();
+
+        assert_eq!(pack2.assets.len(), 4);
+        let asset = &pack2.assets[pack2.find_asset_index_by_name("test.txt").unwrap()];
+        assert_eq!(asset.name.as_deref(), Some("test.txt"));
+        assert!(asset.is_zipped);
+        assert_eq!(asset.extract_text(&mut br).unwrap(), "hello world");
+        let asset = &pack2.assets[pack2.find_asset_index_by_name_hash(1234).unwrap()];
+        assert!(asset.name.is_none());
+        assert_eq!(asset.extract_bytes(&mut br).unwrap(), vec![1, 2, 3, 4]);
+        let asset = &pack2.assets[pack2.find_asset_index_by_name("empty.bin").unwrap()];
+        assert!(!asset.is_zipped);
+        assert_eq!(asset.extract_bytes(&mut br).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn write_and_read_pack2_slice() {
+        let mut writer = Pack2Writer::new();
+        writer
+            .set_compression(false)
+            .add_bytes("test.txt", b"hello world".to_vec())
+            .add_bytes("raw.txt", b"raw".to_vec());
+        let mut br: Cursor<Vec<u8>> = Cursor::new(Vec::new());
+        writer.write(&mut br).unwrap();
+        let data: Vec<u8> = br.into_inner();
+
+        let pack2: Pack2 = Pack2::load_from_slice(&data).unwrap();
+        assert_eq!(pack2.assets.len(), 2);
+        for asset in pack2.assets.iter() {
+            assert!(!asset.is_zipped);
+        }
+        let asset = &pack2.assets[pack2.find_asset_index_by_name("raw.txt").unwrap()];
+        assert!(matches!(
+            asset.extract_bytes_from_slice(&data).unwrap(),
+            std::borrow::Cow::Borrowed(b"raw")
+        ));
+    }
+}
@@ -0,0 +1,505 @@
+use crate::cli_utils::humanise_bytes;
+use crate::extract_utils::{ExtractReport, OnConflict};
+use crate::pack2::{candidate_name_hashes, extract_into_directory, Asset, ExtractLayout, Pack2};
+use crate::Nups2Error;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// All pack2 files of a game install (or any other directory) as one virtual filesystem.
+///
+/// Packs are sorted by their path relative to the directory, with numbers compared by value
+/// (`assets_x64_2.pack2` comes before `assets_x64_10.pack2`).
+/// If a name hash exists in multiple packs the first pack in that order wins,
+/// the other copies are "shadowed" (still visible through `find_all`).
+#[derive(Debug)]
+pub struct PackSet {
+    pub packs: Vec<PackSetEntry>,
+    /// name_hash -> location of the winning asset
+    index: HashMap<u64, AssetLocation>,
+}
+
+#[derive(Debug)]
+pub struct PackSetEntry {
+    pub path: PathBuf,
+    /// path relative to the directory the set was opened from (for display)
+    pub name: String,
+    pub pack2: Pack2,
+}
+
+/// index into `PackSet::packs` and the pack's `assets`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AssetLocation {
+    pub pack: usize,
+    pub asset: usize,
+}
+
+impl PackSet {
+    /// every `*.pack2` file below `directory` (recursive)
+    pub fn open_directory(directory: &Path) -> std::result::Result<Self, Nups2Error> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        find_pack2_files(directory, &mut paths)?;
+        let named_paths: Vec<(PathBuf, String)> = paths
+            .into_iter()
+            .map(|path| {
+                let name: String = path
+                    .strip_prefix(directory)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .into_owned();
+                (path, name)
+            })
+            .collect();
+        Self::open_files(named_paths)
+    }
+
+    /// `(path, display name)` pairs, sorted by the display name (see the struct docs)
+    pub fn open_files(mut paths: Vec<(PathBuf, String)>) -> std::result::Result<Self, Nups2Error> {
+        paths.sort_by(|a, b| natural_cmp(&a.1, &b.1));
+        #[cfg(feature = "fast")]
+        let packs: Vec<PackSetEntry> = {
+            use rayon::iter::{IntoParallelIterator, ParallelIterator};
+            paths
+                .into_par_iter()
+                .map(|(path, name)| load_entry(path, name))
+                .collect::<std::result::Result<Vec<PackSetEntry>, Nups2Error>>()?
+        };
+        #[cfg(not(feature = "fast"))]
+        let packs: Vec<PackSetEntry> = paths
+            .into_iter()
+            .map(|(path, name)| load_entry(path, name))
+            .collect::<std::result::Result<Vec<PackSetEntry>, Nups2Error>>()?;
+        let mut result: Self = Self {
+            packs,
+            index: HashMap::new(),
+        };
+        result.rebuild_index();
+        Ok(result)
+    }
+
+    /// has to be called after modifying `packs` (or the assets in them)
+    pub fn rebuild_index(&mut self) {
+        self.index.clear();
+        for (pack_id, entry) in self.packs.iter().enumerate() {
+            for (asset_id, asset) in entry.pack2.assets.iter().enumerate() {
+                self.index.entry(asset.name_hash).or_insert(AssetLocation {
+                    pack: pack_id,
+                    asset: asset_id,
+                });
+            }
+        }
+    }
+
+    pub fn apply_filename_lookup_table(&mut self, filename_lookup_table: &HashMap<u64, String>) {
+        for entry in self.packs.iter_mut() {
+            entry
+                .pack2
+                .apply_filename_lookup_table(filename_lookup_table);
+        }
+    }
+
+    pub fn apply_filename_list(&mut self, filename_list: &[String]) {
+        self.apply_filename_lookup_table(&crate::crc64::filename_list_to_lookup_table(
+            filename_list,
+        ));
+    }
+
+    pub fn get(&self, location: AssetLocation) -> (&PackSetEntry, &Asset) {
+        let entry: &PackSetEntry = &self.packs[location.pack];
+        (entry, &entry.pack2.assets[location.asset])
+    }
+
+    /// the winning copy of `name_hash`
+    pub fn find(&self, name_hash: u64) -> Option<AssetLocation> {
+        self.index.get(&name_hash).copied()
+    }
+
+    /// every copy of `name_hash`, the winning one first
+    pub fn find_all(&self, name_hash: u64) -> Vec<AssetLocation> {
+        let mut result: Vec<AssetLocation> = Vec::new();
+        for (pack_id, entry) in self.packs.iter().enumerate() {
+            for (asset_id, asset) in entry.pack2.assets.iter().enumerate() {
+                if asset.name_hash == name_hash {
+                    result.push(AssetLocation {
+                        pack: pack_id,
+                        asset: asset_id,
+                    });
+                }
+            }
+        }
+        result
+    }
+
+    /// by name, `0x` prefixed hex hash or decimal hash (see `Pack2::find_asset`)
+    pub fn find_asset(&self, name_or_hash: &str) -> Option<AssetLocation> {
+        candidate_name_hashes(name_or_hash)
+            .into_iter()
+            .find_map(|hash| self.find(hash))
+    }
+
+    /// all assets, which are not shadowed by a copy in an earlier pack (in pack order)
+    pub fn list(&self) -> impl Iterator<Item = (AssetLocation, &PackSetEntry, &Asset)> {
+        self.packs
+            .iter()
+            .enumerate()
+            .flat_map(move |(pack_id, entry)| {
+                entry
+                    .pack2
+                    .assets
+                    .iter()
+                    .enumerate()
+                    .map(move |(asset_id, asset)| {
+                        (
+                            AssetLocation {
+                                pack: pack_id,
+                                asset: asset_id,
+                            },
+                            entry,
+                            asset,
+                        )
+                    })
+            })
+            .filter(|(location, _, asset)| self.find(asset.name_hash) == Some(*location))
+    }
+
+    /// the (uncompressed) content of the winning copy (see `Asset::open`)
+    pub fn open(&self, name_or_hash: &str) -> Result<impl Read> {
+        let location: AssetLocation = self.find_asset(name_or_hash).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{name_or_hash:?} not found in any pack2 file"),
+            )
+        })?;
+        let (entry, asset) = self.get(location);
+        asset.open_owned(BufReader::new(File::open(&entry.path)?))
+    }
+
+    #[cfg(feature = "json")]
+    pub fn ls_as_json(&self, locations: &[AssetLocation]) -> String {
+        use crate::json_utils::escape_string;
+
+        format!(
+            "[{}]",
+            locations
+                .iter()
+                .map(|location| {
+                    let (entry, asset) = self.get(*location);
+                    format!(
+                        r#"{{"pack":{},"asset":{}}}"#,
+                        escape_string(&entry.name),
+                        asset.to_json()
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+
+    pub fn ls_for_humans(&self, locations: &[AssetLocation]) -> String {
+        #[cfg(feature = "use_comfy_table")]
+        let mut table = comfy_table::Table::new();
+        #[cfg(feature = "use_comfy_table")]
+        table.set_header(vec!["Name", "Pack", "Size"]);
+
+        #[cfg(not(feature = "use_comfy_table"))]
+        let mut out: Vec<String> = Vec::with_capacity(locations.len());
+
+        for location in locations {
+            let (entry, asset) = self.get(*location);
+            let columns: Vec<String> = vec![
+                match asset.name {
+                    Some(ref name) => name.clone(),
+                    None => format!("crc64({})", asset.name_hash),
+                },
+                entry.name.clone(),
+                if asset.is_zipped {
+                    format!("{} [Z]", humanise_bytes(asset.unzipped_length.into()))
+                } else {
+                    humanise_bytes(asset.data_length as f64)
+                },
+            ];
+
+            #[cfg(feature = "use_comfy_table")]
+            table.add_row(columns);
+            #[cfg(not(feature = "use_comfy_table"))]
+            out.push(columns.join(" "));
+        }
+
+        #[cfg(feature = "use_comfy_table")]
+        return table.to_string();
+        #[cfg(not(feature = "use_comfy_table"))]
+        return out.join("\n");
+    }
+
+    /// extract the given assets (see `Pack2::extract_all_named`), opening each pack only once.
+    /// with `ExtractLayout::ByPack` each asset goes into the directory of its own pack,
+    /// its name without the extension (`live/assets_x64_0.pack2` -> `live/assets_x64_0/`).
+    /// the directory name in `layout` is ignored. packs, which would end up in the same directory, are an error.
+    pub fn extract(
+        &self,
+        locations: &[AssetLocation],
+        output_directory: &Path,
+        layout: &ExtractLayout,
+        on_conflict: OnConflict,
+    ) -> Result<ExtractReport> {
+        let mut by_pack: Vec<Vec<usize>> = vec![Vec::new(); self.packs.len()];
+        for location in locations {
+            by_pack[location.pack].push(location.asset);
+        }
+        if let ExtractLayout::ByPack(_) = layout {
+            let mut directories: HashMap<String, &str> = HashMap::new();
+            for (entry, asset_ids) in self.packs.iter().zip(by_pack.iter()) {
+                if asset_ids.is_empty() {
+                    continue;
+                }
+                if let Some(other) = directories.insert(pack_directory(entry), &entry.name) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "{other:?} and {:?} would both be extracted into {:?}",
+                            entry.name,
+                            pack_directory(entry)
+                        ),
+                    ));
+                }
+            }
+        }
+        let mut report: ExtractReport = ExtractReport::default();
+        for (entry, mut asset_ids) in self.packs.iter().zip(by_pack) {
+            if asset_ids.is_empty() {
+                continue;
+            }
+            asset_ids.sort();
+            asset_ids.dedup();
+            let mut br: BufReader<File> = BufReader::new(File::open(&entry.path)?);
+            let layout: ExtractLayout = match layout {
+                ExtractLayout::ByPack(_) => ExtractLayout::ByPack(pack_directory(entry)),
+                other => other.clone(),
+            };
+            for asset_id in asset_ids {
+                report = report.merge(extract_into_directory(
+                    &entry.pack2.assets[asset_id],
+                    &mut br,
+                    output_directory,
+                    &layout,
+                    on_conflict,
+                )?);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// the `ExtractLayout::ByPack` directory of a pack: its name without the extension.
+/// only plain path components are kept, so it always stays inside the output directory.
+fn pack_directory(entry: &PackSetEntry) -> String {
+    let directory: Vec<String> = Path::new(&entry.name)
+        .with_extension("")
+        .components()
+        .filter_map(|i| match i {
+            Component::Normal(i) => Some(i.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    if directory.is_empty() {
+        return entry
+            .path
+            .file_stem()
+            .map(|i| i.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    directory.join("/")
+}
+
+fn load_entry(path: PathBuf, name: String) -> std::result::Result<PackSetEntry, Nups2Error> {
+    log::debug!("loading {path:?}");
+    let pack2: Pack2 = Pack2::load_from_file(&mut BufReader::new(File::open(&path)?))
+        .map_err(|e| format!("{name}: {e}"))?;
+    Ok(PackSetEntry { path, name, pack2 })
+}
+
+/// every `*.pack2` file below `directory` (recursive, unsorted)
+pub(crate) fn find_pack2_files(directory: &Path, result: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path: PathBuf = entry?.path();
+        if path.is_dir() {
+            find_pack2_files(&path, result)?;
+        } else if path
+            .extension()
+            .is_some_and(|i| i.eq_ignore_ascii_case("pack2"))
+        {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// compares runs of digits by their value (`a2` < `a10`)
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x: String = take_digits(&mut a);
+                let y: String = take_digits(&mut b);
+                let x: &str = x.trim_start_matches('0');
+                let y: &str = y.trim_start_matches('0');
+                match x.len().cmp(&y.len()).then_with(|| x.cmp(y)) {
+                    Ordering::Equal => {}
+                    other => return other,
+                }
+            }
+            (Some(x), Some(y)) => match x.cmp(y) {
+                Ordering::Equal => {
+                    a.next();
+                    b.next();
+                }
+                other => return other,
+            },
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut result: String = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{natural_cmp, AssetLocation, PackSet};
+    use crate::extract_utils::OnConflict;
+    use crate::pack2::ExtractLayout;
+    use crate::pack2_writer::Pack2Writer;
+    use std::cmp::Ordering;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    fn write_pack(path: &Path, assets: &[(&str, &str)]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut writer = Pack2Writer::new();
+        for (name, content) in assets {
+            writer.add_bytes(name, content.as_bytes().to_vec());
+        }
+        writer.write_to_file(path).unwrap();
+    }
+
+    #[test]
+    fn natural_order() {
+        assert_eq!(natural_cmp("assets_x64_2", "assets_x64_10"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("assets_x64_02", "assets_x64_2"),
+            Ordering::Equal
+        );
+        assert_eq!(natural_cmp("a/b_1", "a_0"), Ordering::Less);
+        assert_eq!(natural_cmp("b", "a10"), Ordering::Greater);
+    }
+
+    #[test]
+    fn first_pack_wins() {
+        let root: PathBuf =
+            std::env::temp_dir().join(format!("nups2_pack_set_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        // a_10 is loaded after a_2, even though it sorts first as a plain string
+        write_pack(
+            &root.join("a_10.pack2"),
+            &[("shared.txt", "second"), ("y.txt", "y")],
+        );
+        write_pack(
+            &root.join("a_2.pack2"),
+            &[("shared.txt", "first"), ("x.txt", "x")],
+        );
+
+        let mut pack_set: PackSet = PackSet::open_directory(&root).unwrap();
+        pack_set.apply_filename_list(&["shared.txt".into(), "x.txt".into(), "y.txt".into()]);
+        let names: Vec<&str> = pack_set.packs.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["a_2.pack2", "a_10.pack2"]);
+
+        let winner: AssetLocation = pack_set.find_asset("shared.txt").unwrap();
+        assert_eq!(winner.pack, 0);
+        let all: Vec<AssetLocation> = pack_set.find_all(pack_set.get(winner).1.name_hash);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0], winner);
+        assert_eq!(all[1].pack, 1);
+
+        // the shadowed copy in a_10 is not listed
+        let mut listed: Vec<(&str, &str)> = pack_set
+            .list()
+            .map(|(_, entry, asset)| (entry.name.as_str(), asset.name.as_deref().unwrap()))
+            .collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            vec![
+                ("a_10.pack2", "y.txt"),
+                ("a_2.pack2", "shared.txt"),
+                ("a_2.pack2", "x.txt")
+            ]
+        );
+
+        let mut content: String = String::new();
+        pack_set
+            .open("shared.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "first");
+        assert_eq!(
+            pack_set.open("missing.txt").err().unwrap().kind(),
+            std::io::ErrorKind::NotFound
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn extract_by_pack_keeps_directories_apart() {
+        let root: PathBuf =
+            std::env::temp_dir().join(format!("nups2_pack_set_extract_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        write_pack(&root.join("live/assets.pack2"), &[("a.txt", "live")]);
+        write_pack(&root.join("pts/assets.pack2"), &[("b.txt", "pts")]);
+        let by_pack: ExtractLayout = ExtractLayout::ByPack(String::new());
+
+        let mut pack_set: PackSet = PackSet::open_directory(&root).unwrap();
+        pack_set.apply_filename_list(&["a.txt".into(), "b.txt".into()]);
+        let locations: Vec<AssetLocation> = pack_set.list().map(|i| i.0).collect();
+        let output: PathBuf = root.join("out");
+        pack_set
+            .extract(&locations, &output, &by_pack, OnConflict::Overwrite)
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(output.join("live/assets/a.txt")).unwrap(),
+            "live"
+        );
+        assert_eq!(
+            std::fs::read_to_string(output.join("pts/assets/b.txt")).unwrap(),
+            "pts"
+        );
+
+        // same name without the directory -> rejected instead of mixed together
+        let pack_set: PackSet = PackSet::open_files(vec![
+            (root.join("live/assets.pack2"), "assets.pack2".into()),
+            (root.join("pts/assets.pack2"), "assets.pack2".into()),
+        ])
+        .unwrap();
+        let locations: Vec<AssetLocation> = pack_set.list().map(|i| i.0).collect();
+        assert_eq!(
+            pack_set
+                .extract(&locations, &output, &by_pack, OnConflict::Overwrite)
+                .err()
+                .unwrap()
+                .kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,634 @@
+use crate::filename_extractor::CONTINENTS;
+use crate::install_snapshot::{InstallDiff, MovedAsset};
+use crate::pack2_manifest::{classify_changes, ManifestChange, ManifestChangeKind, ManifestDiff};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// name tokens, which get their own category
+pub const FACTIONS: &[&str] = &["NC", "TR", "VS"];
+
+/// A categorised summary of the changes of an update (for writing patch notes / datamining posts).
+///
+/// Changes of assets with a known name become entries, LOD variants (`Tree_Lod0.dme`,
+/// `Tree_Lod1.dme`, ..) with the same kind of change are folded into one entry (`Tree_Lod{0,1}.dme`).
+/// Changes of unnamed assets are only counted per pack.
+#[derive(Debug, Clone, Default)]
+pub struct PatchNotes {
+    pub title: String,
+    /// sorted by name
+    pub entries: Vec<PatchNoteEntry>,
+    /// per pack, only packs with unnamed changes
+    pub unnamed: Vec<UnnamedChanges>,
+    pub added_packs: Vec<String>,
+    pub removed_packs: Vec<String>,
+    /// (name, move) of moved assets with a known name
+    pub moved: Vec<(String, MovedAsset)>,
+    pub unnamed_moved: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchNoteEntry {
+    pub kind: ManifestChangeKind,
+    /// with the LOD variants folded together (`Tree_Lod{0,1}.dme`)
+    pub name: String,
+    /// only set for renames
+    pub old_name: Option<String>,
+    /// the part before `_Lod<N>` (only set for LOD variants)
+    pub model: Option<String>,
+    /// the folded LOD levels (empty if this is no LOD variant)
+    pub lods: Vec<u32>,
+    /// lowercase, without the dot (empty if there is none)
+    pub extension: String,
+    pub packs: Vec<String>,
+    /// number of changed assets (more than one if it contains multiple LODs or packs)
+    pub asset_count: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnnamedChanges {
+    pub pack: String,
+    pub created: usize,
+    pub changed: usize,
+    pub renamed: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    /// a complete html page
+    Html,
+}
+
+/// `<model>_Lod<N>[_<suffix>].<ext>` split into (model, level, everything up to and including `Lod`, the rest)
+fn split_lod(name: &str) -> Option<(String, u32, String, String)> {
+    let (stem, extension): (&str, &str) = match name.rfind('.') {
+        Some(index) => name.split_at(index),
+        None => (name, ""),
+    };
+    let tokens: Vec<&str> = stem.split('_').collect();
+    let index: usize = tokens.iter().position(|token| {
+        token.len() > 3
+            && token
+                .get(..3)
+                .is_some_and(|i| i.eq_ignore_ascii_case("lod"))
+            && token[3..].bytes().all(|i| i.is_ascii_digit())
+    })?;
+    if index == 0 {
+        return None;
+    }
+    let level: u32 = tokens[index][3..].parse().ok()?;
+    let before: String = format!("{}_{}", tokens[..index].join("_"), &tokens[index][..3]);
+    let after: String = tokens[index + 1..]
+        .iter()
+        .map(|i| format!("_{i}"))
+        .chain(std::iter::once(extension.to_string()))
+        .collect();
+    Some((tokens[..index].join("_"), level, before, after))
+}
+
+/// the categories (`FACTIONS`, `Weapons` and `CONTINENTS`) of an asset by the tokens of its name
+pub fn categories(name: &str) -> Vec<&'static str> {
+    let stem: &str = name.rsplit_once('.').map(|i| i.0).unwrap_or(name);
+    let tokens: Vec<&str> = stem.split(['_', '-', '.', ' ']).collect();
+    let has_token = |wanted: &str| tokens.iter().any(|i| i.eq_ignore_ascii_case(wanted));
+    let mut result: Vec<&'static str> = FACTIONS.iter().copied().filter(|i| has_token(i)).collect();
+    if has_token("Weapon") {
+        result.push("Weapons");
+    }
+    result.extend(CONTINENTS.iter().copied().filter(|i| has_token(i)));
+    result
+}
+
+fn kind_order(kind: ManifestChangeKind) -> u8 {
+    match kind {
+        ManifestChangeKind::Created => 0,
+        ManifestChangeKind::Changed => 1,
+        ManifestChangeKind::Renamed => 2,
+        ManifestChangeKind::Deleted => 3,
+    }
+}
+
+impl PatchNotes {
+    /// `diffs` are (pack name, diff) pairs
+    pub fn from_manifest_diffs(
+        title: &str,
+        diffs: &[(String, ManifestDiff)],
+        name_hash_lookup_table: &HashMap<u64, String>,
+    ) -> Self {
+        // (kind, name or folded LOD name, old name) -> (entry, lod levels, packs)
+        type Folded = (PatchNoteEntry, BTreeSet<u32>, BTreeSet<String>);
+        let mut folded: BTreeMap<(u8, String, String, Option<String>), Folded> = BTreeMap::new();
+        let mut unnamed: Vec<UnnamedChanges> = Vec::new();
+        for (pack, diff) in diffs.iter() {
+            let mut pack_unnamed: UnnamedChanges = UnnamedChanges {
+                pack: pack.clone(),
+                ..UnnamedChanges::default()
+            };
+            for change in classify_changes(diff) {
+                let name: &String = match name_hash_lookup_table.get(&change.name_hash) {
+                    Some(name) => name,
+                    None => {
+                        *match change.kind {
+                            ManifestChangeKind::Created => &mut pack_unnamed.created,
+                            ManifestChangeKind::Changed => &mut pack_unnamed.changed,
+                            ManifestChangeKind::Renamed => &mut pack_unnamed.renamed,
+                            ManifestChangeKind::Deleted => &mut pack_unnamed.deleted,
+                        } += 1;
+                        continue;
+                    }
+                };
+                let old_name: Option<String> = match change {
+                    ManifestChange {
+                        kind: ManifestChangeKind::Renamed,
+                        old_name_hash: Some(old_name_hash),
+                        ..
+                    } => Some(
+                        name_hash_lookup_table
+                            .get(&old_name_hash)
+                            .cloned()
+                            .unwrap_or_else(|| format!("0x{old_name_hash:X}")),
+                    ),
+                    _ => None,
+                };
+                // renames are not folded
+                let lod: Option<(String, u32, String, String)> =
+                    split_lod(name).filter(|_| old_name.is_none());
+                let key: (u8, String, String, Option<String>) = match lod {
+                    Some((_, _, ref before, ref after)) => {
+                        (kind_order(change.kind), before.clone(), after.clone(), None)
+                    }
+                    None => (
+                        kind_order(change.kind),
+                        name.clone(),
+                        String::new(),
+                        old_name.clone(),
+                    ),
+                };
+                let (entry, lods, packs) = folded.entry(key).or_insert_with(|| {
+                    (
+                        PatchNoteEntry {
+                            kind: change.kind,
+                            name: name.clone(),
+                            old_name,
+                            model: lod.as_ref().map(|i| i.0.clone()),
+                            lods: Vec::new(),
+                            extension: name
+                                .rsplit_once('.')
+                                .map(|i| i.1.to_lowercase())
+                                .unwrap_or_default(),
+                            packs: Vec::new(),
+                            asset_count: 0,
+                        },
+                        BTreeSet::new(),
+                        BTreeSet::new(),
+                    )
+                });
+                entry.asset_count += 1;
+                if let Some((_, level, _, _)) = lod {
+                    lods.insert(level);
+                }
+                packs.insert(pack.clone());
+            }
+            if pack_unnamed.created
+                + pack_unnamed.changed
+                + pack_unnamed.renamed
+                + pack_unnamed.deleted
+                > 0
+            {
+                unnamed.push(pack_unnamed);
+            }
+        }
+
+        let mut entries: Vec<PatchNoteEntry> = folded
+            .into_iter()
+            .map(|((_, before, after, _), (mut entry, lods, packs))| {
+                if lods.len() > 1 {
+                    entry.name = format!(
+                        "{before}{{{}}}{after}",
+                        lods.iter()
+                            .map(|i| i.to_string())
+                            .collect::<Vec<String>>()
+                            .join(",")
+                    );
+                }
+                entry.lods = lods.into_iter().collect();
+                entry.packs = packs.into_iter().collect();
+                entry
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            (a.name.to_lowercase(), kind_order(a.kind))
+                .cmp(&(b.name.to_lowercase(), kind_order(b.kind)))
+        });
+        Self {
+            title: title.to_string(),
+            entries,
+            unnamed,
+            ..Self::default()
+        }
+    }
+
+    pub fn from_install_diff(
+        title: &str,
+        diff: &InstallDiff,
+        name_hash_lookup_table: &HashMap<u64, String>,
+    ) -> Self {
+        let mut result: Self =
+            Self::from_manifest_diffs(title, &diff.packs, name_hash_lookup_table);
+        result.added_packs = diff.added_packs.clone();
+        result.removed_packs = diff.removed_packs.clone();
+        for moved in diff.moved_assets.iter() {
+            match name_hash_lookup_table.get(&moved.name_hash) {
+                Some(name) => result.moved.push((name.clone(), moved.clone())),
+                None => result.unnamed_moved += 1,
+            }
+        }
+        result.moved.sort_by_key(|i| i.0.to_lowercase());
+        result
+    }
+
+    /// (category, entries) for every category of `categories` with changes
+    pub fn by_category(&self) -> Vec<(&'static str, Vec<&PatchNoteEntry>)> {
+        let mut result: Vec<(&'static str, Vec<&PatchNoteEntry>)> = FACTIONS
+            .iter()
+            .chain(std::iter::once(&"Weapons"))
+            .chain(CONTINENTS.iter())
+            .map(|category| (*category, Vec::new()))
+            .collect();
+        for entry in self.entries.iter() {
+            for category in categories(&entry.name) {
+                if let Some(i) = result.iter_mut().find(|i| i.0 == category) {
+                    i.1.push(entry);
+                }
+            }
+        }
+        result.retain(|i| !i.1.is_empty());
+        result
+    }
+
+    /// (model, entries) of all LOD variants
+    pub fn by_model(&self) -> Vec<(&str, Vec<&PatchNoteEntry>)> {
+        let mut result: BTreeMap<&str, Vec<&PatchNoteEntry>> = BTreeMap::new();
+        for entry in self.entries.iter() {
+            if let Some(ref model) = entry.model {
+                result.entry(model.as_str()).or_default().push(entry);
+            }
+        }
+        result.into_iter().collect()
+    }
+
+    /// (extension, entries), sorted by extension
+    pub fn by_extension(&self) -> Vec<(&str, Vec<&PatchNoteEntry>)> {
+        let mut result: BTreeMap<&str, Vec<&PatchNoteEntry>> = BTreeMap::new();
+        for entry in self.entries.iter() {
+            result
+                .entry(entry.extension.as_str())
+                .or_default()
+                .push(entry);
+        }
+        result.into_iter().collect()
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        let mut report: ReportWriter = ReportWriter {
+            format,
+            out: Vec::new(),
+        };
+        report.heading(1, &self.title);
+
+        let count = |kind: ManifestChangeKind| -> usize {
+            self.entries
+                .iter()
+                .filter(|i| i.kind == kind)
+                .map(|i| i.asset_count)
+                .sum()
+        };
+        let unnamed: usize = self
+            .unnamed
+            .iter()
+            .map(|i| i.created + i.changed + i.renamed + i.deleted)
+            .sum();
+        report.paragraph(&[Span::Text(format!(
+            "{} created, {} changed, {} renamed and {} deleted files with a known name, {} changes of files without one.{}",
+            count(ManifestChangeKind::Created),
+            count(ManifestChangeKind::Changed),
+            count(ManifestChangeKind::Renamed),
+            count(ManifestChangeKind::Deleted),
+            unnamed,
+            if self.moved.is_empty() && self.unnamed_moved == 0 {
+                String::new()
+            } else {
+                format!(
+                    " {} files moved to another pack.",
+                    self.moved.len() + self.unnamed_moved
+                )
+            }
+        ))]);
+
+        if !self.added_packs.is_empty() || !self.removed_packs.is_empty() {
+            report.heading(2, "Packs");
+            report.list(
+                self.added_packs
+                    .iter()
+                    .map(|pack| vec![Span::Text(String::from("added ")), Span::Code(pack.clone())])
+                    .chain(self.removed_packs.iter().map(|pack| {
+                        vec![
+                            Span::Text(String::from("removed ")),
+                            Span::Code(pack.clone()),
+                        ]
+                    }))
+                    .collect(),
+            );
+        }
+
+        let by_category = self.by_category();
+        if !by_category.is_empty() {
+            report.heading(2, "Categories");
+            for (category, entries) in by_category {
+                report.heading(3, &format!("{category} ({})", entries.len()));
+                report.list(entries.into_iter().map(entry_spans).collect());
+            }
+        }
+
+        let by_model = self.by_model();
+        if !by_model.is_empty() {
+            report.heading(2, "Models");
+            report.list(
+                by_model
+                    .into_iter()
+                    .map(|(model, entries)| {
+                        let mut spans: Vec<Span> = vec![
+                            Span::Code(model.to_string()),
+                            Span::Text(String::from(": ")),
+                        ];
+                        for (index, entry) in entries.into_iter().enumerate() {
+                            if index > 0 {
+                                spans.push(Span::Text(String::from(", ")));
+                            }
+                            spans.extend(entry_spans(entry));
+                        }
+                        spans
+                    })
+                    .collect(),
+            );
+        }
+
+        if !self.entries.is_empty() {
+            report.heading(2, "File types");
+            for (extension, entries) in self.by_extension() {
+                report.heading(
+                    3,
+                    &format!(
+                        "{} ({})",
+                        if extension.is_empty() {
+                            "no extension"
+                        } else {
+                            extension
+                        },
+                        entries.len()
+                    ),
+                );
+                report.list(entries.into_iter().map(entry_spans).collect());
+            }
+        }
+
+        if !self.moved.is_empty() || self.unnamed_moved > 0 {
+            report.heading(2, "Moved between packs");
+            let mut items: Vec<Vec<Span>> = self
+                .moved
+                .iter()
+                .map(|(name, moved)| {
+                    vec![
+                        Span::Code(name.clone()),
+                        Span::Text(String::from(": ")),
+                        Span::Code(moved.old_pack.clone()),
+                        Span::Text(String::from(" -> ")),
+                        Span::Code(moved.new_pack.clone()),
+                        Span::Text(if moved.old_data_hash != moved.new_data_hash {
+                            String::from(" (changed)")
+                        } else {
+                            String::new()
+                        }),
+                    ]
+                })
+                .collect();
+            if self.unnamed_moved > 0 {
+                items.push(vec![Span::Text(format!(
+                    "{} files without a known name",
+                    self.unnamed_moved
+                ))]);
+            }
+            report.list(items);
+        }
+
+        if !self.unnamed.is_empty() {
+            report.heading(2, "Changes without a known name");
+            report.table(
+                &["Pack", "Created", "Changed", "Renamed", "Deleted"],
+                self.unnamed
+                    .iter()
+                    .map(|i| {
+                        vec![
+                            i.pack.clone(),
+                            i.created.to_string(),
+                            i.changed.to_string(),
+                            i.renamed.to_string(),
+                            i.deleted.to_string(),
+                        ]
+                    })
+                    .collect(),
+            );
+        }
+        report.finish(&self.title)
+    }
+}
+
+fn entry_spans(entry: &PatchNoteEntry) -> Vec<Span> {
+    let mut spans: Vec<Span> = vec![Span::Text(format!("{} ", entry.kind.as_str()))];
+    if let Some(ref old_name) = entry.old_name {
+        spans.push(Span::Code(old_name.clone()));
+        spans.push(Span::Text(String::from(" -> ")));
+    }
+    spans.push(Span::Code(entry.name.clone()));
+    spans
+}
+
+enum Span {
+    Text(String),
+    Code(String),
+}
+
+/// the same layout as markdown or html
+struct ReportWriter {
+    format: ReportFormat,
+    out: Vec<String>,
+}
+
+impl ReportWriter {
+    fn spans(&self, spans: &[Span]) -> String {
+        spans
+            .iter()
+            .map(|span| match (self.format, span) {
+                (ReportFormat::Markdown, Span::Text(text)) => text.clone(),
+                (ReportFormat::Markdown, Span::Code(text)) => format!("`{text}`"),
+                (ReportFormat::Html, Span::Text(text)) => escape_html(text),
+                (ReportFormat::Html, Span::Code(text)) => {
+                    format!("<code>{}</code>", escape_html(text))
+                }
+            })
+            .collect()
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        self.out.push(match self.format {
+            ReportFormat::Markdown => format!("{} {text}\n", "#".repeat(level)),
+            ReportFormat::Html => format!("<h{level}>{}</h{level}>", escape_html(text)),
+        });
+    }
+
+    fn paragraph(&mut self, spans: &[Span]) {
+        let text: String = self.spans(spans);
+        self.out.push(match self.format {
+            ReportFormat::Markdown => format!("{text}\n"),
+            ReportFormat::Html => format!("<p>{text}</p>"),
+        });
+    }
+
+    fn list(&mut self, items: Vec<Vec<Span>>) {
+        let items: Vec<String> = items.iter().map(|i| self.spans(i)).collect();
+        self.out.push(match self.format {
+            ReportFormat::Markdown => items.iter().map(|i| format!("- {i}\n")).collect(),
+            ReportFormat::Html => format!(
+                "<ul>\n{}</ul>",
+                items
+                    .iter()
+                    .map(|i| format!("<li>{i}</li>\n"))
+                    .collect::<String>()
+            ),
+        });
+    }
+
+    fn table(&mut self, header: &[&str], rows: Vec<Vec<String>>) {
+        self.out.push(match self.format {
+            ReportFormat::Markdown => format!(
+                "| {} |\n|{}\n{}",
+                header.join(" | "),
+                "---|".repeat(header.len()),
+                rows.iter()
+                    .map(|row| format!("| {} |\n", row.join(" | ")))
+                    .collect::<String>()
+            ),
+            ReportFormat::Html => format!(
+                "<table>\n<tr>{}</tr>\n{}</table>",
+                header
+                    .iter()
+                    .map(|i| format!("<th>{}</th>", escape_html(i)))
+                    .collect::<String>(),
+                rows.iter()
+                    .map(|row| format!(
+                        "<tr>{}</tr>\n",
+                        row.iter()
+                            .map(|i| format!("<td>{}</td>", escape_html(i)))
+                            .collect::<String>()
+                    ))
+                    .collect::<String>()
+            ),
+        });
+    }
+
+    fn finish(self, title: &str) -> String {
+        match self.format {
+            ReportFormat::Markdown => self.out.join("\n"),
+            ReportFormat::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}\n</body>\n</html>",
+                escape_html(title),
+                self.out.join("\n")
+            ),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{categories, PatchNotes, ReportFormat};
+    use crate::pack2_manifest::{ManifestChangeKind, ManifestDiff, ManifestDiffEntry};
+    use std::collections::HashMap;
+
+    #[test]
+    fn patch_notes() {
+        let names: Vec<String> = vec![
+            "Weapon_NC_Gauss_Lod0.dme".into(),
+            "Weapon_NC_Gauss_Lod1.dme".into(),
+            "Weapon_NC_Gauss_Lod2.dme".into(),
+            "Indar_Rock_Lod0_LODAuto.dme".into(),
+            "ui.xml".into(),
+        ];
+        let lookup: HashMap<u64, String> = crate::crc64::filename_list_to_lookup_table(&names);
+        let hash = |name: &str| crate::crc64::convert_filename(name);
+        let one: ManifestDiff = vec![
+            ManifestDiffEntry::new(hash("Weapon_NC_Gauss_Lod0.dme"), Some(1), Some(2)),
+            ManifestDiffEntry::new(hash("Weapon_NC_Gauss_Lod1.dme"), Some(1), Some(3)),
+            ManifestDiffEntry::new(hash("Indar_Rock_Lod0_LODAuto.dme"), None, Some(4)),
+            ManifestDiffEntry::new(1234, None, Some(5)),
+            ManifestDiffEntry::new(5678, Some(6), None),
+        ];
+        let two: ManifestDiff = vec![
+            ManifestDiffEntry::new(hash("Weapon_NC_Gauss_Lod2.dme"), Some(1), Some(7)),
+            ManifestDiffEntry::new(hash("ui.xml"), Some(8), None),
+        ];
+        let notes: PatchNotes = PatchNotes::from_manifest_diffs(
+            "Update",
+            &[("one.pack2".into(), one), ("two.pack2".into(), two)],
+            &lookup,
+        );
+        assert_eq!(
+            notes
+                .entries
+                .iter()
+                .map(|i| (i.kind, i.name.as_str(), i.asset_count))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    ManifestChangeKind::Created,
+                    "Indar_Rock_Lod0_LODAuto.dme",
+                    1
+                ),
+                (ManifestChangeKind::Deleted, "ui.xml", 1),
+                (
+                    ManifestChangeKind::Changed,
+                    "Weapon_NC_Gauss_Lod{0,1,2}.dme",
+                    3
+                ),
+            ]
+        );
+        assert_eq!(notes.entries[2].model.as_deref(), Some("Weapon_NC_Gauss"));
+        assert_eq!(notes.entries[2].packs, vec!["one.pack2", "two.pack2"]);
+        assert_eq!(notes.unnamed.len(), 1);
+        assert_eq!((notes.unnamed[0].created, notes.unnamed[0].deleted), (1, 1));
+        assert_eq!(
+            notes
+                .by_category()
+                .iter()
+                .map(|i| (i.0, i.1.len()))
+                .collect::<Vec<_>>(),
+            vec![("NC", 1), ("Weapons", 1), ("Indar", 1)]
+        );
+        assert_eq!(categories("Vehicle_TR_Prowler.adr"), vec!["TR"]);
+
+        let markdown: String = notes.render(ReportFormat::Markdown);
+        assert!(markdown.starts_with("# Update\n"));
+        assert!(markdown.contains("- changed `Weapon_NC_Gauss_Lod{0,1,2}.dme`\n"));
+        assert!(markdown.contains("| one.pack2 | 1 | 0 | 0 | 1 |\n"));
+        let html: String = notes.render(ReportFormat::Html);
+        assert!(html.contains("<li>changed <code>Weapon_NC_Gauss_Lod{0,1,2}.dme</code></li>"));
+        assert!(html.ends_with("</html>"));
+    }
+}
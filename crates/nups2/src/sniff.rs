@@ -0,0 +1,233 @@
+// Guess the type of asset data by its content (for assets without a known name).
+
+use std::fmt::Display;
+
+/// how many bytes from the start of the data `sniff` looks at
+pub const SNIFF_LENGTH: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    /// DirectDraw Surface (textures)
+    Dds,
+    Png,
+    Jpeg,
+    Bmp,
+    Wav,
+    Ogg,
+    /// TrueType / OpenType font
+    Ttf,
+    /// FMOD sound bank
+    Fsb5,
+    /// material (see `dma`)
+    Dmat,
+    /// model (see `dme`)
+    Dmod,
+    Dmv,
+    Cdt,
+    /// actor runtime definition (xml)
+    Adr,
+    Xml,
+    /// Scaleform GFx (ui)
+    Gfx,
+    /// Flash (ui)
+    Swf,
+    /// compiled DirectX shader
+    Dxbc,
+    /// nested pack2 file
+    Pack2,
+    /// raw zlib stream
+    Zlib,
+    /// UTF-8 text (no better match)
+    Text,
+    Unknown,
+}
+
+impl FileType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileType::Dds => "dds",
+            FileType::Png => "png",
+            FileType::Jpeg => "jpeg",
+            FileType::Bmp => "bmp",
+            FileType::Wav => "wav",
+            FileType::Ogg => "ogg",
+            FileType::Ttf => "ttf",
+            FileType::Fsb5 => "fsb5",
+            FileType::Dmat => "dmat",
+            FileType::Dmod => "dmod",
+            FileType::Dmv => "dmv",
+            FileType::Cdt => "cdt",
+            FileType::Adr => "adr",
+            FileType::Xml => "xml",
+            FileType::Gfx => "gfx",
+            FileType::Swf => "swf",
+            FileType::Dxbc => "dxbc",
+            FileType::Pack2 => "pack2",
+            FileType::Zlib => "zlib",
+            FileType::Text => "text",
+            FileType::Unknown => "unknown",
+        }
+    }
+
+    /// the file extension the game uses for this type (without `.`)
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            FileType::Dds => Some("dds"),
+            FileType::Png => Some("png"),
+            FileType::Jpeg => Some("jpg"),
+            FileType::Bmp => Some("bmp"),
+            FileType::Wav => Some("wav"),
+            FileType::Ogg => Some("ogg"),
+            FileType::Ttf => Some("ttf"),
+            FileType::Fsb5 => Some("fsb"),
+            FileType::Dmat => Some("dma"),
+            FileType::Dmod => Some("dme"),
+            FileType::Dmv => Some("dmv"),
+            FileType::Cdt => Some("cdt"),
+            FileType::Adr => Some("adr"),
+            FileType::Xml => Some("xml"),
+            FileType::Gfx => Some("gfx"),
+            FileType::Swf => Some("swf"),
+            FileType::Dxbc => Some("cso"),
+            FileType::Pack2 => Some("pack2"),
+            FileType::Zlib => Some("zlib"),
+            FileType::Text => Some("txt"),
+            FileType::Unknown => None,
+        }
+    }
+}
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// order matters: more specific signatures have to come first (dmv before dmod)
+const SIGNATURES: &[(&[u8], FileType)] = &[
+    (b"DDS |", FileType::Dds),
+    (
+        &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A],
+        FileType::Png,
+    ),
+    (&[0xFF, 0xD8, 0xFF], FileType::Jpeg),
+    (b"OggS", FileType::Ogg),
+    (&[0x00, 0x01, 0x00, 0x00, 0x00], FileType::Ttf),
+    (b"OTTO", FileType::Ttf),
+    (b"FSB5", FileType::Fsb5),
+    (b"DMAT", FileType::Dmat),
+    (b"DMODD", FileType::Dmv),
+    (b"DMOD", FileType::Dmod),
+    (b"CDTA", FileType::Cdt),
+    (b"GFX", FileType::Gfx),
+    (b"CFX", FileType::Gfx),
+    (b"FWS", FileType::Swf),
+    (b"CWS", FileType::Swf),
+    (b"ZWS", FileType::Swf),
+    (b"DXBC", FileType::Dxbc),
+    (b"PAK\x01", FileType::Pack2),
+];
+
+/// guess the type from the start of the (uncompressed) data.
+/// only the first `SNIFF_LENGTH` bytes are looked at.
+pub fn sniff(data: &[u8]) -> FileType {
+    let data: &[u8] = &data[..data.len().min(SNIFF_LENGTH)];
+    if let Some((_, file_type)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+    {
+        return *file_type;
+    }
+    if data.starts_with(b"BM") && data.len() >= 14 && data[6..10] == [0, 0, 0, 0] {
+        return FileType::Bmp;
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+        return FileType::Wav;
+    }
+    if is_zlib_header(data) {
+        return FileType::Zlib;
+    }
+    if let Some(text) = as_text(data) {
+        let text: &str = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with('<') {
+            let root: &str = skip_xml_prolog(text);
+            if root.starts_with("<ActorRuntime") {
+                return FileType::Adr;
+            }
+            return FileType::Xml;
+        }
+        return FileType::Text;
+    }
+    FileType::Unknown
+}
+
+fn is_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            *cmf == 0x78
+                && [0x01, 0x5E, 0x9C, 0xDA].contains(flg)
+                && (*cmf as u16 * 256 + *flg as u16).is_multiple_of(31)
+        }
+        _ => false,
+    }
+}
+
+/// `Some` if the data is utf-8 (a character cut off at the end is fine) without binary control characters
+fn as_text(data: &[u8]) -> Option<&str> {
+    let text: &str = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        // incomplete character at the end (`data` might be cut off)
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    if text.is_empty()
+        || text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+    {
+        return None;
+    }
+    Some(text)
+}
+
+/// skip `<?xml ...?>` and comments
+fn skip_xml_prolog(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        let end: Option<usize> = if text.starts_with("<?") {
+            text.find("?>").map(|i| i + 2)
+        } else if text.starts_with("<!--") {
+            text.find("-->").map(|i| i + 3)
+        } else {
+            return text;
+        };
+        match end {
+            Some(end) => text = &text[end..],
+            None => return text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sniff, FileType};
+
+    #[test]
+    fn sniff_file_types() {
+        assert_eq!(sniff(b"DDS |\x00\x00\x00"), FileType::Dds);
+        assert_eq!(sniff(b"DMOD\x04\x00\x00\x00"), FileType::Dmod);
+        assert_eq!(sniff(b"DMODD\x04\x00"), FileType::Dmv);
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<ActorRuntime>"),
+            FileType::Adr
+        );
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>\n<Foo>"), FileType::Xml);
+        assert_eq!(sniff(&[0x78, 0x9C, 0x01]), FileType::Zlib);
+        assert_eq!(sniff("Grüße\n".as_bytes()), FileType::Text);
+        // cut off in the middle of a character
+        assert_eq!(sniff(&"Grüße".as_bytes()[..3]), FileType::Text);
+        assert_eq!(sniff(&[0x00, 0x12, 0xFF]), FileType::Unknown);
+        assert_eq!(sniff(&[]), FileType::Unknown);
+        assert_eq!(FileType::Dmat.extension(), Some("dma"));
+    }
+}